@@ -0,0 +1,625 @@
+use std::{collections::HashSet, time::Duration};
+
+use futures::StreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOptions, ReplaceOptions},
+    Collection,
+};
+use rocket::{delete, get, post, put, serde::json::Json};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::{
+    auth::{ApiKey, Auth, Ncbi},
+    error::{api_ok, ApiResult, AppError},
+    model::PaperCsvResult,
+    slurm::{
+        db::Db,
+        sync::{send_markdown, Delivery},
+    },
+};
+
+static DB_NAME: &str = "rust_eutils";
+static SEARCH_COLLECTION: &str = "saved_searches";
+static ALERT_COLLECTION: &str = "search_alerts";
+
+/// 每次检索比较的最新 PMID 数
+const RUN_SIZE: usize = 500;
+/// 保存的已见过的 PMID 数, 超过时丢掉最旧的
+const MAX_SEEN: usize = 5000;
+/// 提醒中列出的文献数, 也是 LLM 总结的数量
+const DIGEST_PAPERS: usize = 20;
+/// 检查到期的检索的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+/// 单篇总结的超时, 超时后不带总结
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(60);
+const DAY_MS: i64 = 24 * 3600 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Schedule {
+    Daily,
+    #[default]
+    Weekly,
+    Monthly,
+}
+
+impl Schedule {
+    fn interval_ms(&self) -> i64 {
+        match self {
+            Schedule::Daily => DAY_MS,
+            Schedule::Weekly => 7 * DAY_MS,
+            Schedule::Monthly => 30 * DAY_MS,
+        }
+    }
+}
+
+/// 保存的检索, 保存在 mongo 的 `rust_eutils.saved_searches`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SavedSearch {
+    pub id: String,
+    /// 创建者的 API key 名称
+    pub owner: String,
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub schedule: Schedule,
+    /// 每篇新文献附带 LLM 的一句话总结
+    #[serde(default)]
+    pub summarize: bool,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// 已经见过的 PMID, 新的在前; 第一次运行只记录当前的结果, 不发送提醒
+    #[serde(default)]
+    pub seen: Vec<String>,
+    pub created_at: i64,
+    pub last_run: Option<i64>,
+    pub next_run: i64,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl SavedSearch {
+    /// 接口返回的内容, `seen` 只返回数量
+    fn view(&self) -> serde_json::Value {
+        let mut v = serde_json::to_value(self).unwrap_or_default();
+        v["seen"] = serde_json::json!(self.seen.len());
+        v
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AlertPaper {
+    pub pmid: String,
+    pub title: String,
+    pub journal: String,
+    pub year: String,
+    #[serde(default)]
+    pub summary: Option<String>,
+}
+
+/// 一次提醒, 保存在 `rust_eutils.search_alerts`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Alert {
+    pub id: String,
+    pub search_id: String,
+    pub owner: String,
+    pub query: String,
+    pub created_at: i64,
+    /// 新文献的数量, `papers` 最多列出 `DIGEST_PAPERS` 篇
+    pub total: usize,
+    pub papers: Vec<AlertPaper>,
+    /// 钉钉是否发送成功
+    pub sent: bool,
+    /// 没有配置钉钉, 没有发送
+    #[serde(default)]
+    pub skipped: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// 新建和修改的参数
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchRequest {
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub summarize: bool,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn collection(name: &str) -> Result<Collection<Document>, AppError> {
+    Db::instance()
+        .map(|c| c.database(DB_NAME).collection(name))
+        .ok_or_else(|| AppError::unavailable("saved search store is not initialized"))
+}
+
+fn db_error(err: mongodb::error::Error) -> AppError {
+    AppError::unavailable(format!("saved search store error: {}", err))
+}
+
+fn to_document<T: Serialize>(v: &T) -> Result<Document, AppError> {
+    bson::to_document(v).map_err(|err| AppError::internal(err.to_string()))
+}
+
+async fn find_searches(filter: Document) -> Result<Vec<SavedSearch>, AppError> {
+    let option = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .build();
+    let mut cursor = collection(SEARCH_COLLECTION)?
+        .find(filter, option)
+        .await
+        .map_err(db_error)?;
+    let mut v = Vec::new();
+    while let Some(d) = cursor.next().await {
+        match bson::from_document::<SavedSearch>(d.map_err(db_error)?) {
+            Ok(s) => v.push(s),
+            Err(err) => log::warn!("parse saved search error = {:?}", err),
+        }
+    }
+    Ok(v)
+}
+
+/// 其他人的检索按不存在处理, 管理员可以访问全部
+async fn load(key: &ApiKey, id: &str) -> Result<SavedSearch, AppError> {
+    let not_found = || AppError::not_found(format!("saved search not found: {}", id));
+    let d = collection(SEARCH_COLLECTION)?
+        .find_one(doc! { "id": id }, None)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(not_found)?;
+    let s =
+        bson::from_document::<SavedSearch>(d).map_err(|err| AppError::internal(err.to_string()))?;
+    if key.admin || s.owner == key.name {
+        Ok(s)
+    } else {
+        Err(not_found())
+    }
+}
+
+async fn save(s: &SavedSearch) -> Result<(), AppError> {
+    collection(SEARCH_COLLECTION)?
+        .replace_one(
+            doc! { "id": &s.id },
+            to_document(s)?,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// 只更新运行的结果, 不覆盖运行中的修改; 检索式已被修改, 或者同时有其他运行先完成
+/// (`last_run` 不再是开始时的值) 时不更新, 返回 false
+async fn commit_run(s: &SavedSearch, last_run: Option<i64>) -> Result<bool, AppError> {
+    let filter = doc! { "id": &s.id, "query": &s.query, "last_run": last_run };
+    let update = doc! { "$set": {
+        "seen": s.seen.clone(),
+        "last_run": s.last_run,
+        "next_run": s.next_run,
+    } };
+    let r = collection(SEARCH_COLLECTION)?
+        .update_one(filter, update, None)
+        .await
+        .map_err(db_error)?;
+    Ok(r.matched_count > 0)
+}
+
+/// 修改时 `$set` 的字段; 检索式没变时不修改运行的结果, 避免覆盖同时完成的运行
+fn edit_fields(
+    s: &SavedSearch,
+    query_changed: bool,
+    schedule_changed: bool,
+) -> Result<Document, AppError> {
+    let mut set = to_document(s)?;
+    for k in ["id", "owner", "created_at"] {
+        set.remove(k);
+    }
+    if !query_changed {
+        set.remove("seen");
+        set.remove("last_run");
+        if !schedule_changed {
+            set.remove("next_run");
+        }
+    }
+    Ok(set)
+}
+
+/// 本次结果中没见过的 PMID, 以及更新后的 `seen`
+fn diff_seen(ids: &[String], seen: &[String]) -> (Vec<String>, Vec<String>) {
+    let old = seen.iter().collect::<HashSet<&String>>();
+    let new = ids
+        .iter()
+        .filter(|f| !old.contains(f))
+        .cloned()
+        .collect::<Vec<String>>();
+    let mut all = new.clone();
+    all.extend(seen.iter().cloned());
+    all.truncate(MAX_SEEN);
+    (new, all)
+}
+
+/// 钉钉 markdown 格式的提醒
+fn digest(s: &SavedSearch, alert: &Alert) -> String {
+    let mut text = format!(
+        "### 新文献: {}\n\n> {}\n\n共 {} 篇新文献\n\n",
+        s.name, s.query, alert.total
+    );
+    for (i, p) in alert.papers.iter().enumerate() {
+        text.push_str(&format!(
+            "{}. [{}](https://pubmed.ncbi.nlm.nih.gov/{}/) *{}* {}\n",
+            i + 1,
+            p.title,
+            p.pmid,
+            p.journal,
+            p.year
+        ));
+        if let Some(summary) = &p.summary {
+            text.push_str(&format!("   > {}\n", summary));
+        }
+    }
+    if alert.total > alert.papers.len() {
+        text.push_str(&format!(
+            "\n还有 {} 篇, 见提醒历史\n",
+            alert.total - alert.papers.len()
+        ));
+    }
+    text
+}
+
+/// 一句话总结, 失败或超时时不带总结
+async fn summary(p: &PaperCsvResult) -> Option<String> {
+    if p.r#abstract.is_empty() {
+        return None;
+    }
+    let content = format!(
+        "Summarize the main finding of the following abstract in one sentence: {}",
+        &p.r#abstract
+    );
+    match tokio::time::timeout(
+        SUMMARY_TIMEOUT,
+        crate::openai::openai_nlp(content, Some(200), None),
+    )
+    .await
+    {
+        Ok(Ok(s)) => Some(s),
+        Ok(Err(err)) => {
+            log::warn!("alert summary pmid = {} error = {:?}", &p.pmid, err);
+            None
+        }
+        Err(_) => {
+            log::warn!("alert summary pmid = {} timeout", &p.pmid);
+            None
+        }
+    }
+}
+
+/// 重新检索, 有新文献时发送提醒并保存; 第一次运行只记录当前的结果.
+/// 先保存 `seen` 再发送, 同时运行的只有一个能保存成功, 同一批新文献只提醒一次
+async fn run_search(s: &mut SavedSearch) -> Result<Option<Alert>, AppError> {
+    log::info!("run saved search id = {}, query = {}", &s.id, &s.query);
+    let r = crate::eutils::fetch_ids("pubmed", &s.query, 0, RUN_SIZE).await?;
+    let (new, seen) = diff_seen(&r.esearchresult.idlist, &s.seen);
+    let last_run = s.last_run;
+
+    let now = now();
+    s.seen = seen;
+    s.last_run = Some(now);
+    s.next_run = now + s.schedule.interval_ms();
+    if !commit_run(s, last_run).await? {
+        log::info!("saved search id = {} changed during run, skip", &s.id);
+        return Ok(None);
+    }
+    if last_run.is_none() || new.is_empty() {
+        log::info!("saved search id = {}, new = {}", &s.id, new.len());
+        return Ok(None);
+    }
+
+    let mut found = crate::eutils::efetch_batch("pubmed", &new).await?;
+    let mut papers = Vec::new();
+    for id in new.iter().take(DIGEST_PAPERS) {
+        if let Some(p) = found.remove(id) {
+            let summary = if s.summarize { summary(&p).await } else { None };
+            papers.push(AlertPaper {
+                pmid: p.pmid,
+                title: p.title,
+                journal: p.journal_title,
+                year: p.pubdate_year,
+                summary,
+            });
+        }
+    }
+
+    let mut alert = Alert {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        search_id: s.id.clone(),
+        owner: s.owner.clone(),
+        query: s.query.clone(),
+        created_at: now,
+        total: new.len(),
+        papers,
+        sent: false,
+        skipped: false,
+        error: None,
+    };
+    let title = format!("新文献: {}", s.name);
+    match send_markdown(&title, &digest(s, &alert)).await {
+        Ok(Delivery::Sent) => alert.sent = true,
+        Ok(Delivery::Skipped) => alert.skipped = true,
+        Err(err) => {
+            log::warn!("send alert id = {} error = {:?}", &alert.id, err);
+            alert.error = Some(err.to_string());
+        }
+    }
+
+    collection(ALERT_COLLECTION)?
+        .insert_one(to_document(&alert)?, None)
+        .await
+        .map_err(db_error)?;
+    log::info!("saved search id = {}, alert new = {}", &s.id, new.len());
+    Ok(Some(alert))
+}
+
+async fn run_due() -> Result<(), AppError> {
+    let due = find_searches(doc! { "enabled": true, "next_run": { "$lte": now() } }).await?;
+    for mut s in due {
+        let id = crate::logging::new_id("alert");
+        if let Err(err) = crate::logging::scope(id, run_search(&mut s)).await {
+            log::warn!("run saved search id = {} error = {}", &s.id, err);
+        }
+    }
+    Ok(())
+}
+
+pub fn start_timetask() {
+    tokio::spawn(async {
+        sleep(Duration::from_secs(90)).await;
+        loop {
+            if Db::instance().is_some() {
+                if let Err(err) = run_due().await {
+                    log::warn!("saved search error = {}", err);
+                }
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+fn validate(req: &SearchRequest) -> Result<(), AppError> {
+    if req.name.trim().is_empty() || req.query.trim().is_empty() {
+        return Err(AppError::bad_request("name and query are required"));
+    }
+    Ok(())
+}
+
+/// 新建后在后台记录当前的结果, 之后按 `schedule` 检查新文献
+#[post("/searches", format = "json", data = "<req>")]
+pub async fn create_search(auth: Auth, req: Json<SearchRequest>) -> ApiResult {
+    validate(&req)?;
+    let req = req.into_inner();
+    let now = now();
+    let mut s = SavedSearch {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        owner: auth.0.name,
+        name: req.name.trim().to_string(),
+        query: req.query.trim().to_string(),
+        schedule: req.schedule,
+        summarize: req.summarize,
+        enabled: req.enabled,
+        seen: Vec::new(),
+        created_at: now,
+        last_run: None,
+        next_run: now,
+    };
+    save(&s).await?;
+    log::info!("create saved search id = {}, owner = {}", &s.id, &s.owner);
+
+    let view = s.view();
+    crate::logging::spawn(async move {
+        if let Err(err) = run_search(&mut s).await {
+            log::warn!("saved search id = {} baseline error = {}", &s.id, err);
+        }
+    });
+    api_ok(&view)
+}
+
+/// 自己的检索, 管理员返回全部
+#[get("/searches")]
+pub async fn list_searches(auth: Auth) -> ApiResult {
+    let filter = if auth.0.admin {
+        doc! {}
+    } else {
+        doc! { "owner": &auth.0.name }
+    };
+    let v = find_searches(filter)
+        .await?
+        .iter()
+        .map(SavedSearch::view)
+        .collect::<Vec<serde_json::Value>>();
+    api_ok(&v)
+}
+
+#[get("/searches/<id>")]
+pub async fn get_search(auth: Auth, id: String) -> ApiResult {
+    api_ok(&load(&auth.0, &id).await?.view())
+}
+
+/// 修改检索式时重新记录当前的结果
+#[put("/searches/<id>", format = "json", data = "<req>")]
+pub async fn update_search(auth: Auth, id: String, req: Json<SearchRequest>) -> ApiResult {
+    validate(&req)?;
+    let mut s = load(&auth.0, &id).await?;
+    let query = req.query.trim().to_string();
+    let query_changed = query != s.query;
+    let schedule_changed = req.schedule != s.schedule;
+    if query_changed {
+        s.query = query;
+        s.seen.clear();
+        s.last_run = None;
+        s.next_run = now();
+    } else if schedule_changed {
+        s.next_run = s.last_run.unwrap_or_else(now) + req.schedule.interval_ms();
+    }
+    s.name = req.name.trim().to_string();
+    s.schedule = req.schedule;
+    s.summarize = req.summarize;
+    s.enabled = req.enabled;
+    let set = edit_fields(&s, query_changed, schedule_changed)?;
+    collection(SEARCH_COLLECTION)?
+        .update_one(doc! { "id": &s.id }, doc! { "$set": set }, None)
+        .await
+        .map_err(db_error)?;
+    api_ok(&s.view())
+}
+
+/// 同时删除提醒历史
+#[delete("/searches/<id>")]
+pub async fn delete_search(auth: Auth, id: String) -> ApiResult {
+    let s = load(&auth.0, &id).await?;
+    collection(SEARCH_COLLECTION)?
+        .delete_one(doc! { "id": &s.id }, None)
+        .await
+        .map_err(db_error)?;
+    collection(ALERT_COLLECTION)?
+        .delete_many(doc! { "search_id": &s.id }, None)
+        .await
+        .map_err(db_error)?;
+    log::info!("delete saved search id = {}", &s.id);
+    api_ok(&serde_json::json!({ "id": s.id, "deleted": true }))
+}
+
+/// 立即检查一次, 没有新文献时返回 null
+#[post("/searches/<id>/run")]
+pub async fn run_search_now(key: Ncbi, id: String) -> ApiResult {
+    let mut s = load(&key.0, &id).await?;
+    api_ok(&run_search(&mut s).await?)
+}
+
+/// 提醒历史, 新的在前, 默认 20 条, 最多 100 条
+#[get("/searches/<id>/alerts?<limit>")]
+pub async fn search_alerts(auth: Auth, id: String, limit: Option<i64>) -> ApiResult {
+    let s = load(&auth.0, &id).await?;
+    let option = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .limit(limit.unwrap_or(20).clamp(1, 100))
+        .build();
+    let mut cursor = collection(ALERT_COLLECTION)?
+        .find(doc! { "search_id": &s.id }, option)
+        .await
+        .map_err(db_error)?;
+    let mut v = Vec::new();
+    while let Some(d) = cursor.next().await {
+        if let Ok(a) = bson::from_document::<Alert>(d.map_err(db_error)?) {
+            v.push(a);
+        }
+    }
+    api_ok(&v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(v: &[&str]) -> Vec<String> {
+        v.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff_seen() {
+        let (new, all) = diff_seen(&ids(&["5", "4", "3"]), &ids(&["3", "2", "1"]));
+        assert_eq!(ids(&["5", "4"]), new);
+        assert_eq!(ids(&["5", "4", "3", "2", "1"]), all);
+
+        let seen = (0..MAX_SEEN)
+            .map(|f| f.to_string())
+            .collect::<Vec<String>>();
+        let (new, all) = diff_seen(&ids(&["new"]), &seen);
+        assert_eq!(ids(&["new"]), new);
+        assert_eq!(MAX_SEEN, all.len());
+        assert_eq!("new", all[0]);
+    }
+
+    #[test]
+    fn test_edit_fields() {
+        let s = SavedSearch {
+            id: "s1".to_string(),
+            owner: "lab".to_string(),
+            name: "DUSP22".to_string(),
+            query: "DUSP22".to_string(),
+            schedule: Schedule::Daily,
+            summarize: false,
+            enabled: false,
+            seen: ids(&["1"]),
+            created_at: 0,
+            last_run: Some(1),
+            next_run: 2,
+        };
+        let keys = |d: Document| d.keys().cloned().collect::<Vec<String>>();
+
+        assert_eq!(
+            vec!["name", "query", "schedule", "summarize", "enabled"],
+            keys(edit_fields(&s, false, false).unwrap())
+        );
+        assert!(keys(edit_fields(&s, false, true).unwrap()).contains(&"next_run".to_string()));
+        let all = keys(edit_fields(&s, true, false).unwrap());
+        assert!(all.contains(&"seen".to_string()) && all.contains(&"last_run".to_string()));
+        assert!(!all.contains(&"owner".to_string()));
+    }
+
+    #[test]
+    fn test_digest() {
+        let s = SavedSearch {
+            id: "s1".to_string(),
+            owner: "lab".to_string(),
+            name: "DUSP22".to_string(),
+            query: "DUSP22[Title/Abstract]".to_string(),
+            schedule: Schedule::Weekly,
+            summarize: true,
+            enabled: true,
+            seen: vec![],
+            created_at: 0,
+            last_run: None,
+            next_run: 0,
+        };
+        let alert = Alert {
+            id: "a1".to_string(),
+            search_id: "s1".to_string(),
+            owner: "lab".to_string(),
+            query: s.query.clone(),
+            created_at: 0,
+            total: 3,
+            papers: vec![AlertPaper {
+                pmid: "36765305".to_string(),
+                title: "Dual-specificity phosphatases 22".to_string(),
+                journal: "BMC medicine".to_string(),
+                year: "2023".to_string(),
+                summary: Some("DUSP22 deficiency promotes AS.".to_string()),
+            }],
+            sent: false,
+            skipped: false,
+            error: None,
+        };
+
+        let text = digest(&s, &alert);
+        assert!(text.starts_with("### 新文献: DUSP22"));
+        assert!(text.contains("1. [Dual-specificity phosphatases 22](https://pubmed.ncbi.nlm.nih.gov/36765305/) *BMC medicine* 2023"));
+        assert!(text.contains("   > DUSP22 deficiency promotes AS."));
+        assert!(text.contains("还有 2 篇"));
+
+        assert_eq!(7 * DAY_MS, Schedule::default().interval_ms());
+        let v = s.view();
+        assert_eq!(0, v["seen"]);
+        assert_eq!("weekly", v["schedule"]);
+    }
+}
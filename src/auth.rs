@@ -0,0 +1,517 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use mongodb::{
+    bson::{self, doc, Document},
+    options::UpdateOptions,
+    Collection,
+};
+use once_cell::sync::Lazy;
+use rocket::{
+    delete, futures::StreamExt, get, post, request, request::FromRequest, serde::json::Json,
+    Request,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{api_ok, guard_error, ApiResult, AppError},
+    slurm::db::Db,
+};
+
+static DB_NAME: &str = "rust_eutils";
+static KEY_COLLECTION: &str = "api_keys";
+static USAGE_COLLECTION: &str = "api_usage";
+
+/// 从 mongo 读取的 key 缓存时间, 其他实例吊销的 key 最多延迟这么久生效
+const KEY_CACHE_TTL: i64 = 60 * 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ApiKey {
+    pub key: String,
+    pub name: String,
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub revoked: bool,
+    /// 每分钟的请求数, 0 表示不限制
+    pub ncbi_per_minute: u32,
+    pub llm_per_minute: u32,
+    /// 每天的请求数, 0 表示不限制
+    pub ncbi_daily: u32,
+    pub llm_daily: u32,
+    pub created_at: i64,
+}
+
+impl ApiKey {
+    /// 关闭鉴权或使用 `auth.admin_key` 时的 key, 不限流
+    fn unlimited(name: &str) -> Self {
+        ApiKey {
+            key: String::new(),
+            name: name.to_string(),
+            admin: true,
+            revoked: false,
+            ncbi_per_minute: 0,
+            llm_per_minute: 0,
+            ncbi_daily: 0,
+            llm_daily: 0,
+            created_at: 0,
+        }
+    }
+
+    fn limits(&self, class: Class) -> (u32, u32) {
+        match class {
+            Class::Ncbi => (self.ncbi_per_minute, self.ncbi_daily),
+            Class::Llm => (self.llm_per_minute, self.llm_daily),
+            Class::Other => (0, 0),
+        }
+    }
+}
+
+/// 按后端区分的接口类别, 分别限流和计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Ncbi,
+    Llm,
+    Other,
+}
+
+impl Class {
+    fn field(&self) -> &'static str {
+        match self {
+            Class::Ncbi => "ncbi",
+            Class::Llm => "llm",
+            Class::Other => "other",
+        }
+    }
+}
+
+/// 每个 key 每天的请求数, 保存在 `api_usage`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Usage {
+    pub key: String,
+    pub day: String,
+    #[serde(default)]
+    pub ncbi: u64,
+    #[serde(default)]
+    pub llm: u64,
+    #[serde(default)]
+    pub other: u64,
+}
+
+impl Usage {
+    fn get(&self, class: Class) -> u64 {
+        match class {
+            Class::Ncbi => self.ncbi,
+            Class::Llm => self.llm,
+            Class::Other => self.other,
+        }
+    }
+
+    fn add(&mut self, class: Class) {
+        match class {
+            Class::Ncbi => self.ncbi += 1,
+            Class::Llm => self.llm += 1,
+            Class::Other => self.other += 1,
+        }
+    }
+}
+
+/// 固定窗口的每分钟计数
+#[derive(Debug, Default)]
+struct Window {
+    minute: i64,
+    count: u32,
+}
+
+impl Window {
+    fn hit(&mut self, minute: i64, limit: u32) -> bool {
+        if self.minute != minute {
+            self.minute = minute;
+            self.count = 0;
+        }
+        if limit > 0 && self.count >= limit {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+}
+
+/// 读取的 key 和读取的时间, 不存在的 key 也缓存
+type CachedKey = (Option<ApiKey>, i64);
+
+static KEYS: Lazy<Mutex<HashMap<String, CachedKey>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static WINDOWS: Lazy<Mutex<HashMap<(String, Class), Window>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static USAGE: Lazy<Mutex<HashMap<String, Usage>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 配置 `auth.enabled = false` (或 `API_AUTH=off`) 关闭鉴权, 默认开启
+fn enabled() -> bool {
+    crate::settings::settings().auth.enabled
+}
+
+/// 配置 `auth.public_static = false` 时 dual 静态文件和 SMILES 图片也需要 key
+fn public_static() -> bool {
+    crate::settings::settings().auth.public_static
+}
+
+fn today() -> String {
+    chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+fn collection(name: &str) -> Result<Collection<Document>, AppError> {
+    Db::instance()
+        .map(|c| c.database(DB_NAME).collection(name))
+        .ok_or_else(|| AppError::unavailable("api key store is not initialized"))
+}
+
+fn db_error(err: mongodb::error::Error) -> AppError {
+    AppError::unavailable(format!("api key store error: {}", err))
+}
+
+/// `X-API-Key` 头, `Authorization: Bearer`, 或者 `api_key` 参数 (`EventSource` 不能设置请求头)
+fn request_key<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    req.headers()
+        .get_one("X-API-Key")
+        .or_else(|| {
+            req.headers()
+                .get_one("Authorization")
+                .and_then(|f| f.strip_prefix("Bearer "))
+        })
+        .or_else(|| req.query_value::<&str>("api_key").and_then(|f| f.ok()))
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+}
+
+async fn find_key(key: &str) -> Result<Option<ApiKey>, AppError> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let cached = KEYS.lock().unwrap().get(key).cloned();
+    if let Some((k, t)) = &cached {
+        if now - t < KEY_CACHE_TTL {
+            return Ok(k.clone());
+        }
+    }
+
+    let found = match collection(KEY_COLLECTION) {
+        Ok(c) => c
+            .find_one(doc! { "key": key }, None)
+            .await
+            .map_err(db_error),
+        Err(err) => Err(err),
+    };
+    let k = match (found, cached) {
+        (Ok(d), _) => d.and_then(|d| bson::from_document::<ApiKey>(d).ok()),
+        // mongo 不可用时继续使用之前读取的 key
+        (Err(err), Some((k, _))) => {
+            log::warn!("{}, use cached api key", err);
+            return Ok(k);
+        }
+        (Err(err), None) => return Err(err),
+    };
+    KEYS.lock()
+        .unwrap()
+        .insert(key.to_string(), (k.clone(), now));
+    Ok(k)
+}
+
+/// 当天的计数, 进程重启后从 mongo 读取
+async fn load_usage(key: &str, day: &str) -> Usage {
+    let id = format!("{}|{}", key, day);
+    if let Some(u) = USAGE.lock().unwrap().get(&id) {
+        return u.clone();
+    }
+
+    let found = match collection(USAGE_COLLECTION) {
+        Ok(c) => c
+            .find_one(doc! { "key": key, "day": day }, None)
+            .await
+            .ok()
+            .flatten(),
+        Err(_) => None,
+    };
+    let u = found
+        .and_then(|d| bson::from_document::<Usage>(d).ok())
+        .unwrap_or_else(|| Usage {
+            key: key.to_string(),
+            day: day.to_string(),
+            ..Default::default()
+        });
+    let mut usage = USAGE.lock().unwrap();
+    // 只保留当天的计数
+    usage.retain(|k, _| k.ends_with(day));
+    usage.entry(id).or_insert(u).clone()
+}
+
+/// 检查限流和配额, 通过后计数
+async fn consume(key: &ApiKey, class: Class) -> Result<(), AppError> {
+    let (per_minute, daily) = key.limits(class);
+    let minute = chrono::Utc::now().timestamp() / 60;
+    let day = today();
+    load_usage(&key.key, &day).await;
+
+    {
+        let mut usage = USAGE.lock().unwrap();
+        let u = usage
+            .get_mut(&format!("{}|{}", &key.key, &day))
+            .ok_or_else(|| AppError::internal("usage not loaded"))?;
+        if daily > 0 && u.get(class) >= daily as u64 {
+            return Err(AppError::quota_exceeded(format!(
+                "daily {} quota {} exceeded",
+                class.field(),
+                daily
+            )));
+        }
+
+        let mut windows = WINDOWS.lock().unwrap();
+        let w = windows.entry((key.key.clone(), class)).or_default();
+        if !w.hit(minute, per_minute) {
+            return Err(AppError::rate_limited(format!(
+                "{} rate limit {} per minute exceeded",
+                class.field(),
+                per_minute
+            )));
+        }
+        u.add(class);
+    }
+
+    let filter = doc! { "key": &key.key, "day": &day };
+    let inc = doc! { "$inc": { class.field(): 1 } };
+    tokio::spawn(async move {
+        let opt = UpdateOptions::builder().upsert(true).build();
+        let r = match collection(USAGE_COLLECTION) {
+            Ok(c) => c.update_one(filter, inc, opt).await.map_err(db_error),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = r {
+            log::warn!("save api usage error = {:?}", err);
+        }
+    });
+    Ok(())
+}
+
+/// 比较时间不随相同前缀的长度变化, 避免按响应时间逐位猜出管理员 key
+fn constant_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn authorize(req: &Request<'_>, class: Class) -> Result<ApiKey, AppError> {
+    if !enabled() {
+        return Ok(ApiKey::unlimited("anonymous"));
+    }
+
+    let key = request_key(req).ok_or_else(|| AppError::unauthorized("missing api key"))?;
+    let admin = crate::settings::settings().auth.admin_key.expose();
+    if !admin.is_empty() && constant_eq(admin.as_bytes(), key.as_bytes()) {
+        return Ok(ApiKey::unlimited("admin"));
+    }
+
+    let k = find_key(key)
+        .await?
+        .filter(|k| !k.revoked)
+        .ok_or_else(|| AppError::unauthorized("invalid or revoked api key"))?;
+    consume(&k, class).await?;
+    Ok(k)
+}
+
+fn outcome<T>(req: &Request<'_>, r: Result<T, AppError>) -> request::Outcome<T, AppError> {
+    match r {
+        Ok(v) => request::Outcome::Success(v),
+        Err(err) => request::Outcome::Error((guard_error(req, err.clone()), err)),
+    }
+}
+
+/// 需要有效的 key, 不限流
+pub struct Auth(pub ApiKey);
+/// 访问 NCBI 的接口
+pub struct Ncbi(pub ApiKey);
+/// 访问 LLM 的接口
+pub struct Llm(pub ApiKey);
+/// 管理 key 等接口
+pub struct Admin(pub ApiKey);
+/// 静态文件, 默认公开
+pub struct Public;
+
+macro_rules! guard {
+    ($t:ident, $class:expr) => {
+        #[rocket::async_trait]
+        impl<'r> FromRequest<'r> for $t {
+            type Error = AppError;
+
+            async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+                outcome(req, authorize(req, $class).await.map($t))
+            }
+        }
+    };
+}
+
+guard!(Auth, Class::Other);
+guard!(Ncbi, Class::Ncbi);
+guard!(Llm, Class::Llm);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = AppError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let r = authorize(req, Class::Other).await.and_then(|k| {
+            if k.admin {
+                Ok(Admin(k))
+            } else {
+                Err(AppError::forbidden("admin api key required"))
+            }
+        });
+        outcome(req, r)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Public {
+    type Error = AppError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if public_static() {
+            return request::Outcome::Success(Public);
+        }
+        outcome(req, authorize(req, Class::Other).await.map(|_| Public))
+    }
+}
+
+/// 新建 key 的参数, 没有指定的限制使用默认值
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct IssueKey {
+    pub name: String,
+    #[serde(default)]
+    pub admin: bool,
+    pub ncbi_per_minute: Option<u32>,
+    pub llm_per_minute: Option<u32>,
+    pub ncbi_daily: Option<u32>,
+    pub llm_daily: Option<u32>,
+}
+
+impl IssueKey {
+    fn into_key(self) -> ApiKey {
+        ApiKey {
+            key: format!("ek_{}", uuid::Uuid::new_v4().simple()),
+            name: self.name,
+            admin: self.admin,
+            revoked: false,
+            ncbi_per_minute: self.ncbi_per_minute.unwrap_or(60),
+            llm_per_minute: self.llm_per_minute.unwrap_or(10),
+            ncbi_daily: self.ncbi_daily.unwrap_or(5000),
+            llm_daily: self.llm_daily.unwrap_or(500),
+            created_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+#[post("/admin/keys", format = "json", data = "<req>")]
+pub async fn issue_key(_admin: Admin, req: Json<IssueKey>) -> ApiResult {
+    if req.name.trim().is_empty() {
+        return Err(AppError::bad_request("name is empty"));
+    }
+    let key = req.into_inner().into_key();
+    let d = bson::to_document(&key).map_err(|err| AppError::internal(err.to_string()))?;
+    collection(KEY_COLLECTION)?
+        .insert_one(d, None)
+        .await
+        .map_err(db_error)?;
+    log::info!("issue api key name = {}", &key.name);
+    api_ok(&key)
+}
+
+/// 全部 key 和当天的用量
+#[get("/admin/keys")]
+pub async fn list_keys(_admin: Admin) -> ApiResult {
+    let mut cursor = collection(KEY_COLLECTION)?
+        .find(None, None)
+        .await
+        .map_err(db_error)?;
+    let day = today();
+    let mut v = Vec::new();
+    while let Some(d) = cursor.next().await {
+        if let Ok(k) = bson::from_document::<ApiKey>(d.map_err(db_error)?) {
+            let usage = load_usage(&k.key, &day).await;
+            v.push(serde_json::json!({ "key": k, "usage": usage }));
+        }
+    }
+    api_ok(&v)
+}
+
+#[delete("/admin/keys/<key>")]
+pub async fn revoke_key(_admin: Admin, key: String) -> ApiResult {
+    let r = collection(KEY_COLLECTION)?
+        .update_one(
+            doc! { "key": &key },
+            doc! { "$set": { "revoked": true } },
+            None,
+        )
+        .await
+        .map_err(db_error)?;
+    if r.matched_count == 0 {
+        return Err(AppError::not_found("api key not found"));
+    }
+    KEYS.lock().unwrap().remove(&key);
+    log::info!("revoke api key {}", &key);
+    api_ok(&serde_json::json!({ "key": key, "revoked": true }))
+}
+
+/// 当前 key 的限制和当天的用量
+#[get("/keys/usage")]
+pub async fn key_usage(auth: Auth) -> ApiResult {
+    let usage = load_usage(&auth.0.key, &today()).await;
+    api_ok(&serde_json::json!({ "key": auth.0, "usage": usage }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let mut w = Window::default();
+        assert!(w.hit(1, 2));
+        assert!(w.hit(1, 2));
+        assert!(!w.hit(1, 2));
+        assert!(w.hit(2, 2));
+
+        let mut w = Window::default();
+        assert!((0..100).all(|_| w.hit(1, 0)));
+    }
+
+    #[test]
+    fn test_constant_eq() {
+        assert!(constant_eq(b"admin", b"admin"));
+        assert!(!constant_eq(b"admin", b"admix"));
+        assert!(!constant_eq(b"admin", b"admin2"));
+        assert!(!constant_eq(b"", b"a"));
+    }
+
+    #[test]
+    fn test_issue_key() {
+        let k = IssueKey {
+            name: "lab".to_string(),
+            admin: false,
+            ncbi_per_minute: None,
+            llm_per_minute: Some(0),
+            ncbi_daily: None,
+            llm_daily: None,
+        }
+        .into_key();
+        assert!(k.key.starts_with("ek_"));
+        assert_eq!((60, 5000), k.limits(Class::Ncbi));
+        assert_eq!((0, 500), k.limits(Class::Llm));
+        assert_eq!((0, 0), k.limits(Class::Other));
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut u = Usage::default();
+        u.add(Class::Llm);
+        u.add(Class::Llm);
+        assert_eq!(2, u.get(Class::Llm));
+        assert_eq!(0, u.get(Class::Ncbi));
+    }
+}
@@ -0,0 +1,287 @@
+use std::{collections::HashSet, error::Error};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::model::PaperCsvResult;
+
+/// 一次最多查询的 PMID 和 DOI 数
+pub const MAX_IDS: usize = 5000;
+
+#[derive(Debug, PartialEq)]
+enum Id {
+    Pmid(String),
+    /// 小写的 DOI
+    Doi(String),
+}
+
+/// 纯数字为 PMID, `10.` 开头为 DOI, 支持 `doi:` 和 `https://doi.org/` 前缀
+fn parse_id(s: &str) -> Option<Id> {
+    let s = s.trim();
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return s.parse::<usize>().ok().map(|f| Id::Pmid(f.to_string()));
+    }
+
+    let lower = s.to_lowercase();
+    let doi = [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "doi:",
+    ]
+    .iter()
+    .find_map(|p| lower.strip_prefix(p))
+    .unwrap_or(&lower)
+    .trim();
+    if doi.starts_with("10.") && doi.contains('/') {
+        Some(Id::Doi(doi.to_string()))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct Lookup {
+    /// 按输入的顺序, 重复的只保留第一个
+    pub data: Vec<PaperCsvResult>,
+    /// 没有找到的输入, 保持原样
+    pub not_found: Vec<String>,
+}
+
+/// 按 PMID 或 DOI 批量读取文献, 缓存没有的通过 efetch 下载; 有不合法的 id 时返回错误
+pub async fn lookup(ids: &[String]) -> Result<Lookup, Box<dyn Error + Send + Sync>> {
+    let mut seen = HashSet::new();
+    let mut parsed = Vec::new();
+    for raw in ids {
+        let id = parse_id(raw).ok_or_else(|| format!("invalid pmid or doi: {}", raw))?;
+        let key = match &id {
+            Id::Pmid(f) | Id::Doi(f) => f.clone(),
+        };
+        if seen.insert(key) {
+            parsed.push((raw.trim().to_string(), id));
+        }
+    }
+
+    let pmids = parsed
+        .iter()
+        .filter_map(|(_, f)| match f {
+            Id::Pmid(id) => Some(id.clone()),
+            Id::Doi(_) => None,
+        })
+        .collect::<Vec<String>>();
+    let dois = parsed
+        .iter()
+        .filter_map(|(_, f)| match f {
+            Id::Doi(doi) => Some(doi.clone()),
+            Id::Pmid(_) => None,
+        })
+        .collect::<Vec<String>>();
+    log::info!("lookup pmids = {}, dois = {}", pmids.len(), dois.len());
+
+    let mut by_pmid = crate::eutils::efetch_batch("pubmed", &pmids).await?;
+    let mut by_doi = if dois.is_empty() {
+        Default::default()
+    } else {
+        crate::eutils::efetch_dois("pubmed", &dois).await?
+    };
+
+    let mut r = Lookup::default();
+    for (raw, id) in parsed {
+        let paper = match &id {
+            Id::Pmid(f) => by_pmid.remove(f),
+            Id::Doi(f) => by_doi.remove(f),
+        };
+        match paper {
+            Some(p) => r.data.push(p),
+            None => r.not_found.push(raw),
+        }
+    }
+    Ok(r)
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{
+        http::{Accept, ContentType, MediaType},
+        post,
+        response::{self, Responder},
+        serde::json::Json,
+        Request, Response,
+    };
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::auth::Ncbi;
+    use crate::error::AppError;
+    use crate::response::response_ok;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    pub struct FetchRequest {
+        /// PMID 或 DOI
+        pub ids: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Format {
+        Json,
+        Csv,
+        Ris,
+    }
+
+    /// 按 `Accept` 的权重选择格式, 都不支持时返回 json
+    fn negotiate(accept: Option<&Accept>) -> Format {
+        let Some(accept) = accept else {
+            return Format::Json;
+        };
+        let mut types = accept.iter().collect::<Vec<_>>();
+        types.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+        types
+            .iter()
+            .find_map(|f| {
+                let m = f.media_type();
+                if m.top() == "*" || *m == MediaType::JSON {
+                    Some(Format::Json)
+                } else if *m == MediaType::CSV {
+                    Some(Format::Csv)
+                } else if m.sub() == "x-research-info-systems" {
+                    Some(Format::Ris)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Format::Json)
+    }
+
+    /// 响应头 `X-Not-Found` 中最多放的 id 数, 避免超过代理的响应头大小限制
+    const MAX_NOT_FOUND_HEADER: usize = 100;
+
+    /// csv 和 ris 没有找到的 id 数放在响应头 `X-Not-Found-Count`, 前 100 个放在 `X-Not-Found`
+    pub struct Fetched {
+        content_type: ContentType,
+        body: String,
+        not_found: Vec<String>,
+    }
+
+    fn not_found_header(ids: &[String]) -> String {
+        ids.iter()
+            .take(MAX_NOT_FOUND_HEADER)
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    impl<'r> Responder<'r, 'static> for Fetched {
+        fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+            let mut r = Response::build_from(self.body.respond_to(req)?);
+            r.header(self.content_type);
+            if !self.not_found.is_empty() {
+                r.raw_header("X-Not-Found-Count", self.not_found.len().to_string());
+                r.raw_header("X-Not-Found", not_found_header(&self.not_found));
+            }
+            r.ok()
+        }
+    }
+
+    /// 按 PMID 或 DOI 批量读取, 按 `Accept` 返回 json, csv (`text/csv`) 或 ris (`application/x-research-info-systems`)
+    #[post("/pubmed/fetch", format = "json", data = "<req>")]
+    pub async fn fetch(
+        _key: Ncbi,
+        accept: Option<&Accept>,
+        req: Json<FetchRequest>,
+    ) -> Result<Fetched, AppError> {
+        if req.ids.is_empty() || req.ids.len() > MAX_IDS {
+            return Err(AppError::bad_request(format!(
+                "ids must have 1 to {} items",
+                MAX_IDS
+            )));
+        }
+        if let Some(id) = req.ids.iter().find(|f| parse_id(f).is_none()) {
+            return Err(AppError::bad_request(format!(
+                "invalid pmid or doi: {}",
+                id
+            )));
+        }
+
+        let r = lookup(&req.ids).await?;
+        let not_found = r.not_found.clone();
+        let fetched = match negotiate(accept) {
+            Format::Json => Fetched {
+                content_type: ContentType::JSON,
+                body: response_ok(serde_json::to_value(&r)?).0,
+                not_found: vec![],
+            },
+            Format::Csv => Fetched {
+                content_type: ContentType::CSV,
+                body: PaperCsvResult::list_csv(&r.data),
+                not_found,
+            },
+            Format::Ris => {
+                let records = r
+                    .data
+                    .iter()
+                    .map(crate::export::Record::new)
+                    .collect::<Vec<crate::export::Record>>();
+                Fetched {
+                    content_type: ContentType::new("application", "x-research-info-systems"),
+                    body: crate::export::ris::to_ris(&records),
+                    not_found,
+                }
+            }
+        };
+        Ok(fetched)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_negotiate() {
+            let accept = |s: &str| s.parse::<Accept>().unwrap();
+            assert_eq!(Format::Json, negotiate(None));
+            assert_eq!(Format::Json, negotiate(Some(&accept("*/*"))));
+            assert_eq!(Format::Csv, negotiate(Some(&accept("text/csv"))));
+            assert_eq!(
+                Format::Ris,
+                negotiate(Some(&accept(
+                    "text/csv;q=0.5, application/x-research-info-systems"
+                )))
+            );
+            assert_eq!(Format::Json, negotiate(Some(&accept("text/html"))));
+        }
+
+        #[test]
+        fn test_not_found_header() {
+            let ids = (0..150).map(|f| f.to_string()).collect::<Vec<String>>();
+            let header = not_found_header(&ids);
+            assert_eq!(MAX_NOT_FOUND_HEADER, header.split(',').count());
+            assert!(header.starts_with("0,1,2,"));
+            assert_eq!("1,2", not_found_header(&ids[1..3]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(
+            Some(Id::Pmid("28250621".to_string())),
+            parse_id(" 28250621 ")
+        );
+        assert_eq!(
+            Some(Id::Doi("10.1186/s12916-023-02745-6".to_string())),
+            parse_id("https://doi.org/10.1186/S12916-023-02745-6")
+        );
+        assert_eq!(
+            Some(Id::Doi("10.4103/0974-777x.199997".to_string())),
+            parse_id("doi:10.4103/0974-777X.199997")
+        );
+        assert_eq!(None, parse_id("abc"));
+        assert_eq!(None, parse_id("10.1186"));
+        assert_eq!(None, parse_id(""));
+    }
+}
@@ -0,0 +1,620 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::{Author, PaperCsvResult},
+    search::SearchIndex,
+    utils::{file_exist, pmid_path, read_target_csv},
+};
+
+static PMID_ROOT: &str = "pmid";
+
+/// 缓存记录的元信息
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct PaperMeta {
+    pub pmid: String,
+    /// 下载到本地的时间, 毫秒
+    pub fetched_at: i64,
+    /// PubMed 上的最后修改日期 (DateRevised), 如 2023/02/10
+    pub modified_at: String,
+    #[serde(default)]
+    pub mesh: Vec<String>,
+    /// 完整的作者列表, 导出引用时使用
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub volume: String,
+    #[serde(default)]
+    pub issue: String,
+    /// MedlinePgn, 如 123-30
+    #[serde(default)]
+    pub pages: String,
+}
+
+impl PaperMeta {
+    pub fn new(pmid: &str, modified_at: &str) -> Self {
+        PaperMeta {
+            pmid: pmid.to_string(),
+            fetched_at: chrono::Utc::now().timestamp_millis(),
+            modified_at: modified_at.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn load(id: usize) -> Option<PaperMeta> {
+        global().load_meta(id)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        global().save_meta(self)
+    }
+}
+
+/// 文献缓存, 保存在数据目录 `dir` 的 `pmid` 下; 有 `index` 时写入和删除同时更新本地检索索引
+#[derive(Debug, Clone)]
+pub struct PaperCache {
+    dir: String,
+    index: Option<Arc<SearchIndex>>,
+}
+
+impl PaperCache {
+    pub fn new(dir: &str) -> Self {
+        PaperCache {
+            dir: dir.to_string(),
+            index: None,
+        }
+    }
+
+    pub fn with_index(mut self, index: Arc<SearchIndex>) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    pub fn csv_path(&self, id: usize) -> String {
+        pmid_path(&self.dir, id)
+    }
+
+    pub fn meta_path(&self, id: usize) -> String {
+        format!("{}.json", self.csv_path(id).trim_end_matches(".csv"))
+    }
+
+    pub fn load_paper(&self, id: usize) -> Option<PaperCsvResult> {
+        let path = self.csv_path(id);
+        if !file_exist(&path) {
+            return None;
+        }
+
+        let mut v: Vec<PaperCsvResult> = Vec::new();
+        read_target_csv(&path, b',', &mut v).ok()?;
+        v.pop()
+    }
+
+    pub fn load_meta(&self, id: usize) -> Option<PaperMeta> {
+        let text = fs::read_to_string(self.meta_path(id)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save_meta(&self, meta: &PaperMeta) -> io::Result<()> {
+        let id = match meta.pmid.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => return Ok(()),
+        };
+
+        let file_name = self.meta_path(id);
+        let path = Path::new(&file_name);
+        if let Some(prefix) = path.parent() {
+            fs::create_dir_all(prefix)?;
+        }
+
+        fs::write(path, serde_json::to_string(meta)?)
+    }
+
+    /// 写入缓存并更新索引, `overwrite` 为 false 时不覆盖已有的 csv; PMID 不合法时跳过
+    pub fn store(
+        &self,
+        paper: &PaperCsvResult,
+        meta: &PaperMeta,
+        overwrite: bool,
+    ) -> io::Result<()> {
+        let id = match paper.pmid.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => return Ok(()),
+        };
+
+        let path = self.csv_path(id);
+        if overwrite || !file_exist(&path) {
+            paper.write_csv(&path)?;
+        }
+        self.save_meta(meta)?;
+        if let Some(index) = &self.index {
+            index.index_paper(paper, meta);
+        }
+
+        Ok(())
+    }
+
+    /// 记录并删除损坏的记录, 同时删除元信息和索引, 下次请求时重新下载
+    pub fn mark_corrupt(&self, id: usize) {
+        let path = self.csv_path(id);
+        log::warn!("cache file corrupt, removed: {}", &path);
+        self.remove(id);
+
+        let mut v = CORRUPT.lock().unwrap();
+        if !v.iter().any(|f| f == &path) {
+            if v.len() >= MAX_CORRUPT {
+                v.remove(0);
+            }
+            v.push(path);
+        }
+    }
+
+    /// 删除缓存及索引, 返回 csv 是否存在
+    pub fn remove(&self, id: usize) -> bool {
+        let _ = fs::remove_file(self.meta_path(id));
+        if let Some(index) = &self.index {
+            index.remove(id);
+        }
+        fs::remove_file(self.csv_path(id)).is_ok()
+    }
+
+    /// 缓存目录下的全部文件, (pmid, 路径)
+    fn files(&self) -> Vec<(usize, PathBuf)> {
+        let mut v = Vec::new();
+
+        let dirs = |p: &Path| -> Vec<PathBuf> {
+            fs::read_dir(p)
+                .map(|dir| {
+                    dir.filter_map(|f| f.ok().map(|e| e.path()))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        for million in dirs(&Path::new(&self.dir).join(PMID_ROOT)) {
+            for thousand in dirs(&million) {
+                for file in dirs(&thousand) {
+                    if let Some(id) = file
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .and_then(|s| s.parse::<usize>().ok())
+                    {
+                        v.push((id, file));
+                    }
+                }
+            }
+        }
+
+        v
+    }
+
+    /// 已缓存的全部 pmid
+    pub fn cached_pmids(&self) -> Vec<usize> {
+        let mut v = self
+            .files()
+            .into_iter()
+            .filter(|(_, p)| is_csv(p))
+            .map(|(id, _)| id)
+            .collect::<Vec<usize>>();
+
+        v.sort();
+        v
+    }
+
+    /// 检查一条缓存记录, 有问题时返回原因; PubMed 上有标题为空的记录, 不算损坏
+    fn validate(&self, id: usize, path: &Path) -> Option<String> {
+        let mut v: Vec<PaperCsvResult> = Vec::new();
+        if let Err(err) = read_target_csv(path, b',', &mut v) {
+            return Some(format!("csv parse error: {}", err));
+        }
+        if v.len() != 1 {
+            return Some(format!("expect 1 record, found {}", v.len()));
+        }
+        if v[0].pmid.is_empty() {
+            return Some("missing pmid".to_string());
+        }
+        if v[0].pmid != id.to_string() {
+            return Some(format!("pmid mismatch: {}", v[0].pmid));
+        }
+
+        if Path::new(&self.meta_path(id)).exists() && self.load_meta(id).is_none() {
+            return Some("meta parse error".to_string());
+        }
+
+        None
+    }
+
+    /// 重新校验全部缓存记录, 已经在运行时返回 false
+    pub fn scan(&self, fix: bool) -> bool {
+        if !begin_scan(fix) {
+            return false;
+        }
+        self.run_scan(fix);
+        true
+    }
+
+    fn run_scan(&self, fix: bool) {
+        let files = self
+            .files()
+            .into_iter()
+            .filter(|(_, p)| is_csv(p))
+            .collect::<Vec<_>>();
+        SCAN.lock().unwrap().total = files.len();
+
+        for (id, path) in files {
+            let reason = self.validate(id, &path);
+            let mut report = SCAN.lock().unwrap();
+            report.checked += 1;
+            if let Some(reason) = reason {
+                let p = path.to_string_lossy().to_string();
+                if fix {
+                    self.remove(id);
+                }
+                report.corrupt.push((p, reason));
+            }
+        }
+
+        let mut report = SCAN.lock().unwrap();
+        report.running = false;
+        report.finished_at = Some(chrono::Utc::now().timestamp_millis());
+        log::info!(
+            "cache scan done, checked = {}, corrupt = {}",
+            report.checked,
+            report.corrupt.len()
+        );
+    }
+
+    /// 按 pmid 范围或下载时间删除, 条件同时给出时都要满足
+    pub fn purge(
+        &self,
+        from: Option<usize>,
+        to: Option<usize>,
+        older_than_days: Option<i64>,
+    ) -> usize {
+        let before = older_than_days
+            .map(|d| (chrono::Utc::now() - chrono::Duration::days(d)).timestamp_millis());
+
+        let fetched_at = |id: usize, path: &Path| -> i64 {
+            self.load_meta(id)
+                .map(|m| m.fetched_at)
+                .or_else(|| {
+                    fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).timestamp_millis())
+                })
+                .unwrap_or(0)
+        };
+
+        let mut n = 0;
+        for (id, path) in self.files().into_iter().filter(|(_, p)| is_csv(p)) {
+            if from.is_some_and(|f| id < f) || to.is_some_and(|t| id > t) {
+                continue;
+            }
+            if before.is_some_and(|b| fetched_at(id, &path) >= b) {
+                continue;
+            }
+            if self.remove(id) {
+                n += 1;
+            }
+        }
+
+        log::info!(
+            "cache purge {:?} - {:?}, older_than_days = {:?}, removed = {}",
+            from,
+            to,
+            older_than_days,
+            n
+        );
+        n
+    }
+
+    fn stats(&self) -> serde_json::Value {
+        let mut records = 0u64;
+        let mut metas = 0u64;
+        let mut bytes = 0u64;
+        for (_, path) in self.files() {
+            if is_csv(&path) {
+                records += 1;
+            } else {
+                metas += 1;
+            }
+            bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        }
+
+        let hits = HITS.load(Ordering::Relaxed);
+        let misses = MISSES.load(Ordering::Relaxed);
+        let hit_rate = if hits + misses > 0 {
+            hits as f64 / (hits + misses) as f64
+        } else {
+            0.0
+        };
+
+        serde_json::json!({
+            "records": records,
+            "meta_records": metas,
+            "size_bytes": bytes,
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": hit_rate,
+            "corrupt": CORRUPT.lock().unwrap().clone(),
+            "last_scan": SCAN.lock().unwrap().clone(),
+        })
+    }
+}
+
+/// 按配置的 `data.dir` 和服务的检索索引, 服务和命令行使用
+static GLOBAL: Lazy<PaperCache> = Lazy::new(|| {
+    PaperCache::new(&crate::settings::settings().data.dir).with_index(crate::search::shared())
+});
+
+pub fn global() -> &'static PaperCache {
+    &GLOBAL
+}
+
+/// 写入缓存并更新本地索引, `overwrite` 为 false 时不覆盖已有的 csv
+pub fn store(paper: &PaperCsvResult, meta: &PaperMeta, overwrite: bool) -> io::Result<()> {
+    global().store(paper, meta, overwrite)
+}
+
+/// 读取缓存的文献
+pub fn load_paper(id: usize) -> Option<PaperCsvResult> {
+    global().load_paper(id)
+}
+
+/// 删除缓存及索引, 返回 csv 是否存在
+pub fn remove(id: usize) -> bool {
+    global().remove(id)
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("csv")
+}
+
+/// 本地已缓存的全部 pmid
+pub fn cached_pmids() -> Vec<usize> {
+    global().cached_pmids()
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// 最近发现的损坏文件
+static CORRUPT: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+const MAX_CORRUPT: usize = 1000;
+
+pub fn record_hit() {
+    HITS.fetch_add(1, Ordering::Relaxed);
+    crate::metrics::CACHE_HITS.inc();
+}
+
+pub fn record_miss() {
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    crate::metrics::CACHE_MISSES.inc();
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ScanReport {
+    pub running: bool,
+    pub total: usize,
+    pub checked: usize,
+    /// (路径, 原因)
+    pub corrupt: Vec<(String, String)>,
+    /// 是否删除损坏的记录
+    pub fix: bool,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
+
+static SCAN: Lazy<Mutex<ScanReport>> = Lazy::new(|| Mutex::new(ScanReport::default()));
+
+/// 没有在运行时标记为开始, 检查和标记在同一个锁内, 同时只有一个校验
+fn begin_scan(fix: bool) -> bool {
+    let mut report = SCAN.lock().unwrap();
+    if report.running {
+        return false;
+    }
+    *report = ScanReport {
+        running: true,
+        fix,
+        started_at: Some(chrono::Utc::now().timestamp_millis()),
+        ..Default::default()
+    };
+    true
+}
+
+/// 重新校验全部缓存记录, 已经在运行时返回 false
+pub fn scan(fix: bool) -> bool {
+    global().scan(fix)
+}
+
+/// 按 pmid 范围或下载时间删除, 条件同时给出时都要满足
+pub fn purge(from: Option<usize>, to: Option<usize>, older_than_days: Option<i64>) -> usize {
+    global().purge(from, to, older_than_days)
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PrefetchRequest {
+    pub pmids: Vec<String>,
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{get, post, serde::json::Json};
+
+    use super::*;
+    use crate::auth::{Admin, Auth, Ncbi};
+    use crate::error::{api_ok, ApiResult, AppError};
+    use crate::response::response_ok;
+
+    #[get("/cache/stats")]
+    pub async fn cache_stats(_key: Auth) -> ApiResult {
+        let s = tokio::task::spawn_blocking(|| global().stats())
+            .await
+            .map_err(|err| AppError::internal(err.to_string()))?;
+        Ok(response_ok(s))
+    }
+
+    #[post("/cache/purge?<from>&<to>&<older_than_days>")]
+    pub async fn cache_purge(
+        _key: Admin,
+        from: Option<usize>,
+        to: Option<usize>,
+        older_than_days: Option<i64>,
+    ) -> ApiResult {
+        if from.is_none() && to.is_none() && older_than_days.is_none() {
+            return Err(AppError::bad_request("need from, to or older_than_days"));
+        }
+
+        let n = tokio::task::spawn_blocking(move || global().purge(from, to, older_than_days))
+            .await
+            .map_err(|err| AppError::internal(err.to_string()))?;
+        Ok(response_ok(serde_json::json!({ "removed": n })))
+    }
+
+    #[post("/cache/prefetch", format = "json", data = "<req>")]
+    pub async fn cache_prefetch(_key: Ncbi, req: Json<PrefetchRequest>) -> ApiResult {
+        let mut missing = Vec::new();
+        for pmid in &req.pmids {
+            match pmid.trim().parse::<usize>() {
+                Ok(id) if !file_exist(&global().csv_path(id)) => missing.push(id.to_string()),
+                Ok(_) => {}
+                Err(_) => return Err(AppError::bad_request(format!("invalid pmid: {}", pmid))),
+            }
+        }
+
+        let queued = missing.len();
+        log::info!("cache prefetch, queued = {}", queued);
+        crate::logging::spawn(async move {
+            if let Err(err) = crate::eutils::efetch("pubmed", &missing).await {
+                log::warn!("cache prefetch error = {:?}", err);
+            }
+        });
+
+        Ok(response_ok(serde_json::json!({
+            "queued": queued,
+            "cached": req.pmids.len() - queued,
+        })))
+    }
+
+    #[post("/cache/scan?<fix>")]
+    pub async fn cache_scan(_key: Admin, fix: Option<bool>) -> ApiResult {
+        let fix = fix.unwrap_or(false);
+        if !begin_scan(fix) {
+            return Err(AppError::conflict("scan is already running"));
+        }
+        tokio::task::spawn_blocking(move || global().run_scan(fix));
+
+        Ok(response_ok(serde_json::json!({ "fix": fix })))
+    }
+
+    #[get("/cache/scan")]
+    pub async fn cache_scan_status(_key: Auth) -> ApiResult {
+        let s = SCAN.lock().unwrap().clone();
+        api_ok(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let dir = std::env::temp_dir().join("rust_eutils_test_validate");
+        let _ = fs::create_dir_all(&dir);
+        let cache = PaperCache::new(dir.to_str().unwrap());
+
+        let ok = dir.join("1.csv");
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            title: "title".to_string(),
+            ..Default::default()
+        };
+        crate::utils::save_to_file(ok.to_str().unwrap(), &[paper]).unwrap();
+        assert_eq!(None, cache.validate(1, &ok));
+        assert!(cache.validate(2, &ok).unwrap().starts_with("pmid mismatch"));
+
+        let untitled = dir.join("4.csv");
+        let paper = PaperCsvResult {
+            pmid: "4".to_string(),
+            ..Default::default()
+        };
+        crate::utils::save_to_file(untitled.to_str().unwrap(), &[paper]).unwrap();
+        assert_eq!(None, cache.validate(4, &untitled));
+
+        let missing = dir.join("5.csv");
+        crate::utils::save_to_file(missing.to_str().unwrap(), &[PaperCsvResult::default()])
+            .unwrap();
+        assert_eq!(
+            Some("missing pmid".to_string()),
+            cache.validate(5, &missing)
+        );
+
+        let bad = dir.join("3.csv");
+        fs::write(&bad, "PMID,Title\n\"3,broken").unwrap();
+        assert!(cache.validate(3, &bad).is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_begin_scan() {
+        assert!(begin_scan(false));
+        assert!(!begin_scan(true));
+        SCAN.lock().unwrap().running = false;
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("rust_eutils_test_{}", uuid::Uuid::new_v4()));
+        let cache = PaperCache::new(dir.to_str().unwrap());
+        for id in ["36765305", "1"] {
+            let paper = PaperCsvResult {
+                pmid: id.to_string(),
+                ..Default::default()
+            };
+            cache.store(&paper, &PaperMeta::new(id, ""), true).unwrap();
+        }
+        assert_eq!(vec![1, 36765305], cache.cached_pmids());
+        assert_eq!(4, cache.files().len());
+
+        assert_eq!(1, cache.purge(Some(2), None, None));
+        assert_eq!(vec![1], cache.cached_pmids());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_mark_corrupt() {
+        let dir = std::env::temp_dir().join(format!("rust_eutils_test_{}", uuid::Uuid::new_v4()));
+        let cache = PaperCache::new(dir.to_str().unwrap());
+        let paper = PaperCsvResult {
+            pmid: "36765305".to_string(),
+            ..Default::default()
+        };
+        cache
+            .store(&paper, &PaperMeta::new("36765305", "2023/02/10"), true)
+            .unwrap();
+        assert!(cache.load_meta(36765305).is_some());
+
+        cache.mark_corrupt(36765305);
+        assert!(!file_exist(&cache.csv_path(36765305)));
+        assert!(cache.load_meta(36765305).is_none());
+        assert!(CORRUPT.lock().unwrap().contains(&cache.csv_path(36765305)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    error::Error,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{export::columns, model::PaperCsvResult};
+
+type CliResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// PubMed E-utilities 的 http 服务和命令行工具, 不带子命令时启动 http 服务
+#[derive(Parser, Debug)]
+#[command(name = "rust-eutils", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 启动 http 服务
+    Serve,
+    /// 检索 PubMed, 输出 PMID 列表和总数
+    Search {
+        term: String,
+        #[arg(long, default_value_t = 0)]
+        page: usize,
+        #[arg(long, default_value_t = 10)]
+        page_size: usize,
+    },
+    /// 按 PMID 下载文献, PMID 可以用逗号或空格分隔
+    Fetch {
+        #[arg(required = true)]
+        pmids: Vec<String>,
+        #[command(flatten)]
+        out: Output,
+    },
+    /// 导出检索结果
+    Export {
+        term: String,
+        #[arg(long, default_value_t = 0)]
+        page: usize,
+        #[arg(long, default_value_t = 10)]
+        page_size: usize,
+        /// 导出全部结果, 忽略分页
+        #[arg(long)]
+        all: bool,
+        /// 导出的列, 如 `PMID,Title:标题,Authors`
+        #[arg(long)]
+        columns: Option<String>,
+        /// 保存的列配置
+        #[arg(long)]
+        profile: Option<String>,
+        #[command(flatten)]
+        out: Output,
+    },
+    /// 读取文献 csv, 让 LLM 针对每篇摘要回答问题
+    Summarize {
+        csv: PathBuf,
+        question: String,
+        /// csv 或 xlsx
+        #[arg(long, default_value = "csv")]
+        format: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 读取基因和疾病的 csv, 统计相关的文献数量
+    Disease {
+        csv: PathBuf,
+        /// csv 或 xlsx
+        #[arg(long, default_value = "csv")]
+        format: String,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 从 PubMed baseline/update 文件导入本地缓存, 不需要网络, 中断后重新运行会继续
+    Import {
+        /// `.xml.gz` 或 `.xml` 文件所在的目录
+        dir: PathBuf,
+    },
+    /// Slurm 作业同步
+    Slurm {
+        #[command(subcommand)]
+        command: SlurmCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SlurmCommand {
+    /// 从各个集群同步作业, 默认每 10 分钟一次
+    Sync {
+        /// 只同步一次
+        #[arg(long)]
+        once: bool,
+    },
+    /// 最近结束的作业
+    Jobs {
+        /// 集群名或集群描述
+        #[arg(long)]
+        cloud: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Output {
+    /// json, csv, ris, bib, xml 或 xlsx
+    #[arg(long, default_value = "json")]
+    format: String,
+    /// 输出文件, 默认输出到 stdout, xlsx 必须指定
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub async fn run(command: Command) -> CliResult {
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Search {
+            term,
+            page,
+            page_size,
+        } => {
+            let r = crate::eutils::esearch2("pubmed", &term, Some(page), Some(page_size)).await?;
+            println!("{}", serde_json::to_string_pretty(&r)?);
+            Ok(())
+        }
+        Command::Fetch { pmids, out } => {
+            let ids = split_pmids(&pmids)?;
+            let list = crate::eutils::efetch("pubmed", &ids).await?;
+            emit(&list, &ids.join(","), None, &out)
+        }
+        Command::Export {
+            term,
+            page,
+            page_size,
+            all,
+            columns,
+            profile,
+            out,
+        } => {
+            let fields = columns::request_fields(columns.as_deref(), profile.as_deref())?;
+            let list = if all {
+                crate::eutils::esearch("pubmed", &term).await?
+            } else {
+                crate::eutils::esearch3("pubmed", &term, Some(page), Some(page_size)).await?
+            };
+            emit(&list, &term, fields.as_deref(), &out)
+        }
+        Command::Summarize {
+            csv,
+            question,
+            format,
+            output,
+        } => {
+            let file = crate::openai::chat_abstract_summary(&csv, &question, Some(&format)).await?;
+            move_result(&file, output.as_deref())
+        }
+        Command::Disease {
+            csv,
+            format,
+            output,
+        } => {
+            let rows = crate::disease::query_gene_and_disease(&csv).await?;
+            let meta = crate::export::xlsx::Meta {
+                query: crate::disease::QUERY_TEMPLATE,
+                counts: vec![("genes", rows.len())],
+            };
+            let (file, _, _) = crate::export::save_table(&rows, Some(&format), &meta)?;
+            move_result(&file, output.as_deref())
+        }
+        Command::Import { dir } => {
+            let dir = dir.to_string_lossy().to_string();
+            tokio::task::spawn_blocking(move || crate::import::import_dir(&dir)).await??;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&crate::import::progress())?
+            );
+            Ok(())
+        }
+        Command::Slurm { command } => slurm(command).await,
+    }
+}
+
+async fn slurm(command: SlurmCommand) -> CliResult {
+    let url = crate::settings::settings().mongo.url.expose();
+    crate::slurm::db::init_db(url).await?;
+    crate::slurm::db::Db::ping().await?;
+
+    match command {
+        SlurmCommand::Sync { once } => loop {
+            crate::logging::scope(
+                crate::logging::new_id("sync"),
+                crate::slurm::sync::start_sync(),
+            )
+            .await;
+            if once {
+                return Ok(());
+            }
+            tokio::time::sleep(crate::slurm::SYNC_INTERVAL).await;
+        },
+        SlurmCommand::Jobs { cloud, limit } => {
+            let jobs = crate::slurm::sync::recent_jobs(cloud.as_deref(), limit).await?;
+            println!("job_id\tcloud\tuser\tstate\tend\telapsed\tname\twork_dir");
+            for j in jobs {
+                let end = j
+                    .end
+                    .map(|f| f.to_chrono().format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default();
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    j.job_id, j.cloud, j.user, j.state, end, j.elapsed_raw, j.job_name, j.work_dir
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 参数中的 PMID, 支持逗号分隔
+fn split_pmids(args: &[String]) -> Result<Vec<String>, String> {
+    let ids: Vec<String> = args
+        .iter()
+        .flat_map(|f| f.split(','))
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_string())
+        .collect();
+    match ids.iter().find(|f| f.parse::<usize>().is_err()) {
+        Some(id) => Err(format!("invalid pmid: {}", id)),
+        None => Ok(ids),
+    }
+}
+
+/// 按 `--format` 写入 `--output`, 没有指定时输出到 stdout
+fn emit(
+    list: &[PaperCsvResult],
+    query: &str,
+    fields: Option<&[(columns::Field, String)]>,
+    out: &Output,
+) -> CliResult {
+    if out.format.eq_ignore_ascii_case("json") {
+        let s = serde_json::to_string_pretty(list)?;
+        match &out.output {
+            Some(p) => std::fs::write(p, s)?,
+            None => println!("{}", s),
+        }
+        return Ok(());
+    }
+
+    if let Some(p) = &out.output {
+        crate::export::write_list(list, &out.format, query, fields, &p.to_string_lossy())?;
+        return Ok(());
+    }
+
+    if crate::export::normalize_type(&out.format) == Some("xlsx") {
+        return Err("xlsx needs --output".into());
+    }
+    let tmp = std::env::temp_dir().join(format!("rust-eutils-{}", uuid::Uuid::new_v4()));
+    let tmp = tmp.to_string_lossy().to_string();
+    let r = crate::export::write_list(list, &out.format, query, fields, &tmp)
+        .and_then(|_| Ok(std::fs::read(&tmp)?));
+    let _ = std::fs::remove_file(&tmp);
+    std::io::stdout().write_all(&r?)?;
+    Ok(())
+}
+
+/// 下载目录中的结果移动到 `output`, 没有指定时输出文件路径
+fn move_result(file: &str, output: Option<&Path>) -> CliResult {
+    match output {
+        Some(p) => {
+            std::fs::copy(file, p)?;
+            std::fs::remove_file(file)?;
+        }
+        None => println!("{}", file),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cli =
+            Cli::try_parse_from(["rust-eutils", "fetch", "1,2", "3", "--format", "ris"]).unwrap();
+        match cli.command {
+            Some(Command::Fetch { pmids, out }) => {
+                assert_eq!(vec!["1", "2", "3"], split_pmids(&pmids).unwrap());
+                assert_eq!("ris", out.format);
+                assert!(out.output.is_none());
+            }
+            _ => panic!("expected fetch"),
+        }
+
+        let cli = Cli::try_parse_from(["rust-eutils", "slurm", "sync", "--once"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Slurm {
+                command: SlurmCommand::Sync { once: true }
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["rust-eutils", "import", "/data/baseline"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(Command::Import { dir }) if dir.as_path() == Path::new("/data/baseline"))
+        );
+
+        assert!(Cli::try_parse_from(["rust-eutils"])
+            .unwrap()
+            .command
+            .is_none());
+        assert!(Cli::try_parse_from(["rust-eutils", "fetch"]).is_err());
+        assert!(split_pmids(&["12,abc".to_string()]).is_err());
+    }
+}
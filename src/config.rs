@@ -1,51 +1,41 @@
-use crossbeam_deque::Worker;
-use log::LevelFilter;
-use log4rs::{
-    append::{console::ConsoleAppender, file::FileAppender},
-    config::{Appender, Logger, Root},
-    encode::pattern::PatternEncoder,
-};
+use log4rs::append::console::Target;
 
-fn init_request_par() {
-    let limit_request = 8;
-
-    let work: Worker<i32> = Worker::new_lifo();
-    (0..limit_request).for_each(|f| {
-        work.push(f);
-    });
-
-    crate::eutils::init_work(work);
+/// 按配置创建 E-utilities 客户端, 并发数为 `ncbi.limit`, esearch 结果缓存 `ncbi.search_cache_ttl` 秒
+fn init_eutils() {
+    let client = crate::eutils::EutilsClient::from_settings(crate::settings::settings());
+    crate::eutils::init_client(client);
 }
 
-fn init_log() {
-    init_request_par();
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
-        .build();
-
-    let file = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
-        .build(format!(
-            "log/log_{}.log",
-            chrono::Utc::now().format("%y-%m-%d")
-        ))
-        .unwrap();
+/// 加载配置, 有错误时退出, 不带着错误的配置启动
+fn init_settings() {
+    match crate::settings::load() {
+        Ok(s) => {
+            if let Err(err) = crate::logging::apply(&s.log) {
+                log::error!("invalid log settings: {}", err);
+                std::process::exit(1);
+            }
+            crate::settings::init(s)
+        }
+        Err(errors) => {
+            for err in &errors {
+                log::error!("invalid settings: {}", err);
+            }
+            std::process::exit(1);
+        }
+    }
+}
 
-    let config = log4rs::Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .appender(Appender::builder().build("file", Box::new(file)))
-        .logger(Logger::builder().build("ssh_rs", LevelFilter::Off))
-        .build(
-            Root::builder()
-                .appender("stdout")
-                .appender("file")
-                .build(LevelFilter::Info),
-        )
-        .unwrap();
+pub fn init_config() {
+    init(Target::Stdout);
+}
 
-    let _ = log4rs::init_config(config).unwrap();
+/// 命令行模式下日志输出到 stderr, stdout 只输出结果
+pub fn init_cli_config() {
+    init(Target::Stderr);
 }
 
-pub fn init_config() {
-    init_log();
+fn init(target: Target) {
+    crate::logging::init(target);
+    init_settings();
+    init_eutils();
 }
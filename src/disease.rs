@@ -3,43 +3,59 @@ use std::path::Path;
 use crate::{
     eutils::{efetch, fetch_ids},
     model::GeneDisease,
-    utils::{read_target_csv, save_to_file},
-};
-use rocket::{form::Form, FromForm};
-use rocket::{
-    fs::{NamedFile, TempFile},
-    post,
+    utils::read_target_csv,
 };
 
-#[derive(FromForm)]
-pub struct UploadCsv<'r> {
-    pub file: TempFile<'r>,
-}
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{form::Form, FromForm};
+    use rocket::{
+        fs::{NamedFile, TempFile},
+        post,
+    };
+
+    use super::*;
+    use crate::auth::Ncbi;
+    use crate::error::AppError;
+    use crate::export::{save_table, xlsx::Meta};
+
+    #[derive(FromForm)]
+    pub struct UploadCsv<'r> {
+        pub file: TempFile<'r>,
+        /// csv 或 xlsx, 默认 csv
+        pub file_type: Option<&'r str>,
+    }
 
-#[post("/query/disease", data = "<req>")]
-pub async fn query_disease_gene_(req: Form<UploadCsv<'_>>) -> Option<NamedFile> {
-    let res = req.file.path();
-    if res.is_none() {
-        log::info!("summary temp file path = {:?}, failed", &res);
-        None
-    } else {
-        let p = res.unwrap();
+    #[post("/query/disease", data = "<req>")]
+    pub async fn query_disease_gene_(
+        _key: Ncbi,
+        req: Form<UploadCsv<'_>>,
+    ) -> Result<NamedFile, AppError> {
+        let p = req.file.path().ok_or_else(|| {
+            log::info!("query_disease_gene_ temp file path is none");
+            AppError::bad_request("upload file is missing")
+        })?;
 
         log::info!("query_disease_gene_ ..");
-        match query_gene_and_disease(p).await {
-            Ok(pp) => {
-                let (file_name, _) = crate::utils::get_download_path("csv").unwrap();
-                save_to_file(&file_name, &pp).unwrap();
-                NamedFile::open(&file_name).await.ok()
-            }
-            Err(err) => {
-                log::info!("summary error = {:?}", err);
-                None
-            }
-        }
+        let pp = query_gene_and_disease(p).await.map_err(|err| {
+            log::info!("summary error = {:?}", err);
+            AppError::from(err)
+        })?;
+
+        let found = pp.iter().filter(|f| f.n_pubmed_minging > Some(0)).count();
+        let meta = Meta {
+            query: QUERY_TEMPLATE,
+            counts: vec![("genes", pp.len()), ("with_papers", found)],
+        };
+        let (file_name, _, _) = save_table(&pp, req.file_type, &meta)?;
+        Ok(NamedFile::open(&file_name).await?)
     }
 }
-async fn query(g: &mut GeneDisease) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+
+pub static QUERY_TEMPLATE: &str = "(<gene>[Title/Abstract]) AND (<disease>[Title/Abstract])";
+
+pub async fn query(g: &mut GeneDisease) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let query = format!(
         "({}[Title/Abstract]) AND ({}[Title/Abstract])",
         &g.gene, &g.disease
@@ -58,7 +74,7 @@ async fn query(g: &mut GeneDisease) -> Result<(), Box<dyn std::error::Error + Se
     Ok(())
 }
 
-async fn query_gene_and_disease<P: AsRef<Path>>(
+pub async fn query_gene_and_disease<P: AsRef<Path>>(
     path: P,
 ) -> Result<Vec<GeneDisease>, Box<dyn std::error::Error + Send + Sync>> {
     let mut genes: Vec<GeneDisease> = Vec::new();
@@ -3,12 +3,14 @@ use tokio::sync::RwLock;
 
 use log::info;
 use once_cell::sync::Lazy;
-use rocket::{get, response::content};
+use rocket::get;
 use serde::{Deserialize, Serialize};
 
+use crate::auth::Auth;
+use crate::settings::data_path;
 use crate::{
+    error::{api_ok, ApiResult},
     linker::{get_link, get_pair},
-    response::response_ok,
     utils::{file_exist, read_target_csv},
 };
 
@@ -90,7 +92,7 @@ struct GenCPDs {
 fn get_dual_list() -> Vec<String> {
     let mut sub = Vec::new();
 
-    if let Ok(dir) = std::fs::read_dir("data/dual") {
+    if let Ok(dir) = std::fs::read_dir(data_path("dual")) {
         sub = dir
             .filter_map(|f| {
                 f.ok().and_then(|e| {
@@ -114,16 +116,16 @@ fn get_dual_list() -> Vec<String> {
 }
 
 #[get("/dual/list")]
-pub async fn dual_list() -> content::RawJson<String> {
+pub async fn dual_list(_key: Auth) -> ApiResult {
     info!("dual_list ..");
-    response_ok(serde_json::to_value(get_dual_list()).unwrap())
+    api_ok(&get_dual_list())
 }
 
 fn get_target_info(target: &str, left: bool) -> Vec<Motifs> {
     if target.contains("-") {
         let v: Vec<&str> = target.split("-").collect();
         let t = if left { v[0] } else { v[1] };
-        let csv = format!("data/dual/{}/{}/Motifs.csv", target, t);
+        let csv = data_path(&format!("dual/{}/{}/Motifs.csv", target, t));
         if !file_exist(&csv) {
             log::warn!("{} not exist, please check!!", &csv);
         } else {
@@ -187,7 +189,7 @@ async fn choose_from_global_map(key: String, left: &str, right: &str) -> Option<
 
 async fn get_gen_cpds(target: &str, left: &str, right: &str) -> Vec<GenCPDs> {
     if target.contains("-") && !contains_to_global_map(target.to_string()).await {
-        let csv = format!("data/dual/{}/GenCPDs/GenCPDs.csv", target);
+        let csv = data_path(&format!("dual/{}/GenCPDs/GenCPDs.csv", target));
         if !file_exist(&csv) {
             log::warn!("{} not exist, please check!!", &csv);
         } else {
@@ -210,42 +212,35 @@ async fn get_gen_cpds(target: &str, left: &str, right: &str) -> Vec<GenCPDs> {
 }
 
 #[get("/dual/<target>/<left_or_right>")]
-pub async fn dual_target_info(target: String, left_or_right: usize) -> content::RawJson<String> {
+pub async fn dual_target_info(_key: Auth, target: String, left_or_right: usize) -> ApiResult {
     info!(
         "dual_target_info .. target = {} left_or_right = {}",
         &target, left_or_right
     );
     if target.contains("-LINK") {
-        response_ok(
-            serde_json::to_value(crate::linker::get_target_info(&target, left_or_right == 0))
-                .unwrap(),
-        )
+        api_ok(&crate::linker::get_target_info(&target, left_or_right == 0))
     } else {
-        response_ok(serde_json::to_value(get_target_info(&target, left_or_right == 0)).unwrap())
+        api_ok(&get_target_info(&target, left_or_right == 0))
     }
 }
 
 #[get("/dual/pair?<target>&<link>")]
-pub async fn dual_pair(target: String, link: String) -> content::RawJson<String> {
+pub async fn dual_pair(_key: Auth, target: String, link: String) -> ApiResult {
     info!("dual_link .. target = {} link = {}", &target, link);
-    response_ok(serde_json::to_value(get_pair(&target, &link)).unwrap())
+    api_ok(&get_pair(&target, &link))
 }
 
 #[get("/dual/<target>/<left>/<right>")]
-pub async fn dual_gen_cpds(
-    target: String,
-    left: String,
-    right: String,
-) -> content::RawJson<String> {
+pub async fn dual_gen_cpds(_key: Auth, target: String, left: String, right: String) -> ApiResult {
     info!(
         "dual_gen_cpds .. target = {} left = {}, right = {}",
         &target, &left, &right
     );
 
     if target.contains("-LINK") {
-        response_ok(serde_json::to_value(get_link(&target, &left, &right)).unwrap())
+        api_ok(&get_link(&target, &left, &right))
     } else {
-        response_ok(serde_json::to_value(get_gen_cpds(&target, &left, &right).await).unwrap())
+        api_ok(&get_gen_cpds(&target, &left, &right).await)
     }
 }
 
@@ -0,0 +1,236 @@
+use std::{error::Error, fmt};
+
+use rocket::{
+    catch,
+    http::Status,
+    response::{self, content, Responder},
+    Request, Response,
+};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::response::response_ok;
+
+/// 接口的错误, 返回对应的 HTTP 状态码, 响应体仍然是 `{"error": {"msg": ...}}`, 另外加上 `code`
+#[derive(Debug, Clone)]
+pub struct AppError {
+    pub status: Status,
+    /// 稳定的错误码, 客户端用来判断错误类型
+    pub code: &'static str,
+    pub msg: String,
+}
+
+pub type ApiResult = Result<content::RawJson<String>, AppError>;
+
+impl AppError {
+    pub fn new(status: Status, code: &'static str, msg: impl Into<String>) -> Self {
+        AppError {
+            status,
+            code,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn bad_request(msg: impl Into<String>) -> Self {
+        Self::new(Status::BadRequest, "invalid_argument", msg)
+    }
+
+    pub fn not_found(msg: impl Into<String>) -> Self {
+        Self::new(Status::NotFound, "not_found", msg)
+    }
+
+    /// 任务已经在运行等状态冲突
+    pub fn conflict(msg: impl Into<String>) -> Self {
+        Self::new(Status::Conflict, "conflict", msg)
+    }
+
+    /// 缺少或无效的 API key
+    pub fn unauthorized(msg: impl Into<String>) -> Self {
+        Self::new(Status::Unauthorized, "unauthenticated", msg)
+    }
+
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self::new(Status::Forbidden, "permission_denied", msg)
+    }
+
+    pub fn rate_limited(msg: impl Into<String>) -> Self {
+        Self::new(Status::TooManyRequests, "rate_limited", msg)
+    }
+
+    /// 超过每天的配额
+    pub fn quota_exceeded(msg: impl Into<String>) -> Self {
+        Self::new(Status::TooManyRequests, "quota_exceeded", msg)
+    }
+
+    /// NCBI 或 LLM 返回了错误的结果
+    pub fn upstream(msg: impl Into<String>) -> Self {
+        Self::new(Status::BadGateway, "upstream_error", msg)
+    }
+
+    /// NCBI 或 LLM 连接失败, 超时
+    pub fn unavailable(msg: impl Into<String>) -> Self {
+        Self::new(Status::ServiceUnavailable, "upstream_unavailable", msg)
+    }
+
+    pub fn internal(msg: impl Into<String>) -> Self {
+        Self::new(Status::InternalServerError, "internal", msg)
+    }
+
+    fn body(&self) -> String {
+        let v = json!({ "error": { "msg": self.msg, "code": self.code } });
+        serde_json::to_string_pretty(&v).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.status.code, self.code, self.msg)
+    }
+}
+
+impl Error for AppError {}
+
+fn from_reqwest(err: &reqwest::Error) -> AppError {
+    match err.status() {
+        Some(s) if s.as_u16() == 429 => AppError::rate_limited(err.to_string()),
+        Some(s) if s.as_u16() == 404 => AppError::not_found(err.to_string()),
+        _ if err.is_timeout() || err.is_connect() => AppError::unavailable(err.to_string()),
+        _ => AppError::upstream(err.to_string()),
+    }
+}
+
+/// 按错误的类型分类, 其余的视为内部错误
+impl From<Box<dyn Error + Send + Sync>> for AppError {
+    fn from(err: Box<dyn Error + Send + Sync>) -> Self {
+        let err = match err.downcast::<AppError>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+            return from_reqwest(e);
+        }
+        if err.is::<std::num::ParseIntError>() || err.is::<crate::export::UnsupportedType>() {
+            return AppError::bad_request(err.to_string());
+        }
+        if err.is::<serde_json::Error>() || err.is::<serde_xml_rs::Error>() {
+            return AppError::upstream(err.to_string());
+        }
+        match err.downcast::<std::io::Error>() {
+            Ok(e) => AppError::from(*e),
+            Err(e) => AppError::internal(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => AppError::not_found(err.to_string()),
+            _ => AppError::internal(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::internal(err.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for AppError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        if self.status.code >= 500 {
+            log::warn!("{} {} => {}", req.method(), uri(req), &self);
+        }
+        Response::build_from(content::RawJson(self.body()).respond_to(req)?)
+            .status(self.status)
+            .ok()
+    }
+}
+
+/// 序列化失败时返回 500, 代替 `to_value(..).unwrap()`
+pub fn api_ok<T: Serialize>(v: &T) -> ApiResult {
+    Ok(response_ok(serde_json::to_value(v)?))
+}
+
+/// 错误信息中的 url, 隐藏 `api_key` 参数
+fn uri(req: &Request) -> String {
+    crate::logging::redact(&req.uri().to_string()).into_owned()
+}
+
+/// 请求守卫失败时保存的错误, catcher 中取出返回给客户端
+struct GuardError(Option<AppError>);
+
+pub fn guard_error(req: &Request, err: AppError) -> Status {
+    let status = err.status;
+    req.local_cache(|| GuardError(Some(err)));
+    status
+}
+
+fn stashed(req: &Request) -> Option<AppError> {
+    req.local_cache(|| GuardError(None)).0.clone()
+}
+
+#[catch(400)]
+pub fn bad_request(req: &Request) -> AppError {
+    AppError::bad_request(format!("bad request: {}", uri(req)))
+}
+
+#[catch(404)]
+pub fn not_found(req: &Request) -> AppError {
+    AppError::not_found(format!("not found: {}", uri(req)))
+}
+
+/// 鉴权, 限流等请求守卫的错误
+#[catch(default)]
+pub fn default(status: Status, req: &Request) -> AppError {
+    stashed(req).unwrap_or_else(|| {
+        AppError::new(
+            status,
+            "error",
+            format!("{}: {}", status.reason_lossy(), uri(req)),
+        )
+    })
+}
+
+/// 参数解析失败
+#[catch(422)]
+pub fn unprocessable(req: &Request) -> AppError {
+    AppError::new(
+        Status::UnprocessableEntity,
+        "invalid_argument",
+        format!("invalid request: {}", uri(req)),
+    )
+}
+
+#[catch(500)]
+pub fn internal(req: &Request) -> AppError {
+    AppError::internal(format!("internal error: {}", uri(req)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_error() {
+        let err: Box<dyn Error + Send + Sync> = "abc".parse::<usize>().unwrap_err().into();
+        let e = AppError::from(err);
+        assert_eq!(Status::BadRequest, e.status);
+        assert_eq!("invalid_argument", e.code);
+
+        let err: Box<dyn Error + Send + Sync> = AppError::not_found("job").into();
+        assert_eq!(Status::NotFound, AppError::from(err).status);
+
+        let err: Box<dyn Error + Send + Sync> = "job cancelled".into();
+        assert_eq!("internal", AppError::from(err).code);
+    }
+
+    #[test]
+    fn test_body() {
+        let v: serde_json::Value =
+            serde_json::from_str(&AppError::not_found("not found").body()).unwrap();
+        assert_eq!("not found", v["error"]["msg"]);
+        assert_eq!("not_found", v["error"]["code"]);
+    }
+}
@@ -1,210 +1,593 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use crate::{
+    cache::{PaperCache, PaperMeta},
     model::{PaperCsvResult, PubmedArticleSet},
-    utils::{file_exist, get_pmid_path_by_id, read_target_csv},
+    settings::Settings,
+    utils::{file_exist, read_target_csv},
 };
 use crossbeam_deque::Worker;
 use once_cell::sync::OnceCell;
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-static INSTANCE: OnceCell<Arc<Mutex<Worker<i32>>>> = OnceCell::new();
-// static CPU_LOCK: OnceCell<Arc<Mutex<usize>>> = OnceCell::new();
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// 服务使用的全局客户端, 未初始化时按配置创建
+static CLIENT: OnceCell<EutilsClient> = OnceCell::new();
+
+const DEFAULT_LIMIT: usize = 8;
+/// 没有指定时的数据目录, 同 `data.dir` 的默认值
+const DEFAULT_DATA_DIR: &str = "data";
+/// esearch 结果缓存的有效期, 默认 10 分钟, 0 表示不缓存
+const DEFAULT_SEARCH_TTL: u64 = 600;
+const MAX_SEARCH_CACHE: usize = 1000;
+/// 批量下载时每次 efetch 的 PMID 数
+const FETCH_BATCH: usize = 200;
+/// 每次按 DOI 检索的数量, 检索式太长时 NCBI 会拒绝
+const DOI_BATCH: usize = 50;
+
+pub fn init_client(client: EutilsClient) {
+    if CLIENT.set(client).is_err() {
+        log::warn!("eutils client already initialized");
+    }
+}
 
-pub fn init_work(work: Worker<i32>) {
-    INSTANCE
-        .set(Arc::new(Mutex::new(work)))
-        .expect("work init error");
+pub fn client() -> &'static EutilsClient {
+    CLIENT.get_or_init(|| EutilsClient::from_settings(crate::settings::settings()))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SearchResult {
-    pub esearchresult: ESearchResult,
+/// PubMed E-utilities 客户端, 包含并发限制, NCBI api_key 和 esearch 结果缓存;
+/// 下载的文献保存在 `cache` 的数据目录下, 不依赖全局配置
+pub struct EutilsClient {
+    http: reqwest::Client,
+    cache: PaperCache,
+    api_key: Option<String>,
+    work: Mutex<Worker<i32>>,
+    search_ttl: Duration,
+    search_cache: Mutex<HashMap<String, (Instant, SearchResult)>>,
+    /// 正在进行中的请求, 相同 key 的请求等待第一个完成后共享结果
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ESearchResult {
-    pub count: String,
-    pub retmax: String,
-    pub retstart: String,
-    pub idlist: Vec<String>,
-    // translationset: Vec<String>,
-    pub querytranslation: String,
+impl Default for EutilsClient {
+    fn default() -> Self {
+        EutilsClient::new(DEFAULT_LIMIT)
+    }
 }
 
-static ESEARCH: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/esearch.fcgi?";
+impl EutilsClient {
+    /// `limit` 为同时进行的请求数
+    pub fn new(limit: usize) -> Self {
+        let work: Worker<i32> = Worker::new_lifo();
+        (0..limit.max(1) as i32).for_each(|f| {
+            work.push(f);
+        });
+
+        EutilsClient {
+            http: reqwest::Client::new(),
+            cache: PaperCache::new(DEFAULT_DATA_DIR),
+            api_key: None,
+            work: Mutex::new(work),
+            search_ttl: Duration::from_secs(DEFAULT_SEARCH_TTL),
+            search_cache: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
 
-static EFETCH: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/efetch.fcgi?";
+    /// 使用 `data.dir` 作为数据目录, 写入缓存时同时更新服务的本地检索索引
+    pub fn from_settings(s: &Settings) -> Self {
+        let c = EutilsClient::new(s.ncbi.limit)
+            .with_search_ttl(Duration::from_secs(s.ncbi.search_cache_ttl))
+            .with_cache(PaperCache::new(&s.data.dir).with_index(crate::search::shared()));
+        if s.ncbi.api_key.is_empty() {
+            c
+        } else {
+            c.with_api_key(s.ncbi.api_key.expose())
+        }
+    }
 
-static PAGE_SIZE: usize = 20;
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// 文献缓存保存在 `dir/pmid` 下, 不更新本地检索索引
+    pub fn with_data_dir(self, dir: &str) -> Self {
+        self.with_cache(PaperCache::new(dir))
+    }
 
-async fn lock() -> i32 {
-    loop {
-        let s = {
-            let w = INSTANCE.get().expect("work need init first");
-            w.lock().unwrap().stealer()
+    pub fn with_cache(mut self, cache: PaperCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn cache(&self) -> &PaperCache {
+        &self.cache
+    }
+
+    pub fn with_search_ttl(mut self, ttl: Duration) -> Self {
+        self.search_ttl = ttl;
+        self
+    }
+
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    async fn lock(&self) -> i32 {
+        loop {
+            let s = self.work.lock().unwrap().stealer();
+
+            if let crossbeam_deque::Steal::Success(id) = s.steal() {
+                return id;
+            } else {
+                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            }
+        }
+    }
+
+    fn unlock(&self, id: i32) {
+        self.work.lock().unwrap().push(id);
+    }
+
+    fn search_cache_get(&self, key: &str) -> Option<SearchResult> {
+        let map = self.search_cache.lock().unwrap();
+        match map.get(key) {
+            Some((t, r)) if t.elapsed() < self.search_ttl => Some(r.clone()),
+            _ => None,
+        }
+    }
+
+    fn search_cache_put(&self, key: String, result: &SearchResult) {
+        let ttl = self.search_ttl;
+        if ttl.is_zero() {
+            return;
+        }
+
+        let mut map = self.search_cache.lock().unwrap();
+        if map.len() >= MAX_SEARCH_CACHE {
+            map.retain(|_, (t, _)| t.elapsed() < ttl);
+        }
+        // 都没过期时淘汰最早的一条
+        if map.len() >= MAX_SEARCH_CACHE && !map.contains_key(&key) {
+            let oldest = map
+                .iter()
+                .min_by_key(|(_, (t, _))| *t)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                map.remove(&k);
+            }
+        }
+        map.insert(key, (Instant::now(), result.clone()));
+    }
+
+    async fn single_flight<T>(&self, key: &str, f: impl Future<Output = T>) -> T {
+        let m = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        let result = {
+            let _guard = m.lock().await;
+            f.await
         };
 
-        if let crossbeam_deque::Steal::Success(id) = s.steal() {
-            return id;
-        } else {
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        let mut map = self.in_flight.lock().unwrap();
+        // 只剩 map 和自己持有时才移除
+        if Arc::strong_count(&m) <= 2 {
+            map.remove(key);
         }
+
+        result
     }
-}
 
-fn unlock(id: i32) {
-    let w = INSTANCE.get().expect("work need init first");
-    w.lock().unwrap().push(id);
-}
+    /// 配置了 NCBI api_key 时附加到请求参数, 日志中的 url 不带 key
+    fn with_api_key_param(&self, url: &str) -> String {
+        match &self.api_key {
+            Some(key) => format!("{}&api_key={}", url, key),
+            None => url.to_string(),
+        }
+    }
 
-pub async fn esearch2(
-    db: &str,
-    query: &str,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-    let retstart = page.unwrap_or(0);
-    let page_size = page_size.unwrap_or(10);
+    /// 在并发限制内请求 NCBI, 记录排队时间, 耗时和返回的状态码
+    async fn get_text(&self, kind: &str, url: &str) -> std::result::Result<String, reqwest::Error> {
+        let wait = Instant::now();
+        let id = self.lock().await;
+        crate::metrics::LIMITER_WAIT.observe(wait.elapsed().as_secs_f64());
+        log::info!("request_task_id = {},  {}, url = {}", id, kind, url);
+
+        let start = Instant::now();
+        let resp = match self
+            .http
+            .get(self.with_api_key_param(url))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+        {
+            Ok(r) => r.text().await.map_err(|e| e.without_url()),
+            Err(e) => Err(e.without_url()),
+        };
+        self.unlock(id);
+
+        let status = match &resp {
+            Ok(_) => "200".to_string(),
+            Err(e) => e
+                .status()
+                .map(|s| s.as_u16().to_string())
+                .unwrap_or_else(|| "error".to_string()),
+        };
+        crate::metrics::EUTILS_REQUESTS
+            .with_label_values(&[kind, &status])
+            .inc();
+        crate::metrics::EUTILS_LATENCY
+            .with_label_values(&[kind])
+            .observe(start.elapsed().as_secs_f64());
+        resp
+    }
 
-    let mut ids: Vec<String> = Vec::new();
-    let resp = fetch_ids(db, query, retstart, page_size).await?;
+    /// 检索并下载一页, 返回 count, data, cur_page 等
+    pub async fn esearch2(
+        &self,
+        db: &str,
+        query: &str,
+        page: Option<usize>,
+        page_size: Option<usize>,
+    ) -> Result<serde_json::Value> {
+        let retstart = page.unwrap_or(0);
+        let page_size = page_size.unwrap_or(10);
 
-    ids.extend(resp.esearchresult.idlist.iter().cloned());
+        let mut ids: Vec<String> = Vec::new();
+        let resp = self.fetch_ids(db, query, retstart, page_size).await?;
 
-    log::info!("ids len={},  data = {:?}", ids.len(), ids);
+        ids.extend(resp.esearchresult.idlist.iter().cloned());
 
-    let res = efetch(db, &ids).await?;
+        log::info!("ids len={},  data = {:?}", ids.len(), ids);
 
-    Ok(serde_json::json!({
-        "count": resp.esearchresult.count.parse::<i32>().unwrap_or(-1),
-        "data": res,
-        "cur_page": retstart,
-        "page_size": page_size,
-        "query_text": resp.esearchresult.querytranslation
-    }))
-}
+        let res = self.efetch(db, &ids).await?;
 
-pub async fn fetch_ids(
-    db: &str,
-    query: &str,
-    retstart: usize,
-    page_size: usize,
-) -> Result<SearchResult, Box<dyn std::error::Error + Send + Sync>> {
-    let start = if retstart == 0 {
-        retstart
-    } else {
-        retstart * page_size
-    };
+        Ok(serde_json::json!({
+            "count": resp.esearchresult.count.parse::<i32>().unwrap_or(-1),
+            "data": res,
+            "cur_page": retstart,
+            "page_size": page_size,
+            "query_text": resp.esearchresult.querytranslation
+        }))
+    }
 
-    let url = format!(
-            "{}db={}&term={}&retmode=json&api_key=f6bc4f0e30a718d326ef842054d988ecdd08&sort=date&retstart={}&retmax={}",
-            ESEARCH,
-            &db,
-            &url_encode(&query),
+    /// esearch, 只返回 PMID 列表, 结果在 `search_ttl` 内缓存
+    pub async fn fetch_ids(
+        &self,
+        db: &str,
+        query: &str,
+        retstart: usize,
+        page_size: usize,
+    ) -> Result<SearchResult> {
+        let start = page_offset(retstart, page_size);
+
+        let key = format!(
+            "{}|{}|date|{}|{}",
+            db,
+            normalize_query(query),
             start,
             page_size
         );
+        if let Some(r) = self.search_cache_get(&key) {
+            log::info!("esearch cache hit, key = {}", &key);
+            return Ok(r);
+        }
 
-    let id = lock().await;
-    log::info!("request_task_id = {},  esearch, url = {}", id, &url);
-    let resp = reqwest::get(&url).await?.json::<SearchResult>().await?;
-    unlock(id);
+        self.single_flight(&key, async {
+            // 等待期间可能已经由其他请求完成
+            if let Some(r) = self.search_cache_get(&key) {
+                return Ok(r);
+            }
 
-    Ok(resp)
-}
+            let url = format!(
+                "{}db={}&term={}&retmode=json&sort=date&retstart={}&retmax={}",
+                ESEARCH,
+                &db,
+                &url_encode(query),
+                start,
+                page_size
+            );
 
-pub async fn esearch3(
-    db: &str,
-    query: &str,
-    page: Option<usize>,
-    page_size: Option<usize>,
-) -> Result<Vec<PaperCsvResult>, Box<dyn std::error::Error + Send + Sync>> {
-    let retstart = page.unwrap_or(0);
-    let page_size = page_size.unwrap_or(10);
+            let resp =
+                serde_json::from_str::<SearchResult>(&self.get_text("esearch", &url).await?)?;
+            self.search_cache_put(key.clone(), &resp);
+            Ok(resp)
+        })
+        .await
+    }
 
-    let mut ids: Vec<String> = Vec::new();
-    let resp = fetch_ids(db, query, retstart, page_size).await?;
-    ids.extend(resp.esearchresult.idlist.iter().cloned());
+    /// 检索并下载一页
+    pub async fn esearch3(
+        &self,
+        db: &str,
+        query: &str,
+        page: Option<usize>,
+        page_size: Option<usize>,
+    ) -> Result<Vec<PaperCsvResult>> {
+        let retstart = page.unwrap_or(0);
+        let page_size = page_size.unwrap_or(10);
+
+        let mut ids: Vec<String> = Vec::new();
+        let resp = self.fetch_ids(db, query, retstart, page_size).await?;
+        ids.extend(resp.esearchresult.idlist.iter().cloned());
 
-    log::info!("ids len={},  data = {:?}", ids.len(), ids);
+        log::info!("ids len={},  data = {:?}", ids.len(), ids);
 
-    let res = efetch(db, &ids).await?;
+        self.efetch(db, &ids).await
+    }
 
-    Ok(res)
-}
+    /// 检索并下载全部结果
+    pub async fn esearch(&self, db: &str, query: &str) -> Result<Vec<PaperCsvResult>> {
+        let mut retstart = 0;
+        let page_size = PAGE_SIZE * 2;
 
-pub async fn esearch(
-    db: &str,
-    query: &str,
-) -> Result<Vec<PaperCsvResult>, Box<dyn std::error::Error + Send + Sync>> {
-    let mut retstart = 0;
-    let page_size = PAGE_SIZE * 2;
+        let mut ids: Vec<String> = Vec::new();
 
-    let mut ids: Vec<String> = Vec::new();
+        loop {
+            let resp = self.fetch_ids(db, query, retstart, page_size).await?;
+            ids.extend(resp.esearchresult.idlist.iter().cloned());
 
-    loop {
-        let resp = fetch_ids(db, query, retstart, page_size).await?;
-        ids.extend(resp.esearchresult.idlist.iter().cloned());
-        // log::info!("{:#?}", resp);
+            let count = resp.esearchresult.count.parse::<usize>()?;
+            let downloaded = resp.esearchresult.idlist.len() + retstart;
 
-        let count = resp.esearchresult.count.parse::<usize>().unwrap();
-        let downloaded = resp.esearchresult.idlist.len() + retstart;
+            if downloaded >= count || resp.esearchresult.idlist.is_empty() {
+                break;
+            }
 
-        if downloaded >= count || resp.esearchresult.idlist.is_empty() {
-            break;
+            retstart += page_size;
         }
 
-        // log::info!("{:#?}", resp);
-        // tokio::time::sleep(Duration::from_millis(500)).await;
+        log::info!("ids len={},  data = {:?}", ids.len(), ids);
 
-        retstart += page_size;
+        self.efetch(db, &ids).await
     }
 
-    log::info!("ids len={},  data = {:?}", ids.len(), ids);
+    /// 按 PMID 读取文献, 本地缓存没有时从 NCBI 下载
+    pub async fn efetch(&self, db: &str, ids: &[String]) -> Result<Vec<PaperCsvResult>> {
+        let mut v: Vec<PaperCsvResult> = Vec::new();
+        for id in ids {
+            let pmid = id.parse::<usize>()?;
+            let path = self.cache.csv_path(pmid);
+            if !file_exist(&path) {
+                crate::cache::record_miss();
+                // 同一个 pmid 只下载一次, 避免同时写同一个 csv
+                let p = self
+                    .single_flight(&format!("{}|efetch|{}", db, pmid), self.download(db, id))
+                    .await?;
+                v.extend(p);
+            } else {
+                crate::cache::record_hit();
+
+                let result = read_target_csv(&path, b',', &mut v);
+                if result.is_err() {
+                    log::warn!("path = {},  csv parse error = {:?}", &path, result);
+                    self.cache.mark_corrupt(pmid);
+                }
+            }
+        }
 
-    let res = efetch(db, &ids).await?;
+        log::info!("PaperCsvResult len = {:?}", v.len());
 
-    Ok(res)
-}
+        Ok(v)
+    }
 
-pub async fn efetch(
-    db: &str,
-    ids: &Vec<String>,
-) -> Result<Vec<PaperCsvResult>, Box<dyn std::error::Error + Send + Sync>> {
-    let mut v: Vec<PaperCsvResult> = Vec::new();
-    for id in ids {
+    async fn download(&self, db: &str, id: &str) -> Result<Vec<PaperCsvResult>> {
         let pmid = id.parse::<usize>()?;
-        let path = get_pmid_path_by_id(pmid);
-        if !file_exist(&path) {
-            let url = format!(
-            "{}db={}&api_key=f6bc4f0e30a718d326ef842054d988ecdd08&retmode=text&rettype=xml&sort=date&id={}",
+        let path = self.cache.csv_path(pmid);
+        if file_exist(&path) {
+            let mut v: Vec<PaperCsvResult> = Vec::new();
+            read_target_csv(&path, b',', &mut v)?;
+            return Ok(v);
+        }
+
+        let url = format!(
+            "{}db={}&retmode=text&rettype=xml&sort=date&id={}",
             EFETCH, &db, id
         );
-            log::info!("start download {}", pmid);
-
-            let id = lock().await;
-            log::info!("request_task_id = {},  efetch, url = {}", id, &url);
-            let resp = reqwest::get(&url).await?.text().await?;
-            let p = parse_xml(&resp)?;
-            v.extend(p);
-            unlock(id);
-            log::info!("downloaded {} end", pmid);
-        } else {
-            // log::info!("pmid = {} already downloaded", pmid);
+        log::info!("start download {}", pmid);
 
-            let result = read_target_csv(&path, b',', &mut v);
-            if result.is_err() {
-                log::warn!("path = {},  csv parse error = {:?}", &path, result);
-                let _ = std::fs::remove_file(&path);
+        let p = parse_xml(&self.cache, &self.get_text("efetch", &url).await?)?;
+        log::info!("downloaded {} end", pmid);
+
+        Ok(p)
+    }
+
+    /// 批量读取, 缓存没有的每 `FETCH_BATCH` 个合并为一次 efetch;
+    /// 返回 PMID 对应的文献, PubMed 上不存在的 PMID 不在结果中
+    pub async fn efetch_batch(
+        &self,
+        db: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, PaperCsvResult>> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for id in ids {
+            let pmid = id.parse::<usize>()?;
+            if found.contains_key(id) || missing.contains(id) {
+                continue;
+            }
+            match self.cache.load_paper(pmid) {
+                Some(p) => {
+                    crate::cache::record_hit();
+                    found.insert(id.clone(), p);
+                }
+                None => {
+                    crate::cache::record_miss();
+                    missing.push(id.clone());
+                }
             }
         }
+
+        let tasks = missing.chunks(FETCH_BATCH).map(|chunk| async move {
+            let url = format!(
+                "{}db={}&retmode=text&rettype=xml&id={}",
+                EFETCH,
+                &db,
+                chunk.join(",")
+            );
+            log::info!("efetch batch {} ids", chunk.len());
+            // 解析失败的文献跳过, 由调用方作为没有找到处理
+            let xml = self.get_text("efetch", &url).await?;
+            Result::Ok(store_all(&self.cache, parse_articles_lenient(&xml)))
+        });
+        for p in futures::future::try_join_all(tasks)
+            .await?
+            .into_iter()
+            .flatten()
+        {
+            found.insert(p.pmid.clone(), p);
+        }
+
+        Ok(found)
+    }
+
+    /// 按 DOI 检索后批量读取, 返回小写的 DOI 对应的文献
+    pub async fn efetch_dois(
+        &self,
+        db: &str,
+        dois: &[String],
+    ) -> Result<HashMap<String, PaperCsvResult>> {
+        let mut ids = Vec::new();
+        for chunk in dois.chunks(DOI_BATCH) {
+            let term = chunk
+                .iter()
+                .map(|f| format!("\"{}\"[DOI]", f))
+                .collect::<Vec<String>>()
+                .join(" OR ");
+            let url = format!(
+                "{}db={}&term={}&retmode=json&retmax={}",
+                ESEARCH,
+                &db,
+                &url_encode(&term),
+                chunk.len() * 2
+            );
+            let resp =
+                serde_json::from_str::<SearchResult>(&self.get_text("esearch", &url).await?)?;
+            ids.extend(resp.esearchresult.idlist);
+        }
+
+        Ok(self
+            .efetch_batch(db, &ids)
+            .await?
+            .into_values()
+            .filter(|f| !f.doi.is_empty())
+            .map(|f| (f.doi.to_lowercase(), f))
+            .collect())
+    }
+
+    /// 重新下载 PubMed 上已修改的文献, 覆盖本地缓存, 返回更新的数量
+    pub async fn refetch(&self, db: &str, ids: &[String]) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let url = format!(
+            "{}db={}&retmode=text&rettype=xml&id={}",
+            EFETCH,
+            &db,
+            ids.join(",")
+        );
+
+        log::info!("refetch {} ids", ids.len());
+        let papers = parse_articles_lenient(&self.get_text("refetch", &url).await?);
+        for (paper, meta) in &papers {
+            self.cache.store(paper, meta, true)?;
+        }
+
+        Ok(papers.len())
     }
+}
+
+fn normalize_query(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct SearchResult {
+    pub esearchresult: ESearchResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ESearchResult {
+    pub count: String,
+    pub retmax: String,
+    pub retstart: String,
+    pub idlist: Vec<String>,
+    // translationset: Vec<String>,
+    pub querytranslation: String,
+}
+
+static ESEARCH: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/esearch.fcgi?";
 
-    log::info!("PaperCsvResult len = {:?}", v.len());
+static EFETCH: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/efetch.fcgi?";
+
+static PAGE_SIZE: usize = 20;
+
+/// `fetch_ids` 的 `retstart` 为页码, 返回实际的 esearch 偏移
+pub fn page_offset(page: usize, page_size: usize) -> usize {
+    page * page_size
+}
+
+pub async fn esearch2(
+    db: &str,
+    query: &str,
+    page: Option<usize>,
+    page_size: Option<usize>,
+) -> Result<serde_json::Value> {
+    client().esearch2(db, query, page, page_size).await
+}
+
+pub async fn fetch_ids(
+    db: &str,
+    query: &str,
+    retstart: usize,
+    page_size: usize,
+) -> Result<SearchResult> {
+    client().fetch_ids(db, query, retstart, page_size).await
+}
+
+pub async fn esearch3(
+    db: &str,
+    query: &str,
+    page: Option<usize>,
+    page_size: Option<usize>,
+) -> Result<Vec<PaperCsvResult>> {
+    client().esearch3(db, query, page, page_size).await
+}
+
+pub async fn esearch(db: &str, query: &str) -> Result<Vec<PaperCsvResult>> {
+    client().esearch(db, query).await
+}
+
+pub async fn efetch(db: &str, ids: &[String]) -> Result<Vec<PaperCsvResult>> {
+    client().efetch(db, ids).await
+}
 
-    Ok(v)
+pub async fn efetch_batch(db: &str, ids: &[String]) -> Result<HashMap<String, PaperCsvResult>> {
+    client().efetch_batch(db, ids).await
+}
+
+pub async fn efetch_dois(db: &str, dois: &[String]) -> Result<HashMap<String, PaperCsvResult>> {
+    client().efetch_dois(db, dois).await
+}
+
+/// 重新下载 PubMed 上已修改的文献, 覆盖本地缓存, 返回更新的数量
+pub async fn refetch(db: &str, ids: &[String]) -> Result<usize> {
+    client().refetch(db, ids).await
 }
 
 fn remove_str(input: &str, key: &str) -> String {
@@ -221,7 +604,48 @@ fn remove_str(input: &str, key: &str) -> String {
     output.to_string()
 }
 
-fn parse_xml(xml: &str) -> Result<Vec<PaperCsvResult>, Box<dyn std::error::Error + Send + Sync>> {
+fn parse_xml(cache: &PaperCache, xml: &str) -> Result<Vec<PaperCsvResult>> {
+    Ok(store_all(cache, parse_articles(xml)?))
+}
+
+/// 写入缓存, 已有的不覆盖
+fn store_all(cache: &PaperCache, v: Vec<(PaperCsvResult, PaperMeta)>) -> Vec<PaperCsvResult> {
+    v.into_iter()
+        .map(|(paper, meta)| {
+            let _ = cache.store(&paper, &meta, false);
+            paper
+        })
+        .collect()
+}
+
+/// 同 `parse_articles`, 整体解析失败时逐篇解析, 跳过解析失败的文献
+pub fn parse_articles_lenient(xml: &str) -> Vec<(PaperCsvResult, PaperMeta)> {
+    let err = match parse_articles(xml) {
+        Ok(v) => return v,
+        Err(err) => err,
+    };
+    log::warn!("parse efetch xml error = {:?}, parse one by one", err);
+
+    let article = Regex::new(r"(?s)<PubmedArticle(?:\s[^>]*)?>.*?</PubmedArticle>").unwrap();
+    let pmid = Regex::new(r"<PMID[^>]*>(\d+)</PMID>").unwrap();
+    article
+        .find_iter(xml)
+        .flat_map(|m| {
+            let one = format!("<PubmedArticleSet>{}</PubmedArticleSet>", m.as_str());
+            parse_articles(&one).unwrap_or_else(|err| {
+                let id = pmid
+                    .captures(m.as_str())
+                    .and_then(|c| c.get(1))
+                    .map_or("", |f| f.as_str());
+                log::warn!("skip article pmid = {}, parse error = {:?}", id, err);
+                vec![]
+            })
+        })
+        .collect()
+}
+
+/// 解析 efetch 返回的 xml, 同时给出每篇文献的缓存元信息 (修改日期, MeSH)
+pub fn parse_articles(xml: &str) -> Result<Vec<(PaperCsvResult, PaperMeta)>> {
     let mut text = remove_str(xml, "AbstractText");
     text = remove_str(&text, "ArticleTitle");
 
@@ -351,10 +775,43 @@ fn parse_xml(xml: &str) -> Result<Vec<PaperCsvResult>, Box<dyn std::error::Error
                 }
             }
 
-            let _ = paper.save_csv();
-            paper
+            let modified_at = f
+                .medline_citation
+                .date_revised
+                .as_ref()
+                .map(|d| d.to_date_str())
+                .unwrap_or_default();
+            let mut meta = PaperMeta::new(&paper.pmid, &modified_at);
+            if let Some(list) = f.medline_citation.mesh_heading_list.as_ref() {
+                meta.mesh = list
+                    .mesh_headings
+                    .iter()
+                    .map(|m| m.descriptor_name.value.clone())
+                    .collect();
+            }
+            let article = &f.medline_citation.article;
+            meta.authors = article.author_list.authors.clone();
+            meta.volume = article
+                .journal
+                .journal_issue
+                .volume
+                .clone()
+                .unwrap_or_default();
+            meta.issue = article
+                .journal
+                .journal_issue
+                .issue
+                .clone()
+                .unwrap_or_default();
+            meta.pages = article
+                .pagination
+                .as_ref()
+                .and_then(|p| p.medline_pgn.clone())
+                .unwrap_or_default();
+
+            (paper, meta)
         })
-        .collect::<Vec<PaperCsvResult>>();
+        .collect::<Vec<(PaperCsvResult, PaperMeta)>>();
 
     Ok(res)
 }
@@ -381,6 +838,88 @@ fn url_encode(s: &str) -> String {
 mod tests {
     use crate::eutils::*;
 
+    #[test]
+    fn test_normalize_query() {
+        assert_eq!(
+            "(TYK2[Title/Abstract]) AND (SLE[Title/Abstract])",
+            normalize_query("  (TYK2[Title/Abstract])\n AND   (SLE[Title/Abstract]) ")
+        );
+    }
+
+    #[test]
+    fn test_client_from_settings() {
+        let mut s = Settings::default();
+        s.ncbi.limit = 2;
+        s.ncbi.search_cache_ttl = 0;
+        let client = EutilsClient::from_settings(&s);
+        assert_eq!("u?db=pubmed", client.with_api_key_param("u?db=pubmed"));
+        assert_eq!(Duration::ZERO, client.search_ttl);
+        assert_eq!(2, client.work.lock().unwrap().len());
+
+        let client = EutilsClient::new(0).with_api_key("k");
+        assert_eq!(
+            "u?db=pubmed&api_key=k",
+            client.with_api_key_param("u?db=pubmed")
+        );
+        assert_eq!(1, client.work.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_search_cache_cap() {
+        let client = EutilsClient::new(1).with_search_ttl(Duration::from_secs(3600));
+        let result = SearchResult {
+            esearchresult: ESearchResult {
+                count: "0".to_string(),
+                retmax: "0".to_string(),
+                retstart: "0".to_string(),
+                idlist: vec![],
+                querytranslation: String::new(),
+            },
+        };
+        for i in 0..MAX_SEARCH_CACHE + 10 {
+            client.search_cache_put(format!("q{}", i), &result);
+        }
+        assert_eq!(MAX_SEARCH_CACHE, client.search_cache.lock().unwrap().len());
+        assert!(client
+            .search_cache_get(&format!("q{}", MAX_SEARCH_CACHE + 9))
+            .is_some());
+
+        // 覆盖已有 key 不淘汰别的
+        let last = format!("q{}", MAX_SEARCH_CACHE + 9);
+        client.search_cache_put(last, &result);
+        assert!(client.search_cache_get("q10").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let client = Arc::new(EutilsClient::new(1));
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let mut tasks = vec![];
+        for _ in 0..4 {
+            let counter = counter.clone();
+            let client = client.clone();
+            tasks.push(tokio::spawn(async move {
+                client
+                    .single_flight("test_single_flight", async {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                    })
+                    .await
+            }));
+        }
+
+        let mut result = vec![];
+        for task in tasks {
+            result.push(task.await.unwrap());
+        }
+        result.sort();
+
+        // 同一个 key 串行执行, 不会同时进入
+        assert_eq!(vec![0, 1, 2, 3], result);
+        assert!(client.in_flight.lock().unwrap().is_empty());
+    }
+
     #[test]
     fn test_urlencode() {
         let s = "(Ankylosing spondylitis[Title/Abstract]) AND (\"2023/1/10\"[Date - Publication] : \"2023/2/10\"[Date - Publication])";
@@ -422,11 +961,7 @@ mod tests {
         log::info!("result = {:?}", result);
     }
 
-    #[test]
-    fn test_parse_xml() {
-        crate::config::init_config();
-        let str = r#"
-        <PubmedArticleSet>
+    const ARTICLE: &str = r#"
     <PubmedArticle>
         <MedlineCitation Status="MEDLINE" Owner="NLM" IndexingMethod="Automated">
             <PMID Version="1">36765305</PMID>
@@ -500,9 +1035,26 @@ mod tests {
             </ArticleIdList>
         </PubmedData>
     </PubmedArticle>
-</PubmedArticleSet>"#;
+"#;
 
-        let p = parse_xml(str);
+    #[test]
+    fn test_parse_articles_lenient() {
+        let bad =
+            "<PubmedArticle><MedlineCitation><PMID>1</PMID></MedlineCitation></PubmedArticle>";
+        let xml = format!("<PubmedArticleSet>{}{}</PubmedArticleSet>", bad, ARTICLE);
+        assert!(parse_articles(&xml).is_err());
+
+        let v = parse_articles_lenient(&xml);
+        assert_eq!(1, v.len());
+        assert_eq!("36765305", v[0].0.pmid);
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        crate::config::init_config();
+        let str = format!("<PubmedArticleSet>{}</PubmedArticleSet>", ARTICLE);
+
+        let p = parse_xml(crate::cache::global(), &str);
 
         match p {
             Ok(q) => log::info!("xml struct = {}", serde_json::to_string_pretty(&q).unwrap()),
@@ -510,6 +1062,28 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_data_dir() {
+        let dir = std::env::temp_dir().join(format!("rust_eutils_test_{}", uuid::Uuid::new_v4()));
+        let client = EutilsClient::new(1).with_data_dir(dir.to_str().unwrap());
+        assert!(client
+            .cache()
+            .csv_path(36765305)
+            .starts_with(dir.to_str().unwrap()));
+
+        let str = format!("<PubmedArticleSet>{}</PubmedArticleSet>", ARTICLE);
+        parse_xml(client.cache(), &str).unwrap();
+        assert!(file_exist(&client.cache().csv_path(36765305)));
+
+        // 已缓存, 不需要请求 PubMed
+        let v = client
+            .efetch("pubmed", &["36765305".to_string()])
+            .await
+            .unwrap();
+        assert_eq!("36765305", v[0].pmid);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[tokio::test]
     async fn test_par() {
         crate::config::init_config();
@@ -527,10 +1101,10 @@ mod tests {
         ];
 
         async fn fetch_url(url: &str) {
-            let id = lock().await;
+            let id = client().lock().await;
             log::info!("request_task_id = {},  esearch, url = {}", id, &url);
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-            unlock(id);
+            client().unlock(id);
         }
 
         let mut tasks = vec![];
@@ -0,0 +1,228 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rocket::{
+    form::Form,
+    get, post,
+    response::stream::{Event, EventStream},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError, Sender};
+
+use crate::auth::{ApiKey, Auth, Llm, Ncbi};
+use crate::{
+    disease::api::UploadCsv,
+    jobs::{get_job, get_owned_job, Job, JobStatus},
+    openai::api::UploadQuery,
+};
+
+/// 每个任务缓存的事件数量, 客户端太慢时丢弃旧的事件
+const CHANNEL_SIZE: usize = 256;
+
+/// 批量任务中每一条的进度, `item` 为 PMID 或 `gene|disease`
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    Started {
+        item: String,
+        index: usize,
+        total: usize,
+    },
+    Fetched {
+        item: String,
+        index: usize,
+        data: serde_json::Value,
+    },
+    Summarized {
+        item: String,
+        index: usize,
+        summary: String,
+    },
+    Failed {
+        item: String,
+        index: usize,
+        error: String,
+    },
+    /// 结束事件, 成功时 `result` 中有下载 id
+    Completed {
+        status: JobStatus,
+        result: Option<serde_json::Value>,
+        error: Option<String>,
+    },
+}
+
+impl JobEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Started { .. } => "started",
+            JobEvent::Fetched { .. } => "fetched",
+            JobEvent::Summarized { .. } => "summarized",
+            JobEvent::Failed { .. } => "failed",
+            JobEvent::Completed { .. } => "completed",
+        }
+    }
+
+    pub fn completed(job: &Job) -> Self {
+        JobEvent::Completed {
+            status: job.status,
+            result: job.result.clone(),
+            error: job.error.clone(),
+        }
+    }
+}
+
+static CHANNELS: Lazy<Mutex<HashMap<String, Sender<JobEvent>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 订阅者, 释放时没有其他订阅者则删除通道, 避免已结束的任务一直占用
+struct Subscription {
+    id: String,
+    rx: Option<broadcast::Receiver<JobEvent>>,
+}
+
+impl Subscription {
+    async fn recv(&mut self) -> Result<JobEvent, RecvError> {
+        match self.rx.as_mut() {
+            Some(rx) => rx.recv().await,
+            None => Err(RecvError::Closed),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut map = CHANNELS.lock().unwrap();
+        drop(self.rx.take());
+        if map.get(&self.id).is_some_and(|tx| tx.receiver_count() == 0) {
+            map.remove(&self.id);
+        }
+    }
+}
+
+fn subscribe(id: &str) -> Subscription {
+    let rx = CHANNELS
+        .lock()
+        .unwrap()
+        .entry(id.to_string())
+        .or_insert_with(|| broadcast::channel(CHANNEL_SIZE).0)
+        .subscribe();
+    Subscription {
+        id: id.to_string(),
+        rx: Some(rx),
+    }
+}
+
+/// 没有订阅者时直接丢弃; 结束事件发出后关闭通道
+pub fn emit(id: &str, event: JobEvent) {
+    let mut map = CHANNELS.lock().unwrap();
+    if let Some(tx) = map.get(id) {
+        let _ = tx.send(event.clone());
+    }
+    if matches!(event, JobEvent::Completed { .. }) {
+        map.remove(id);
+    }
+}
+
+/// 先发送一次当前的任务状态, 再转发任务的事件直到结束
+fn job_stream(id: Result<String, String>, key: &ApiKey) -> EventStream![] {
+    // 先订阅再读状态, 避免错过中间结束的事件
+    let rx = id
+        .as_ref()
+        .ok()
+        .and_then(|id| get_owned_job(id, key).map(|_| subscribe(id)));
+    let job = id.as_ref().ok().and_then(|id| get_owned_job(id, key));
+    // 已经结束的任务直接发送结束事件, 不再保留订阅
+    let rx = rx.filter(|_| job.as_ref().is_some_and(|f| !f.status.finished()));
+
+    EventStream! {
+        match (job, rx) {
+            (Some(job), None) if job.status.finished() => {
+                yield Event::json(&job).event("status");
+                yield Event::json(&JobEvent::completed(&job)).event("completed");
+            }
+            (Some(job), Some(mut rx)) => {
+                yield Event::json(&job).event("status");
+                let mut finished = false;
+
+                while !finished {
+                    match rx.recv().await {
+                        Ok(ev) => {
+                            finished = matches!(ev, JobEvent::Completed { .. });
+                            yield Event::json(&ev).event(ev.name());
+                        }
+                        Err(RecvError::Lagged(n)) => {
+                            log::info!("job {} events lagged {}", &job.id, n);
+                        }
+                        Err(RecvError::Closed) => {
+                            if let Some(job) = get_job(&job.id) {
+                                yield Event::json(&JobEvent::completed(&job)).event("completed");
+                            }
+                            finished = true;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let msg = match id {
+                    Ok(id) => format!("job not found: {}", id),
+                    Err(err) => err,
+                };
+                yield Event::json(&serde_json::json!({ "msg": msg })).event("error");
+            }
+        }
+    }
+}
+
+/// 浏览器 `EventSource` 订阅任务进度
+#[get("/jobs/<id>/events")]
+pub async fn job_events(key: Auth, id: String) -> EventStream![] {
+    job_stream(Ok(id), &key.0)
+}
+
+/// 提交 `summary_with_query` 任务并在同一个请求中返回进度
+#[post("/openai/summary_with_query/events", data = "<req>")]
+pub async fn summary_with_query_events(key: Llm, req: Form<UploadQuery<'_>>) -> EventStream![] {
+    let job = crate::jobs::submit(crate::jobs::summary_query_job(&req), &key.0);
+    job_stream(Ok(job.id), &key.0)
+}
+
+/// 提交 gene-disease 任务并在同一个请求中返回进度
+#[post("/query/disease/events", data = "<req>")]
+pub async fn disease_events(key: Ncbi, mut req: Form<UploadCsv<'_>>) -> EventStream![] {
+    let id = crate::jobs::disease_job(&mut req)
+        .await
+        .map(|job| crate::jobs::submit(job, &key.0).id)
+        .map_err(|err| err.to_string());
+    job_stream(id, &key.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let ev = JobEvent::Summarized {
+            item: "1".to_string(),
+            index: 0,
+            summary: "yes".to_string(),
+        };
+        assert_eq!("summarized", ev.name());
+        assert_eq!(
+            r#"{"event":"summarized","item":"1","index":0,"summary":"yes"}"#,
+            serde_json::to_string(&ev).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_subscription_drop() {
+        let id = "test_subscription_drop";
+        let a = subscribe(id);
+        let b = subscribe(id);
+        drop(a);
+        assert!(CHANNELS.lock().unwrap().contains_key(id));
+        drop(b);
+        assert!(!CHANNELS.lock().unwrap().contains_key(id));
+    }
+}
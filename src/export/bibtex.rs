@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use super::{month_abbr, split_pages, Record};
+
+/// 转义 LaTeX 特殊字符
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+    {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '{' | '}' | '$' | '&' | '%' | '#' | '_' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 引用 key 只保留 ascii 字母和数字, 常见的带重音字母转成对应的字母
+fn ascii_fold(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        let f = match c {
+            'à'..='å' | 'À'..='Å' => "a",
+            'ç' | 'Ç' => "c",
+            'è'..='ë' | 'È'..='Ë' => "e",
+            'ì'..='ï' | 'Ì'..='Ï' => "i",
+            'ñ' | 'Ñ' => "n",
+            'ò'..='ö' | 'ø' | 'Ò'..='Ö' | 'Ø' => "o",
+            'ù'..='ü' | 'Ù'..='Ü' => "u",
+            'ý' | 'ÿ' | 'Ý' => "y",
+            'ß' => "ss",
+            c if c.is_ascii_alphanumeric() => {
+                out.push(c.to_ascii_lowercase());
+                continue;
+            }
+            _ => "",
+        };
+        out.push_str(f);
+    }
+    out
+}
+
+static STOP_WORDS: [&str; 8] = ["a", "an", "the", "of", "on", "in", "for", "and"];
+
+/// `chen2020deep` 格式, 重复时加 a, b, c...
+fn cite_key(r: &Record, used: &mut HashSet<String>) -> String {
+    let family = r
+        .authors()
+        .first()
+        .map(|a| ascii_fold(&a.family))
+        .unwrap_or_default();
+    let word = r
+        .paper
+        .title
+        .split_whitespace()
+        .map(ascii_fold)
+        .find(|w| !w.is_empty() && !STOP_WORDS.contains(&w.as_str()))
+        .unwrap_or_default();
+
+    let mut base = format!("{}{}{}", family, ascii_fold(r.year()), word);
+    if base.is_empty() {
+        base = format!("pmid{}", r.paper.pmid);
+    }
+
+    let mut key = base.clone();
+    let mut n = 0;
+    while used.contains(&key) {
+        key = if n < 26 {
+            format!("{}{}", base, (b'a' + n as u8) as char)
+        } else {
+            format!("{}{}", base, n)
+        };
+        n += 1;
+    }
+    used.insert(key.clone());
+    key
+}
+
+/// doi 和 url 原样输出, biblatex 会按字面读取, 只丢掉不成对的括号
+fn verbatim(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut open = Vec::new();
+    for c in s.trim().chars() {
+        match c {
+            '{' => {
+                open.push(out.len());
+                out.push(c);
+            }
+            '}' => {
+                if open.pop().is_some() {
+                    out.push(c);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    // 没有闭合的左括号从后往前删掉
+    while let Some(i) = open.pop() {
+        out.remove(i);
+    }
+    out
+}
+
+fn push(out: &mut String, name: &str, value: &str) {
+    if !value.is_empty() {
+        out.push_str(&format!("  {} = {{{}}},\n", name, escape(value)));
+    }
+}
+
+fn push_verbatim(out: &mut String, name: &str, value: &str) {
+    if !value.is_empty() {
+        out.push_str(&format!("  {} = {{{}}},\n", name, verbatim(value)));
+    }
+}
+
+pub fn to_bibtex(records: &[Record]) -> String {
+    let mut used = HashSet::new();
+    let mut out = String::new();
+
+    for r in records {
+        let p = r.paper;
+        out.push_str(&format!("@article{{{},\n", cite_key(r, &mut used)));
+
+        let authors = r
+            .authors()
+            .iter()
+            .map(|a| {
+                if a.given.is_empty() {
+                    // 团体作者用括号包起来, 避免被拆成姓和名
+                    format!("{{{}}}", escape(&a.family))
+                } else {
+                    escape(&a.full())
+                }
+            })
+            .collect::<Vec<String>>();
+        if !authors.is_empty() {
+            out.push_str(&format!("  author = {{{}}},\n", authors.join(" and ")));
+        }
+        if !p.title.is_empty() {
+            // 双层括号保留标题大小写
+            out.push_str(&format!("  title = {{{{{}}}}},\n", escape(&p.title)));
+        }
+        push(&mut out, "journal", &p.journal_title);
+        push(&mut out, "year", r.year());
+        if let Some(m) = r.month() {
+            out.push_str(&format!("  month = {},\n", month_abbr(m)));
+        }
+        push(&mut out, "volume", r.volume());
+        push(&mut out, "number", r.issue());
+        let (start, end) = split_pages(r.pages());
+        if end.is_empty() {
+            push(&mut out, "pages", &start);
+        } else {
+            push(&mut out, "pages", &format!("{}--{}", start, end));
+        }
+        push(&mut out, "issn", &p.issn);
+        push_verbatim(&mut out, "doi", &p.doi);
+        push(&mut out, "pmid", &p.pmid);
+        push_verbatim(&mut out, "url", &r.url());
+        push(&mut out, "keywords", &r.mesh().join(", "));
+        push(&mut out, "abstract", &p.r#abstract);
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaperCsvResult;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("50\\% \\& \\{x\\}\\_1", escape("50% & {x}_1"));
+        assert_eq!("a\\textasciitilde{}b", escape("a~b"));
+    }
+
+    #[test]
+    fn test_verbatim() {
+        assert_eq!("10.1016/j.cell_2023", verbatim("10.1016/j.cell_2023"));
+        assert_eq!("a{b}c", verbatim("a{b}}c"));
+        assert_eq!("ab{c}", verbatim("a{b{c}"));
+
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            title: "Deep".to_string(),
+            doi: "10.1016/j.cell_2023%1".to_string(),
+            ..Default::default()
+        };
+        let r = Record {
+            paper: &paper,
+            meta: None,
+        };
+        let out = to_bibtex(&[r]);
+        assert!(out.contains("  doi = {10.1016/j.cell_2023%1},\n"));
+    }
+
+    #[test]
+    fn test_cite_key() {
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            title: "The Müller cells".to_string(),
+            pubdate_year: "2020".to_string(),
+            author_first: "Anna Müller".to_string(),
+            ..Default::default()
+        };
+        let r = Record {
+            paper: &paper,
+            meta: None,
+        };
+        let mut used = HashSet::new();
+        assert_eq!("muller2020muller", cite_key(&r, &mut used));
+        assert_eq!("muller2020mullera", cite_key(&r, &mut used));
+        assert_eq!("muller2020mullerb", cite_key(&r, &mut used));
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use super::{
+    csl::{CslItem, CslName},
+    month_abbr, Record,
+};
+
+/// 一次最多引用的文献数量
+const MAX_PMIDS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Vancouver,
+    Apa,
+    Nature,
+}
+
+impl Style {
+    pub fn parse(s: &str) -> Option<Style> {
+        match s.to_lowercase().as_str() {
+            "vancouver" | "nlm" | "ama" => Some(Style::Vancouver),
+            "apa" => Some(Style::Apa),
+            "nature" => Some(Style::Nature),
+            _ => None,
+        }
+    }
+}
+
+/// 末尾没有标点时补一个句号
+fn sentence(s: &str) -> String {
+    let s = s.trim();
+    if s.is_empty() || s.ends_with(['.', '?', '!']) {
+        s.to_string()
+    } else {
+        format!("{}.", s)
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut c = s.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().chain(c).collect(),
+        None => String::new(),
+    }
+}
+
+/// `Chen MH`
+fn vancouver_name(n: &CslName) -> String {
+    let initials = n.initials().into_iter().collect::<String>();
+    if initials.is_empty() {
+        n.family_or_literal().to_string()
+    } else {
+        format!("{} {}", n.family_or_literal(), initials)
+    }
+}
+
+/// `Chen, M.-H.` 中的 `M. H.`
+fn dotted_initials(n: &CslName) -> String {
+    n.initials()
+        .into_iter()
+        .map(|c| format!("{}.", c))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// `Chen, M. H.`
+fn inverted_name(n: &CslName) -> String {
+    let initials = dotted_initials(n);
+    if initials.is_empty() {
+        n.family_or_literal().to_string()
+    } else {
+        format!("{}, {}", n.family_or_literal(), initials)
+    }
+}
+
+/// NLM: 超过 6 个作者时列出前 6 个再加 et al.
+fn vancouver_authors(authors: &[CslName]) -> String {
+    let mut v = authors
+        .iter()
+        .take(6)
+        .map(vancouver_name)
+        .collect::<Vec<String>>();
+    if authors.len() > 6 {
+        v.push("et al".to_string());
+    }
+    v.join(", ")
+}
+
+/// APA 7: 最多 20 个作者, 超过时列出前 19 个, 省略号, 再加最后一个
+fn apa_authors(authors: &[CslName]) -> String {
+    let names = authors.iter().map(inverted_name).collect::<Vec<String>>();
+    match names.len() {
+        0 => String::new(),
+        1 => names[0].clone(),
+        n if n <= 20 => format!("{}, & {}", names[..n - 1].join(", "), names[n - 1]),
+        n => format!("{}, . . . {}", names[..19].join(", "), names[n - 1]),
+    }
+}
+
+/// Nature: 超过 5 个作者时只列第一个加 et al.
+fn nature_authors(authors: &[CslName]) -> String {
+    let names = authors.iter().map(inverted_name).collect::<Vec<String>>();
+    match names.len() {
+        0 => String::new(),
+        1 => names[0].clone(),
+        n if n <= 5 => format!("{} & {}", names[..n - 1].join(", "), names[n - 1]),
+        _ => format!("{} et al.", names[0]),
+    }
+}
+
+/// `12(3)`, 缺少卷或期时只保留有的部分
+fn volume_issue(item: &CslItem) -> String {
+    match (item.volume.is_empty(), item.issue.is_empty()) {
+        (false, false) => format!("{}({})", item.volume, item.issue),
+        (false, true) => item.volume.clone(),
+        (true, false) => format!("({})", item.issue),
+        (true, true) => String::new(),
+    }
+}
+
+fn vancouver(item: &CslItem) -> String {
+    let mut parts = Vec::new();
+    let authors = vancouver_authors(&item.author);
+    if !authors.is_empty() {
+        parts.push(sentence(&authors));
+    }
+    parts.push(sentence(&item.title));
+
+    let journal = if item.container_title_short.is_empty() {
+        &item.container_title
+    } else {
+        &item.container_title_short
+    };
+    parts.push(sentence(journal));
+
+    let mut date = item.year().map(|y| y.to_string()).unwrap_or_default();
+    if let Some(m) = item.month() {
+        date = format!("{} {}", date, capitalize(month_abbr(m)));
+    }
+    let vi = volume_issue(item);
+    let mut source = date;
+    if !vi.is_empty() {
+        source = format!("{};{}", source, vi);
+    }
+    if !item.page.is_empty() {
+        source = format!("{}:{}", source, compact_pages(&item.page));
+    }
+    if !source.is_empty() {
+        parts.push(format!("{}.", source));
+    }
+
+    if !item.doi.is_empty() {
+        parts.push(format!("doi: {}.", item.doi));
+    }
+    if item.ahead_of_print() {
+        parts.push("Epub ahead of print.".to_string());
+    }
+    parts.push(format!("PMID: {}.", item.pmid));
+    parts.join(" ")
+}
+
+/// NLM 的页码省略结束页中相同的前缀, `123-130` => `123-30`
+fn compact_pages(page: &str) -> String {
+    match page.split_once('-') {
+        Some((start, end))
+            if start.len() == end.len()
+                && (start.to_owned() + end).chars().all(|c| c.is_ascii_digit()) =>
+        {
+            let same = start
+                .chars()
+                .zip(end.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let same = same.min(end.len() - 1);
+            format!("{}-{}", start, &end[same..])
+        }
+        _ => page.to_string(),
+    }
+}
+
+fn link(item: &CslItem) -> String {
+    if item.doi.is_empty() {
+        item.url.clone()
+    } else {
+        super::doi_url(&item.doi)
+    }
+}
+
+fn apa(item: &CslItem) -> String {
+    let mut parts = Vec::new();
+    let authors = apa_authors(&item.author);
+    if !authors.is_empty() {
+        parts.push(sentence(&authors));
+    }
+    let year = item
+        .year()
+        .map(|y| y.to_string())
+        .unwrap_or_else(|| "n.d.".to_string());
+    parts.push(format!("({}).", year));
+    parts.push(sentence(&item.title));
+
+    let mut source = item.container_title.clone();
+    let vi = volume_issue(item);
+    if !vi.is_empty() {
+        source = format!("{}, {}", source, vi);
+    }
+    if !item.page.is_empty() {
+        source = format!("{}, {}", source, item.page.replace('-', "–"));
+    }
+    parts.push(format!("{}.", source));
+    if item.ahead_of_print() {
+        parts.push("Advance online publication.".to_string());
+    }
+    parts.push(link(item));
+    parts.join(" ")
+}
+
+fn nature(item: &CslItem) -> String {
+    let mut parts = Vec::new();
+    let authors = nature_authors(&item.author);
+    if !authors.is_empty() {
+        parts.push(sentence(&authors));
+    }
+    parts.push(sentence(&item.title));
+
+    let journal = if item.container_title_short.is_empty() {
+        &item.container_title
+    } else {
+        &item.container_title_short
+    };
+    let year = item.year().map(|y| y.to_string()).unwrap_or_default();
+    if item.ahead_of_print() || (item.volume.is_empty() && item.page.is_empty()) {
+        parts.push(format!("{} {} ({}).", journal, link(item), year));
+    } else {
+        let mut source = journal.clone();
+        if !item.volume.is_empty() {
+            source = format!("{} {}", source, item.volume);
+        }
+        if !item.page.is_empty() {
+            source = format!("{}, {}", source, item.page.replace('-', "–"));
+        }
+        parts.push(format!("{} ({}).", source, year));
+    }
+    parts.join(" ")
+}
+
+pub fn render(item: &CslItem, style: Style) -> String {
+    match style {
+        Style::Vancouver => vancouver(item),
+        Style::Apa => apa(item),
+        Style::Nature => nature(item),
+    }
+}
+
+/// 按请求的顺序返回, 本地没有缓存的通过 efetch 下载
+async fn load_items(
+    pmids: &[String],
+) -> Result<Vec<CslItem>, Box<dyn std::error::Error + Send + Sync>> {
+    let papers = crate::eutils::efetch("pubmed", pmids).await?;
+    let map = papers
+        .iter()
+        .map(|f| (f.pmid.as_str(), f))
+        .collect::<HashMap<&str, _>>();
+
+    Ok(pmids
+        .iter()
+        .filter_map(|id| map.get(id.as_str()))
+        .map(|p| CslItem::from(&Record::new(p)))
+        .collect())
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::get;
+
+    use super::*;
+    use crate::auth::Ncbi;
+    use crate::error::{api_ok, ApiResult, AppError};
+    use crate::response::response_ok;
+
+    /// `style` 为 `csl` 时返回 CSL-JSON, 否则返回格式化的引用
+    #[get("/pubmed/cite?<pmids>&<style>")]
+    pub async fn cite(_key: Ncbi, pmids: String, style: Option<String>) -> ApiResult {
+        let ids = pmids
+            .split([',', ' '])
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+            .collect::<Vec<String>>();
+        if ids.is_empty() || ids.len() > MAX_PMIDS {
+            return Err(AppError::bad_request(format!(
+                "pmids must have 1 to {} ids",
+                MAX_PMIDS
+            )));
+        }
+        if let Some(id) = ids.iter().find(|f| f.parse::<usize>().is_err()) {
+            return Err(AppError::bad_request(format!("invalid pmid: {}", id)));
+        }
+
+        let style = style.unwrap_or_else(|| "vancouver".to_string());
+        let csl = matches!(style.to_lowercase().as_str(), "csl" | "csl-json");
+        let s = Style::parse(&style);
+        if !csl && s.is_none() {
+            return Err(AppError::bad_request(format!(
+                "unsupported style: {}",
+                style
+            )));
+        }
+
+        log::info!("cite pmids = {:?}, style = {}", &ids, &style);
+        let items = load_items(&ids).await?;
+
+        match s.filter(|_| !csl) {
+            None => api_ok(&items),
+            Some(s) => {
+                let v = items
+                    .iter()
+                    .map(|f| serde_json::json!({ "pmid": f.pmid, "citation": render(f, s) }))
+                    .collect::<Vec<serde_json::Value>>();
+                Ok(response_ok(serde_json::Value::Array(v)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::csl::CslDate;
+
+    fn name(family: &str, given: &str) -> CslName {
+        CslName {
+            family: Some(family.to_string()),
+            given: Some(given.to_string()),
+            literal: None,
+        }
+    }
+
+    fn item(n: usize) -> CslItem {
+        CslItem {
+            pmid: "1".to_string(),
+            title: "A study of things".to_string(),
+            container_title: "Nature Medicine".to_string(),
+            container_title_short: "Nat Med".to_string(),
+            author: (0..n)
+                .map(|i| name(&format!("A{}", i), "Ming-Han"))
+                .collect(),
+            issued: Some(CslDate {
+                date_parts: vec![vec![2020, 2]],
+            }),
+            volume: "26".to_string(),
+            issue: "3".to_string(),
+            page: "123-130".to_string(),
+            doi: "10.1/x".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vancouver() {
+        assert_eq!(
+            "A0 MH, A1 MH. A study of things. Nat Med. 2020 Feb;26(3):123-30. doi: 10.1/x. PMID: 1.",
+            render(&item(2), Style::Vancouver)
+        );
+        let s = render(&item(7), Style::Vancouver);
+        assert!(s.starts_with("A0 MH, A1 MH, A2 MH, A3 MH, A4 MH, A5 MH, et al. A study"));
+    }
+
+    #[test]
+    fn test_apa() {
+        assert_eq!(
+            "A0, M. H., & A1, M. H. (2020). A study of things. Nature Medicine, 26(3), 123–130. https://doi.org/10.1/x",
+            render(&item(2), Style::Apa)
+        );
+        let s = render(&item(25), Style::Apa);
+        assert!(s.contains("A18, M. H., . . . A24, M. H. (2020)"));
+    }
+
+    #[test]
+    fn test_nature() {
+        assert_eq!(
+            "A0, M. H. et al. A study of things. Nat Med 26, 123–130 (2020).",
+            render(&item(6), Style::Nature)
+        );
+
+        // 在线优先出版, 没有页码
+        let mut i = item(1);
+        i.volume = String::new();
+        i.page = String::new();
+        i.status = Some(crate::export::csl::EPUB_AHEAD_OF_PRINT.to_string());
+        assert_eq!(
+            "A0, M. H. A study of things. Nat Med https://doi.org/10.1/x (2020).",
+            render(&i, Style::Nature)
+        );
+        assert!(
+            render(&i, Style::Vancouver).ends_with("doi: 10.1/x. Epub ahead of print. PMID: 1.")
+        );
+    }
+
+    #[test]
+    fn test_compact_pages() {
+        assert_eq!("123-30", compact_pages("123-130"));
+        assert_eq!("e1-e9", compact_pages("e1-e9"));
+        assert_eq!("1-9", compact_pages("1-9"));
+    }
+}
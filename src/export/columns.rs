@@ -0,0 +1,350 @@
+use std::{collections::HashMap, error::Error, fs, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    doi_url,
+    xlsx::{col, Column, Kind},
+    Record,
+};
+use crate::settings::data_path;
+
+static PROFILE_FILE: &str = "export_profiles.json";
+
+/// 可以导出的字段, 前 14 个和 `PaperCsvResult` 的 csv 列相同
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Pmid,
+    Title,
+    PubDateYear,
+    PubDateMonth,
+    JournalTitle,
+    JournalAbbr,
+    Abstract,
+    AuthorFirst,
+    AuthorLast,
+    PublicationType,
+    Doi,
+    Issn,
+    EpubYear,
+    EpubMonth,
+    /// 全部作者, `Chen M; Smith JA`
+    Authors,
+    Mesh,
+    Volume,
+    Issue,
+    Pages,
+    PubmedUrl,
+    DoiUrl,
+}
+
+static FIELDS: [(&str, Field); 21] = [
+    ("PMID", Field::Pmid),
+    ("Title", Field::Title),
+    ("PubDateYear", Field::PubDateYear),
+    ("PubDateMonth", Field::PubDateMonth),
+    ("JournalTitle", Field::JournalTitle),
+    ("JournalAbbr", Field::JournalAbbr),
+    ("Abstract", Field::Abstract),
+    ("AuthorFirst", Field::AuthorFirst),
+    ("AuthorLast", Field::AuthorLast),
+    ("PublicationType", Field::PublicationType),
+    ("DOI", Field::Doi),
+    ("ISSN", Field::Issn),
+    ("EpubYear", Field::EpubYear),
+    ("EpubMonth", Field::EpubMonth),
+    ("Authors", Field::Authors),
+    ("MeSH", Field::Mesh),
+    ("Volume", Field::Volume),
+    ("Issue", Field::Issue),
+    ("Pages", Field::Pages),
+    ("PubMedURL", Field::PubmedUrl),
+    ("DOIURL", Field::DoiUrl),
+];
+
+impl Field {
+    /// 不区分大小写
+    pub fn parse(s: &str) -> Option<Field> {
+        FIELDS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|(_, f)| *f)
+    }
+
+    pub fn name(&self) -> &'static str {
+        FIELDS.iter().find(|(_, f)| f == self).unwrap().0
+    }
+
+    pub fn value(&self, r: &Record) -> String {
+        let p = r.paper;
+        match self {
+            Field::Pmid => p.pmid.clone(),
+            Field::Title => p.title.clone(),
+            Field::PubDateYear => p.pubdate_year.clone(),
+            Field::PubDateMonth => p.pubdate_month.clone(),
+            Field::JournalTitle => p.journal_title.clone(),
+            Field::JournalAbbr => p.journal_abbr.clone(),
+            Field::Abstract => p.r#abstract.clone(),
+            Field::AuthorFirst => p.author_first.clone(),
+            Field::AuthorLast => p.author_last.clone(),
+            Field::PublicationType => p.publication_type.clone(),
+            Field::Doi => p.doi.clone(),
+            Field::Issn => p.issn.clone(),
+            Field::EpubYear => p.epub_year.clone(),
+            Field::EpubMonth => p.epub_month.clone(),
+            Field::Authors => r
+                .authors()
+                .iter()
+                .map(|a| format!("{} {}", a.family, a.initials).trim().to_string())
+                .collect::<Vec<String>>()
+                .join("; "),
+            Field::Mesh => r.mesh().join("; "),
+            Field::Volume => r.volume().to_string(),
+            Field::Issue => r.issue().to_string(),
+            Field::Pages => r.pages().to_string(),
+            Field::PubmedUrl => r.url(),
+            Field::DoiUrl => doi_url(&p.doi),
+        }
+    }
+
+    fn column(&self, header: &str) -> Column {
+        match self {
+            Field::Pmid => col(header, 12.0, Kind::Pmid),
+            Field::Doi => col(header, 28.0, Kind::Doi),
+            Field::PubmedUrl | Field::DoiUrl => col(header, 40.0, Kind::Url),
+            Field::Title | Field::Authors | Field::Mesh => col(header, 50.0, Kind::Wrap),
+            Field::Abstract => col(header, 80.0, Kind::Wrap),
+            Field::PubDateYear | Field::EpubYear => col(header, 8.0, Kind::Number),
+            _ => col(header, 16.0, Kind::Text),
+        }
+    }
+}
+
+/// 和默认 csv 相同的列
+pub fn csv_fields() -> Vec<(Field, String)> {
+    FIELDS[..14]
+        .iter()
+        .map(|(name, f)| (*f, name.to_string()))
+        .collect()
+}
+
+/// 导出的一列, `header` 为空时使用字段名
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ColumnSpec {
+    pub field: String,
+    #[serde(default)]
+    pub header: Option<String>,
+}
+
+/// 有名字的导出列配置, 保存在 `data/export_profiles.json`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Profile {
+    pub name: String,
+    pub columns: Vec<ColumnSpec>,
+}
+
+/// `PMID,Title:论文标题,Authors` 格式, 冒号后面是表头
+pub fn parse_columns(s: &str) -> Vec<ColumnSpec> {
+    s.split(',')
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+        .map(|f| match f.split_once(':') {
+            Some((field, header)) => ColumnSpec {
+                field: field.trim().to_string(),
+                header: Some(header.trim().to_string()),
+            },
+            None => ColumnSpec {
+                field: f.to_string(),
+                header: None,
+            },
+        })
+        .collect()
+}
+
+/// 检查字段名, 返回 (字段, 表头)
+pub fn resolve(columns: &[ColumnSpec]) -> Result<Vec<(Field, String)>, String> {
+    if columns.is_empty() {
+        return Err("columns is empty".to_string());
+    }
+    columns
+        .iter()
+        .map(|c| {
+            let f = Field::parse(&c.field).ok_or(format!("unknown field: {}", c.field))?;
+            let header = c.header.as_deref().filter(|h| !h.is_empty());
+            Ok((f, header.unwrap_or(f.name()).to_string()))
+        })
+        .collect()
+}
+
+/// 请求里的 `columns` 优先, 其次是保存的 `profile`, 都没有时返回 None 使用默认的列
+pub fn request_fields(
+    columns: Option<&str>,
+    profile: Option<&str>,
+) -> Result<Option<Vec<(Field, String)>>, String> {
+    if let Some(c) = columns.filter(|f| !f.trim().is_empty()) {
+        return resolve(&parse_columns(c)).map(Some);
+    }
+    if let Some(name) = profile.filter(|f| !f.is_empty()) {
+        let p = get_profile(name).ok_or(format!("profile not found: {}", name))?;
+        return resolve(&p.columns).map(Some);
+    }
+    Ok(None)
+}
+
+pub struct Table {
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(records: &[Record], fields: &[(Field, String)]) -> Self {
+        Table {
+            columns: fields.iter().map(|(f, h)| f.column(h)).collect(),
+            rows: records
+                .iter()
+                .map(|r| fields.iter().map(|(f, _)| f.value(r)).collect())
+                .collect(),
+        }
+    }
+
+    pub fn save_csv(&self, path: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut writer = csv::WriterBuilder::new()
+            .quote_style(csv::QuoteStyle::Necessary)
+            .from_path(path)?;
+        writer.write_record(self.columns.iter().map(|c| c.header.as_str()))?;
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+static PROFILES: Lazy<Mutex<HashMap<String, Profile>>> = Lazy::new(|| {
+    let map = fs::read_to_string(data_path(PROFILE_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    Mutex::new(map)
+});
+
+fn save_profiles(map: &HashMap<String, Profile>) -> std::io::Result<()> {
+    let s = serde_json::to_string_pretty(map)?;
+    fs::write(data_path(PROFILE_FILE), s)
+}
+
+pub fn get_profile(name: &str) -> Option<Profile> {
+    PROFILES.lock().unwrap().get(name).cloned()
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{delete, get, post, serde::json::Json};
+
+    use super::*;
+    use crate::auth::Auth;
+    use crate::error::{api_ok, ApiResult, AppError};
+    use crate::response::response_ok;
+
+    /// 全部可用的字段和已保存的配置
+    #[get("/export/profiles")]
+    pub async fn list_profiles(_key: Auth) -> ApiResult {
+        let mut profiles = PROFILES
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<Profile>>();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(response_ok(serde_json::json!({
+            "fields": FIELDS.iter().map(|(name, _)| *name).collect::<Vec<&str>>(),
+            "profiles": profiles,
+        })))
+    }
+
+    #[post("/export/profiles", format = "json", data = "<req>")]
+    pub async fn save_profile(_key: Auth, req: Json<Profile>) -> ApiResult {
+        let profile = req.into_inner();
+        if !valid_name(&profile.name) {
+            return Err(AppError::bad_request(format!(
+                "invalid profile name: {}",
+                profile.name
+            )));
+        }
+        resolve(&profile.columns).map_err(AppError::bad_request)?;
+
+        let mut map = PROFILES.lock().unwrap();
+        map.insert(profile.name.clone(), profile.clone());
+        save_profiles(&map)?;
+        api_ok(&profile)
+    }
+
+    #[delete("/export/profiles/<name>")]
+    pub async fn delete_profile(_key: Auth, name: String) -> ApiResult {
+        let mut map = PROFILES.lock().unwrap();
+        if map.remove(&name).is_none() {
+            return Err(AppError::not_found(format!("profile not found: {}", name)));
+        }
+        save_profiles(&map)?;
+        Ok(response_ok(serde_json::json!({ "name": name })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaperCsvResult;
+
+    #[test]
+    fn test_parse_columns() {
+        let cols = parse_columns("pmid, Title:论文标题,DOIURL");
+        let fields = resolve(&cols).unwrap();
+        assert_eq!(
+            vec![
+                (Field::Pmid, "PMID".to_string()),
+                (Field::Title, "论文标题".to_string()),
+                (Field::DoiUrl, "DOIURL".to_string()),
+            ],
+            fields
+        );
+        assert!(resolve(&parse_columns("PMID,Foo")).is_err());
+    }
+
+    #[test]
+    fn test_table() {
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            doi: "10.1/x".to_string(),
+            author_first: "Ming Chen".to_string(),
+            author_last: "Anna Smith".to_string(),
+            ..Default::default()
+        };
+        let records = vec![Record {
+            paper: &paper,
+            meta: None,
+        }];
+        let fields = resolve(&parse_columns("Authors,PubMedURL,DOIURL")).unwrap();
+        let table = Table::new(&records, &fields);
+
+        assert_eq!(
+            vec![
+                "Chen M; Smith A",
+                "https://pubmed.ncbi.nlm.nih.gov/1/",
+                "https://doi.org/10.1/x"
+            ],
+            table.rows[0]
+        );
+    }
+}
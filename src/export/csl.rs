@@ -0,0 +1,190 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{split_pages, Record};
+
+/// CSL-JSON 的作者, 团体作者只有 `literal`
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+pub struct CslName {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub given: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub literal: Option<String>,
+}
+
+impl CslName {
+    /// 名字的首字母, `Ming-Han` => ['M', 'H']
+    pub fn initials(&self) -> Vec<char> {
+        self.given
+            .as_deref()
+            .unwrap_or("")
+            .split([' ', '-', '.'])
+            .filter_map(|f| f.chars().next())
+            .collect()
+    }
+
+    pub fn family_or_literal(&self) -> &str {
+        self.family
+            .as_deref()
+            .or(self.literal.as_deref())
+            .unwrap_or("")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+pub struct CslDate {
+    /// `[[year, month]]`
+    #[serde(rename = "date-parts")]
+    pub date_parts: Vec<Vec<u32>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CslItem {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub title: String,
+    pub container_title: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub container_title_short: String,
+    pub author: Vec<CslName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issued: Option<CslDate>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub volume: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub issue: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub page: String,
+    #[serde(rename = "DOI", skip_serializing_if = "String::is_empty", default)]
+    pub doi: String,
+    #[serde(rename = "PMID")]
+    pub pmid: String,
+    #[serde(rename = "ISSN", skip_serializing_if = "String::is_empty", default)]
+    pub issn: String,
+    #[serde(rename = "URL")]
+    pub url: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub r#abstract: String,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub keyword: String,
+    /// 未正式出版时为 `epub-ahead-of-print`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+pub static EPUB_AHEAD_OF_PRINT: &str = "epub-ahead-of-print";
+
+impl CslItem {
+    pub fn year(&self) -> Option<u32> {
+        self.issued
+            .as_ref()
+            .and_then(|d| d.date_parts.first())
+            .and_then(|p| p.first())
+            .copied()
+    }
+
+    pub fn month(&self) -> Option<u32> {
+        self.issued
+            .as_ref()
+            .and_then(|d| d.date_parts.first())
+            .and_then(|p| p.get(1))
+            .copied()
+    }
+
+    pub fn ahead_of_print(&self) -> bool {
+        self.status.as_deref() == Some(EPUB_AHEAD_OF_PRINT)
+    }
+}
+
+impl From<&Record<'_>> for CslItem {
+    fn from(r: &Record) -> Self {
+        let p = r.paper;
+        // 没有卷和页码, 只有电子版日期的视为在线优先出版
+        let ahead = !p.epub_year.is_empty()
+            && (p.pubdate_year.is_empty()
+                || (r.meta.is_some() && r.volume().is_empty() && r.pages().is_empty()));
+
+        let (year, month) = if ahead {
+            (p.epub_year.as_str(), super::parse_month(&p.epub_month))
+        } else {
+            (r.year(), r.month())
+        };
+        let issued = year.parse::<u32>().ok().map(|y| CslDate {
+            date_parts: vec![std::iter::once(y).chain(month).collect()],
+        });
+
+        let (start, end) = split_pages(r.pages());
+        let page = if end.is_empty() {
+            start
+        } else {
+            format!("{}-{}", start, end)
+        };
+
+        CslItem {
+            id: p.pmid.clone(),
+            r#type: "article-journal".to_string(),
+            title: p.title.clone(),
+            container_title: p.journal_title.clone(),
+            container_title_short: p.journal_abbr.clone(),
+            author: r
+                .authors()
+                .into_iter()
+                .map(|a| {
+                    if a.given.is_empty() {
+                        CslName {
+                            literal: Some(a.family),
+                            ..Default::default()
+                        }
+                    } else {
+                        CslName {
+                            family: Some(a.family),
+                            given: Some(a.given),
+                            literal: None,
+                        }
+                    }
+                })
+                .collect(),
+            issued,
+            volume: r.volume().to_string(),
+            issue: r.issue().to_string(),
+            page,
+            doi: p.doi.clone(),
+            pmid: p.pmid.clone(),
+            issn: p.issn.clone(),
+            url: r.url(),
+            r#abstract: p.r#abstract.clone(),
+            keyword: r.mesh().join(", "),
+            status: ahead.then(|| EPUB_AHEAD_OF_PRINT.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaperCsvResult;
+
+    #[test]
+    fn test_epub_ahead_of_print() {
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            epub_year: "2023".to_string(),
+            epub_month: "11".to_string(),
+            author_first: "Ming Chen".to_string(),
+            ..Default::default()
+        };
+        let item = CslItem::from(&Record {
+            paper: &paper,
+            meta: None,
+        });
+
+        assert!(item.ahead_of_print());
+        assert_eq!(Some(2023), item.year());
+        assert_eq!(Some(11), item.month());
+        assert_eq!(Some("Chen".to_string()), item.author[0].family);
+    }
+}
@@ -0,0 +1,103 @@
+use super::Record;
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn style(value: &str) -> String {
+    format!(
+        "<style face=\"normal\" font=\"default\" size=\"100%\">{}</style>",
+        escape(value)
+    )
+}
+
+fn push(out: &mut String, tag: &str, value: &str) {
+    if !value.is_empty() {
+        out.push_str(&format!("<{}>{}</{}>", tag, style(value), tag));
+    }
+}
+
+/// EndNote XML, 可以直接导入 EndNote 和 Zotero
+pub fn to_endnote(records: &[Record]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<xml><records>\n");
+
+    for (i, r) in records.iter().enumerate() {
+        let p = r.paper;
+        out.push_str(&format!(
+            "<record><rec-number>{}</rec-number><ref-type name=\"Journal Article\">17</ref-type>",
+            i + 1
+        ));
+
+        out.push_str("<contributors><authors>");
+        for a in r.authors() {
+            push(&mut out, "author", &a.full());
+        }
+        out.push_str("</authors></contributors>");
+
+        out.push_str("<titles>");
+        push(&mut out, "title", &p.title);
+        push(&mut out, "secondary-title", &p.journal_title);
+        push(&mut out, "alt-title", &p.journal_abbr);
+        out.push_str("</titles>");
+
+        out.push_str("<periodical>");
+        push(&mut out, "full-title", &p.journal_title);
+        push(&mut out, "abbr-1", &p.journal_abbr);
+        out.push_str("</periodical>");
+
+        push(&mut out, "pages", r.pages());
+        push(&mut out, "volume", r.volume());
+        push(&mut out, "number", r.issue());
+
+        if !r.mesh().is_empty() {
+            out.push_str("<keywords>");
+            for m in r.mesh() {
+                push(&mut out, "keyword", m);
+            }
+            out.push_str("</keywords>");
+        }
+
+        out.push_str("<dates>");
+        push(&mut out, "year", r.year());
+        out.push_str("</dates>");
+
+        push(&mut out, "isbn", &p.issn);
+        push(&mut out, "accession-num", &p.pmid);
+        push(&mut out, "abstract", &p.r#abstract);
+        push(&mut out, "electronic-resource-num", &p.doi);
+        out.push_str(&format!(
+            "<urls><related-urls><url>{}</url></related-urls></urls>",
+            style(&r.url())
+        ));
+        out.push_str("</record>\n");
+    }
+
+    out.push_str("</records></xml>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaperCsvResult;
+
+    #[test]
+    fn test_to_endnote() {
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            title: "IL-6 <b>& TNF</b>".to_string(),
+            ..Default::default()
+        };
+        let xml = to_endnote(&[Record {
+            paper: &paper,
+            meta: None,
+        }]);
+
+        assert!(xml.contains("IL-6 &lt;b&gt;&amp; TNF&lt;/b&gt;"));
+        assert!(xml.contains("<accession-num>"));
+    }
+}
@@ -0,0 +1,323 @@
+use std::{error::Error, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    cache::PaperMeta,
+    model::PaperCsvResult,
+    utils::{get_download_path, save_to_file},
+};
+
+pub mod bibtex;
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub mod cite;
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub mod columns;
+pub mod csl;
+pub mod endnote;
+pub mod ris;
+pub mod xlsx;
+
+/// 导出用的一条文献, 有缓存元信息时使用完整的作者列表和卷期页码
+pub struct Record<'a> {
+    pub paper: &'a PaperCsvResult,
+    pub meta: Option<PaperMeta>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Name {
+    pub family: String,
+    pub given: String,
+    pub initials: String,
+}
+
+impl Name {
+    /// `Chen, Ming-Han` 格式, 团体作者只有 family
+    pub fn full(&self) -> String {
+        if self.given.is_empty() {
+            self.family.clone()
+        } else {
+            format!("{}, {}", self.family, self.given)
+        }
+    }
+
+    /// csv 中 `ForeName LastName` 格式的作者
+    fn from_csv(s: &str) -> Option<Name> {
+        let s = s.trim();
+        if s.is_empty() {
+            return None;
+        }
+        let (given, family) = s.rsplit_once(' ').unwrap_or(("", s));
+        Some(Name {
+            family: family.to_string(),
+            given: given.to_string(),
+            initials: given
+                .split([' ', '-'])
+                .filter_map(|f| f.chars().next())
+                .collect(),
+        })
+    }
+}
+
+impl<'a> Record<'a> {
+    pub fn new(paper: &'a PaperCsvResult) -> Self {
+        let meta = paper.pmid.parse::<usize>().ok().and_then(PaperMeta::load);
+        Record { paper, meta }
+    }
+
+    pub fn authors(&self) -> Vec<Name> {
+        if let Some(meta) = self.meta.as_ref().filter(|m| !m.authors.is_empty()) {
+            return meta
+                .authors
+                .iter()
+                .map(|a| match a.collective_name.as_ref() {
+                    Some(c) => Name {
+                        family: c.clone(),
+                        ..Default::default()
+                    },
+                    None => Name {
+                        family: a.last_name.clone().unwrap_or_default(),
+                        given: a.fore_name.clone().unwrap_or_default(),
+                        initials: a.initials.clone().unwrap_or_default(),
+                    },
+                })
+                .collect();
+        }
+
+        // 旧的缓存只有第一和最后一个作者
+        [&self.paper.author_first, &self.paper.author_last]
+            .iter()
+            .filter_map(|s| Name::from_csv(s))
+            .collect()
+    }
+
+    pub fn year(&self) -> &str {
+        if self.paper.pubdate_year.is_empty() {
+            &self.paper.epub_year
+        } else {
+            &self.paper.pubdate_year
+        }
+    }
+
+    /// 月份 1-12, 支持 `Feb` 和 `02` 两种写法
+    pub fn month(&self) -> Option<u32> {
+        let m = if self.paper.pubdate_month.is_empty() {
+            &self.paper.epub_month
+        } else {
+            &self.paper.pubdate_month
+        };
+        parse_month(m)
+    }
+
+    pub fn volume(&self) -> &str {
+        self.meta.as_ref().map(|m| m.volume.as_str()).unwrap_or("")
+    }
+
+    pub fn issue(&self) -> &str {
+        self.meta.as_ref().map(|m| m.issue.as_str()).unwrap_or("")
+    }
+
+    pub fn pages(&self) -> &str {
+        self.meta.as_ref().map(|m| m.pages.as_str()).unwrap_or("")
+    }
+
+    pub fn mesh(&self) -> &[String] {
+        self.meta.as_ref().map(|m| m.mesh.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn url(&self) -> String {
+        pubmed_url(&self.paper.pmid)
+    }
+}
+
+pub fn pubmed_url(pmid: &str) -> String {
+    format!("https://pubmed.ncbi.nlm.nih.gov/{}/", pmid)
+}
+
+pub fn doi_url(doi: &str) -> String {
+    if doi.is_empty() {
+        String::new()
+    } else {
+        format!("https://doi.org/{}", doi)
+    }
+}
+
+static MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+pub fn parse_month(m: &str) -> Option<u32> {
+    let m = m.trim().to_lowercase();
+    if let Ok(n) = m.parse::<u32>() {
+        return (1..=12).contains(&n).then_some(n);
+    }
+    MONTHS
+        .iter()
+        .position(|f| m.starts_with(f))
+        .map(|i| i as u32 + 1)
+}
+
+pub fn month_abbr(m: u32) -> &'static str {
+    MONTHS[(m as usize - 1) % 12]
+}
+
+/// MedlinePgn 拆成起止页, `123-30` => (`123`, `130`)
+pub fn split_pages(pages: &str) -> (String, String) {
+    let first = pages.split(',').next().unwrap_or("").trim();
+    match first.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (start.trim(), end.trim());
+            // 结束页省略了前面相同的数字
+            let end = if end.len() < start.len() && end.chars().all(|c| c.is_ascii_digit()) {
+                format!("{}{}", &start[..start.len() - end.len()], end)
+            } else {
+                end.to_string()
+            };
+            (start.to_string(), end)
+        }
+        None => (first.to_string(), String::new()),
+    }
+}
+
+/// 下载文件的扩展名
+pub fn normalize_type(file_type: &str) -> Option<&'static str> {
+    match file_type.to_lowercase().as_str() {
+        "csv" => Some("csv"),
+        "ris" => Some("ris"),
+        "bib" | "bibtex" => Some("bib"),
+        "xml" | "endnote" => Some("xml"),
+        "xlsx" | "excel" => Some("xlsx"),
+        _ => None,
+    }
+}
+
+/// 按 `file_type` 导出到下载目录, 返回下载 id;
+/// 指定了 `fields` 时 csv 和 xlsx 只导出这些列
+pub fn save_list(
+    list: &[PaperCsvResult],
+    file_type: &str,
+    query: &str,
+    fields: Option<&[(columns::Field, String)]>,
+) -> Result<serde_json::Value, Box<dyn Error + Send + Sync>> {
+    let ext = normalize_type(file_type).ok_or_else(|| unsupported(file_type))?;
+    let (file_name, id) = get_download_path(ext)?;
+    write_list(list, ext, query, fields, &file_name)?;
+    if ext == "xlsx" {
+        log::info!("save xlsx {}", &file_name);
+    }
+
+    Ok(serde_json::json!({
+        "id": crate::retention::issue_token(ext, id),
+        "file_type": ext,
+    }))
+}
+
+/// 按 `file_type` 导出到 `file_name`, 返回扩展名, 命令行导出时直接使用
+pub fn write_list(
+    list: &[PaperCsvResult],
+    file_type: &str,
+    query: &str,
+    fields: Option<&[(columns::Field, String)]>,
+    file_name: &str,
+) -> Result<&'static str, Box<dyn Error + Send + Sync>> {
+    let ext = normalize_type(file_type).ok_or_else(|| unsupported(file_type))?;
+    let meta = xlsx::Meta {
+        query,
+        counts: vec![],
+    };
+    if let Some(fields) = fields.filter(|_| ext == "csv" || ext == "xlsx") {
+        let records = list.iter().map(Record::new).collect::<Vec<Record>>();
+        let table = columns::Table::new(&records, fields);
+        if ext == "xlsx" {
+            xlsx::save_cells(file_name, &table.columns, &table.rows, &meta)?;
+        } else {
+            table.save_csv(file_name)?;
+        }
+        return Ok(ext);
+    }
+    if ext == "csv" {
+        PaperCsvResult::write_list_csv(list, file_name)?;
+        return Ok(ext);
+    }
+    if ext == "xlsx" {
+        xlsx::save_xlsx(file_name, list, &meta)?;
+        return Ok(ext);
+    }
+
+    let records = list.iter().map(Record::new).collect::<Vec<Record>>();
+    let content = match ext {
+        "ris" => ris::to_ris(&records),
+        "bib" => bibtex::to_bibtex(&records),
+        _ => endnote::to_endnote(&records),
+    };
+    std::fs::write(file_name, content)?;
+    Ok(ext)
+}
+
+/// 不支持的导出格式, http 接口返回 400
+#[derive(Debug)]
+pub struct UnsupportedType(pub String);
+
+impl fmt::Display for UnsupportedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported file_type: {}", self.0)
+    }
+}
+
+impl Error for UnsupportedType {}
+
+fn unsupported(file_type: &str) -> UnsupportedType {
+    UnsupportedType(file_type.to_string())
+}
+
+/// 表格类的结果写入 csv 或 xlsx, 返回 (文件路径, 时间戳, 扩展名)
+pub fn save_table<T: Serialize + xlsx::XlsxRow>(
+    rows: &[T],
+    file_type: Option<&str>,
+    meta: &xlsx::Meta,
+) -> Result<(String, i64, &'static str), Box<dyn Error + Send + Sync>> {
+    let ext = match file_type.map(|f| normalize_type(f).ok_or(f)) {
+        None => "csv",
+        Some(Ok(ext @ ("csv" | "xlsx"))) => ext,
+        Some(Ok(ext)) => return Err(unsupported(ext).into()),
+        Some(Err(f)) => return Err(unsupported(f).into()),
+    };
+
+    let (file_name, id) = get_download_path(ext)?;
+    if ext == "xlsx" {
+        xlsx::save_xlsx(&file_name, rows, meta)?;
+    } else {
+        save_to_file(&file_name, rows)?;
+    }
+    Ok((file_name, id, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pages() {
+        assert_eq!(
+            ("123".to_string(), "130".to_string()),
+            split_pages("123-30")
+        );
+        assert_eq!(("e1234".to_string(), String::new()), split_pages("e1234"));
+        assert_eq!(("S1".to_string(), "S9".to_string()), split_pages("S1-S9"));
+    }
+
+    #[test]
+    fn test_name_from_csv() {
+        let n = Name::from_csv("Ming-Han Chen").unwrap();
+        assert_eq!("Chen", n.family);
+        assert_eq!("MH", n.initials);
+        assert_eq!("Chen, Ming-Han", n.full());
+    }
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(Some(2), parse_month("Feb"));
+        assert_eq!(Some(2), parse_month("02"));
+        assert_eq!(None, parse_month(""));
+    }
+}
@@ -0,0 +1,74 @@
+use super::{split_pages, Record};
+
+/// RIS 的值只能占一行
+fn clean(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn push(out: &mut String, tag: &str, value: &str) {
+    let value = clean(value);
+    if !value.is_empty() {
+        out.push_str(&format!("{}  - {}\r\n", tag, value));
+    }
+}
+
+pub fn to_ris(records: &[Record]) -> String {
+    let mut out = String::new();
+    for r in records {
+        let p = r.paper;
+        push(&mut out, "TY", "JOUR");
+        for a in r.authors() {
+            push(&mut out, "AU", &a.full());
+        }
+        push(&mut out, "TI", &p.title);
+        push(&mut out, "JO", &p.journal_title);
+        push(&mut out, "JA", &p.journal_abbr);
+        push(&mut out, "PY", r.year());
+        if let Some(m) = r.month() {
+            push(&mut out, "DA", &format!("{}/{:02}//", r.year(), m));
+        }
+        push(&mut out, "VL", r.volume());
+        push(&mut out, "IS", r.issue());
+        let (start, end) = split_pages(r.pages());
+        push(&mut out, "SP", &start);
+        push(&mut out, "EP", &end);
+        push(&mut out, "AB", &p.r#abstract);
+        push(&mut out, "SN", &p.issn);
+        push(&mut out, "DO", &p.doi);
+        push(&mut out, "AN", &p.pmid);
+        push(&mut out, "UR", &r.url());
+        for m in r.mesh() {
+            push(&mut out, "KW", m);
+        }
+        out.push_str("ER  - \r\n\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PaperCsvResult;
+
+    #[test]
+    fn test_to_ris() {
+        let paper = PaperCsvResult {
+            pmid: "1".to_string(),
+            title: "A title\nwith newline".to_string(),
+            pubdate_year: "2020".to_string(),
+            pubdate_month: "Feb".to_string(),
+            author_first: "Ming Chen".to_string(),
+            ..Default::default()
+        };
+        let ris = to_ris(&[Record {
+            paper: &paper,
+            meta: None,
+        }]);
+
+        assert!(ris.starts_with("TY  - JOUR\r\nAU  - Chen, Ming\r\n"));
+        assert!(ris.contains("TI  - A title with newline\r\n"));
+        assert!(ris.contains("DA  - 2020/02//\r\n"));
+        assert!(ris.contains("UR  - https://pubmed.ncbi.nlm.nih.gov/1/\r\n"));
+        assert!(ris.ends_with("ER  - \r\n\r\n"));
+    }
+}
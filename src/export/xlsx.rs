@@ -0,0 +1,285 @@
+use std::error::Error;
+
+use rust_xlsxwriter::{Format, FormatAlign, Url, Workbook, Worksheet};
+
+use crate::model::{GeneDisease, PaperCsvResult, PaperCsvSummary};
+
+use super::{doi_url, pubmed_url};
+
+/// Excel 单元格最多 32767 个字符
+const MAX_CELL_LEN: usize = 32767;
+/// Excel 超链接最长 2080 个字符
+const MAX_URL_LEN: usize = 2080;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Text,
+    /// 自动换行, 用于摘要等长文本
+    Wrap,
+    Number,
+    Pmid,
+    Doi,
+    /// 值本身是链接
+    Url,
+}
+
+pub struct Column {
+    pub header: String,
+    pub width: f64,
+    pub kind: Kind,
+}
+
+pub fn col(header: &str, width: f64, kind: Kind) -> Column {
+    Column {
+        header: header.to_string(),
+        width,
+        kind,
+    }
+}
+
+/// 可以写入 xlsx 的一行
+pub trait XlsxRow {
+    fn columns() -> Vec<Column>;
+    fn cells(&self) -> Vec<String>;
+}
+
+fn paper_columns() -> Vec<Column> {
+    vec![
+        col("PMID", 12.0, Kind::Pmid),
+        col("Title", 50.0, Kind::Wrap),
+        col("PubDateYear", 8.0, Kind::Number),
+        col("PubDateMonth", 8.0, Kind::Text),
+        col("JournalTitle", 30.0, Kind::Text),
+        col("JournalAbbr", 20.0, Kind::Text),
+        col("Abstract", 80.0, Kind::Wrap),
+        col("AuthorFirst", 20.0, Kind::Text),
+        col("AuthorLast", 20.0, Kind::Text),
+        col("PublicationType", 20.0, Kind::Text),
+        col("DOI", 28.0, Kind::Doi),
+        col("ISSN", 12.0, Kind::Text),
+        col("EpubYear", 8.0, Kind::Number),
+        col("EpubMonth", 8.0, Kind::Text),
+    ]
+}
+
+impl XlsxRow for PaperCsvResult {
+    fn columns() -> Vec<Column> {
+        paper_columns()
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.pmid.clone(),
+            self.title.clone(),
+            self.pubdate_year.clone(),
+            self.pubdate_month.clone(),
+            self.journal_title.clone(),
+            self.journal_abbr.clone(),
+            self.r#abstract.clone(),
+            self.author_first.clone(),
+            self.author_last.clone(),
+            self.publication_type.clone(),
+            self.doi.clone(),
+            self.issn.clone(),
+            self.epub_year.clone(),
+            self.epub_month.clone(),
+        ]
+    }
+}
+
+impl XlsxRow for PaperCsvSummary {
+    fn columns() -> Vec<Column> {
+        let mut v = paper_columns();
+        v.push(col("Summary", 60.0, Kind::Wrap));
+        v
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.pmid.clone(),
+            self.title.clone(),
+            self.pubdate_year.clone(),
+            self.pubdate_month.clone(),
+            self.journal_title.clone(),
+            self.journal_abbr.clone(),
+            self.r#abstract.clone(),
+            self.author_first.clone(),
+            self.author_last.clone(),
+            self.publication_type.clone(),
+            self.doi.clone(),
+            self.issn.clone(),
+            self.epub_year.clone(),
+            self.epub_month.clone(),
+            self.summary.clone(),
+        ]
+    }
+}
+
+impl XlsxRow for GeneDisease {
+    fn columns() -> Vec<Column> {
+        vec![
+            col("disease", 20.0, Kind::Text),
+            col("gene", 12.0, Kind::Text),
+            col("last_ref_year_mining", 20.0, Kind::Number),
+            col("n_pubmed_minging", 18.0, Kind::Number),
+        ]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let opt = |v: Option<usize>| v.map(|f| f.to_string()).unwrap_or_default();
+        vec![
+            self.disease.clone(),
+            self.gene.clone(),
+            opt(self.last_ref_year_mining),
+            opt(self.n_pubmed_minging),
+        ]
+    }
+}
+
+/// 元信息工作表的内容
+pub struct Meta<'a> {
+    pub query: &'a str,
+    /// 额外的统计, 例如 (`total`, 命中数量)
+    pub counts: Vec<(&'a str, usize)>,
+}
+
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn write_cell(
+    sheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    kind: Kind,
+    value: &str,
+    wrap: &Format,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let value = truncate(value, MAX_CELL_LEN);
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let url = match kind {
+        Kind::Pmid => pubmed_url(value),
+        Kind::Doi => doi_url(value),
+        Kind::Url => value.to_string(),
+        _ => String::new(),
+    };
+
+    match kind {
+        Kind::Number if value.parse::<f64>().is_ok() => {
+            sheet.write_number(row, col, value.parse::<f64>().unwrap())?;
+        }
+        Kind::Pmid | Kind::Doi | Kind::Url if url.len() <= MAX_URL_LEN => {
+            sheet.write_url_with_text(row, col, Url::new(url), value)?;
+        }
+        Kind::Wrap => {
+            sheet.write_string_with_format(row, col, value, wrap)?;
+        }
+        _ => {
+            sheet.write_string(row, col, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// 写入 xlsx: 冻结标题行, 摘要列自动换行, PMID/DOI 为超链接, 另有一个 `Query` 工作表记录查询和数量
+pub fn save_xlsx<T: XlsxRow>(
+    path: &str,
+    rows: &[T],
+    meta: &Meta,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cells = rows.iter().map(|f| f.cells()).collect::<Vec<Vec<String>>>();
+    save_cells(path, &T::columns(), &cells, meta)
+}
+
+/// 按指定的列写入, `rows` 的每一行和 `columns` 一一对应
+pub fn save_cells(
+    path: &str,
+    columns: &[Column],
+    rows: &[Vec<String>],
+    meta: &Meta,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold().set_background_color("#D9E1F2");
+    let wrap = Format::new().set_text_wrap().set_align(FormatAlign::Top);
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Results")?;
+    for (i, c) in columns.iter().enumerate() {
+        sheet.set_column_width(i as u16, c.width)?;
+        sheet.write_string_with_format(0, i as u16, &c.header, &header)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+
+    for (r, row) in rows.iter().enumerate() {
+        for (i, (c, value)) in columns.iter().zip(row.iter()).enumerate() {
+            write_cell(sheet, r as u32 + 1, i as u16, c.kind, value, &wrap)?;
+        }
+    }
+    if !columns.is_empty() {
+        sheet.autofilter(0, 0, rows.len() as u32, columns.len() as u16 - 1)?;
+    }
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Query")?;
+    sheet.set_column_width(0, 16)?;
+    sheet.set_column_width(1, 80)?;
+    sheet.write_string_with_format(0, 0, "query", &header)?;
+    sheet.write_string_with_format(0, 1, truncate(meta.query, MAX_CELL_LEN), &wrap)?;
+    sheet.write_string_with_format(1, 0, "date", &header)?;
+    sheet.write_string(
+        1,
+        1,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    )?;
+    sheet.write_string_with_format(2, 0, "rows", &header)?;
+    sheet.write_number(2, 1, rows.len() as f64)?;
+    for (i, (name, n)) in meta.counts.iter().enumerate() {
+        sheet.write_string_with_format(3 + i as u32, 0, *name, &header)?;
+        sheet.write_number(3 + i as u32, 1, *n as f64)?;
+    }
+
+    workbook.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("ab", truncate("abc", 2));
+        // 不截断在多字节字符中间
+        assert_eq!("a", truncate("a中", 2));
+    }
+
+    #[test]
+    fn test_save_xlsx() {
+        let path = std::env::temp_dir().join("test_save_xlsx.xlsx");
+        let rows = vec![PaperCsvResult {
+            pmid: "1".to_string(),
+            doi: "10.1000/xyz".to_string(),
+            pubdate_year: "2020".to_string(),
+            r#abstract: "中文摘要".to_string(),
+            ..Default::default()
+        }];
+        let meta = Meta {
+            query: "covid",
+            counts: vec![("total", 1)],
+        };
+
+        save_xlsx(path.to_str().unwrap(), &rows, &meta).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        let _ = std::fs::remove_file(&path);
+    }
+}
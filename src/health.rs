@@ -0,0 +1,206 @@
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use rocket::{get, http::Status, response::content, serde::json::Json};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+
+use crate::settings::data_path;
+
+/// 每项检查的超时时间
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// 检查结果的缓存时间, 避免探针频繁请求 NCBI
+const READY_CACHE: Duration = Duration::from_secs(15);
+
+static EINFO: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/einfo.fcgi?retmode=json";
+
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    /// 为 false 时失败不影响 ready, 只是降级
+    pub required: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+async fn check<F>(name: String, required: bool, f: F) -> Check
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let start = Instant::now();
+    let r = match tokio::time::timeout(CHECK_TIMEOUT, f).await {
+        Ok(r) => r,
+        Err(_) => Err(format!("timeout after {:?}", CHECK_TIMEOUT)),
+    };
+    Check {
+        name,
+        ok: r.is_ok(),
+        required,
+        latency_ms: start.elapsed().as_millis() as u64,
+        error: r.err(),
+    }
+}
+
+async fn mongo() -> Result<(), String> {
+    crate::slurm::db::Db::ping()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// 能返回任何 HTTP 响应就认为可以访问
+async fn http(url: &str) -> Result<(), String> {
+    reqwest::Client::builder()
+        .timeout(CHECK_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?
+        .get(url)
+        .send()
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// 只检查 SSH 端口能否连接, 不登录
+async fn tcp(addr: &str) -> Result<(), String> {
+    TcpStream::connect(addr)
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+fn data_dir() -> Result<(), String> {
+    let path = data_path(".ready_check");
+    std::fs::create_dir_all(&crate::settings::settings().data.dir)
+        .and_then(|_| std::fs::write(&path, b"ok"))
+        .and_then(|_| std::fs::remove_file(&path))
+        .map_err(|err| err.to_string())
+}
+
+async fn run_checks() -> Vec<Check> {
+    let clouds = crate::slurm::config::Config::clouds();
+    let ssh = rocket::futures::future::join_all(
+        clouds
+            .iter()
+            .map(|c| check(format!("ssh:{}", &c.info), false, tcp(&c.ssh_url))),
+    );
+
+    let (data, mongo, ncbi, llm, ssh) = tokio::join!(
+        check("data_dir".to_string(), true, async { data_dir() }),
+        check("mongodb".to_string(), false, mongo()),
+        check("ncbi".to_string(), false, http(EINFO)),
+        check("llm".to_string(), false, http(crate::openai::llm_url())),
+        ssh,
+    );
+
+    let mut v = vec![data, mongo, ncbi, llm];
+    v.extend(ssh);
+    v
+}
+
+/// 上次检查的时间和结果
+type CachedChecks = (Instant, Vec<Check>);
+
+static LAST: Lazy<Mutex<Option<CachedChecks>>> = Lazy::new(|| Mutex::new(None));
+
+async fn checks() -> Vec<Check> {
+    if let Some((t, v)) = LAST.lock().unwrap().as_ref() {
+        if t.elapsed() < READY_CACHE {
+            return v.clone();
+        }
+    }
+
+    let v = run_checks().await;
+    *LAST.lock().unwrap() = Some((Instant::now(), v.clone()));
+    v
+}
+
+/// 必需的检查都通过时 ready, 其他失败时为 `degraded`
+fn summary(checks: &[Check]) -> (bool, &'static str) {
+    let ready = checks.iter().all(|f| f.ok || !f.required);
+    let status = if !ready {
+        "unavailable"
+    } else if checks.iter().all(|f| f.ok) {
+        "ok"
+    } else {
+        "degraded"
+    };
+    (ready, status)
+}
+
+pub fn init() {
+    Lazy::force(&STARTED);
+}
+
+/// 存活检查, 进程能响应就返回 200
+#[get("/health")]
+pub async fn health() -> content::RawJson<String> {
+    content::RawJson(
+        json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_secs": STARTED.elapsed().as_secs(),
+        })
+        .to_string(),
+    )
+}
+
+/// 就绪检查, 必需的依赖不可用时返回 503
+#[get("/ready")]
+pub async fn ready() -> (Status, Json<Value>) {
+    let checks = checks().await;
+    let (ready, status) = summary(&checks);
+    let code = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (code, Json(json!({ "status": status, "checks": checks })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(ok: bool, required: bool) -> Check {
+        Check {
+            name: "x".to_string(),
+            ok,
+            required,
+            latency_ms: 0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!((true, "ok"), summary(&[c(true, true), c(true, false)]));
+        assert_eq!(
+            (true, "degraded"),
+            summary(&[c(true, true), c(false, false)])
+        );
+        assert_eq!(
+            (false, "unavailable"),
+            summary(&[c(false, true), c(true, false)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_timeout() {
+        let r = check("slow".to_string(), false, async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        })
+        .await;
+        assert!(!r.ok);
+        assert!(r.error.unwrap().contains("timeout"));
+    }
+}
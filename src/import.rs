@@ -0,0 +1,371 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use flate2::read::GzDecoder;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rocket::{get, post};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{Admin, Auth};
+use crate::settings::data_path;
+use crate::{
+    error::{api_ok, ApiResult, AppError},
+    eutils::parse_articles,
+    utils::file_exist,
+};
+
+static STATE_FILE: &str = "import.json";
+
+/// 每处理多少篇文献保存一次进度
+const SAVE_EVERY: usize = 1000;
+
+/// 导入进度, 写入 `data/import.json`, 中断后从 `current_file` 的 `current_articles` 处继续
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ImportProgress {
+    pub running: bool,
+    pub dir: String,
+    /// 已经处理完的文件
+    pub done_files: Vec<String>,
+    pub current_file: Option<String>,
+    /// 当前文件已处理的文献数量
+    pub current_articles: usize,
+    pub imported: usize,
+    /// 本地缓存已是同一版本或更新的版本
+    pub skipped: usize,
+    /// DeleteCitation 删除的数量
+    pub deleted: usize,
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+static PROGRESS: Lazy<Mutex<ImportProgress>> = Lazy::new(|| {
+    let p = std::fs::read_to_string(data_path(STATE_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str::<ImportProgress>(&s).ok())
+        .unwrap_or_default();
+    Mutex::new(ImportProgress {
+        running: false,
+        ..p
+    })
+});
+
+enum Item {
+    Article(String),
+    Delete(Vec<String>),
+}
+
+fn save_progress() {
+    let p = PROGRESS.lock().unwrap().clone();
+    if let Ok(s) = serde_json::to_string_pretty(&p) {
+        let _ = std::fs::write(data_path(STATE_FILE), s);
+    }
+}
+
+/// 目录下的 `*.xml.gz` 和 `*.xml`, 按文件名排序, 保证 baseline 在 update 之前
+fn list_files(dir: &str) -> io::Result<Vec<PathBuf>> {
+    let mut v = std::fs::read_dir(dir)?
+        .filter_map(|f| f.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().and_then(|s| s.to_str()).unwrap_or("");
+            name.ends_with(".xml.gz") || name.ends_with(".xml")
+        })
+        .collect::<Vec<PathBuf>>();
+    v.sort();
+    Ok(v)
+}
+
+fn open_file(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if path.extension().and_then(|e| e.to_str()) == Some("gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// 逐行读取 `PubmedArticleSet`, 每遇到一个完整的 `PubmedArticle` 或 `DeleteCitation` 回调一次,
+/// 回调返回 false 时停止
+fn scan<R: BufRead>(reader: R, mut f: impl FnMut(Item) -> bool) -> io::Result<()> {
+    let re_pmid = Regex::new(r"<PMID[^>]*>(\d+)</PMID>").unwrap();
+
+    let mut article: Option<String> = None;
+    let mut delete: Option<Vec<String>> = None;
+
+    for line in reader.lines() {
+        let line = line?;
+
+        if let Some(text) = article.as_mut() {
+            text.push_str(&line);
+            text.push('\n');
+            if line.contains("</PubmedArticle>") && !f(Item::Article(article.take().unwrap())) {
+                return Ok(());
+            }
+        } else if let Some(ids) = delete.as_mut() {
+            ids.extend(re_pmid.captures_iter(&line).map(|c| c[1].to_string()));
+            if line.contains("</DeleteCitation>") && !f(Item::Delete(delete.take().unwrap())) {
+                return Ok(());
+            }
+        } else if line.contains("<PubmedArticle>") {
+            let text = format!("{}\n", line);
+            if line.contains("</PubmedArticle>") {
+                if !f(Item::Article(text)) {
+                    return Ok(());
+                }
+            } else {
+                article = Some(text);
+            }
+        } else if line.contains("<DeleteCitation>") {
+            let ids = re_pmid
+                .captures_iter(&line)
+                .map(|c| c[1].to_string())
+                .collect::<Vec<String>>();
+            if line.contains("</DeleteCitation>") {
+                if !f(Item::Delete(ids)) {
+                    return Ok(());
+                }
+            } else {
+                delete = Some(ids);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 同 efetch 一样通过 `parse_articles` 解析, 本地缓存更旧或不存在时写入
+fn save_article(xml: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let text = format!("<PubmedArticleSet>\n{}</PubmedArticleSet>", xml);
+    let mut saved = false;
+    let cache = crate::cache::global();
+
+    for (paper, meta) in parse_articles(&text)? {
+        let id = paper.pmid.parse::<usize>()?;
+        if file_exist(&cache.csv_path(id)) {
+            if let Some(old) = cache.load_meta(id) {
+                if old.modified_at >= meta.modified_at {
+                    continue;
+                }
+            }
+        }
+
+        cache.store(&paper, &meta, true)?;
+        saved = true;
+    }
+
+    Ok(saved)
+}
+
+fn delete_citation(pmid: &str) -> bool {
+    if let Ok(id) = pmid.parse::<usize>() {
+        crate::cache::remove(id)
+    } else {
+        false
+    }
+}
+
+fn import_file(path: &Path, skip: usize) -> io::Result<()> {
+    let mut count = 0usize;
+
+    scan(open_file(path)?, |item| {
+        match item {
+            Item::Article(xml) => {
+                count += 1;
+                if count <= skip {
+                    return true;
+                }
+
+                let result = save_article(&xml);
+                let mut p = PROGRESS.lock().unwrap();
+                match result {
+                    Ok(true) => p.imported += 1,
+                    Ok(false) => p.skipped += 1,
+                    Err(err) => {
+                        log::warn!("import {:?} article {} error = {:?}", path, count, err);
+                        p.failed += 1;
+                    }
+                }
+                p.current_articles = count;
+            }
+            Item::Delete(ids) => {
+                let n = ids.iter().filter(|f| delete_citation(f)).count();
+                PROGRESS.lock().unwrap().deleted += n;
+            }
+        }
+
+        if count.is_multiple_of(SAVE_EVERY) {
+            save_progress();
+        }
+        true
+    })
+}
+
+/// 标记为正在导入, 已经在导入时返回 false; 检查和标记在同一个锁内, 避免同时开始两次导入
+fn begin(dir: &str) -> bool {
+    let mut p = PROGRESS.lock().unwrap();
+    if p.running {
+        return false;
+    }
+    if p.dir != dir {
+        *p = ImportProgress {
+            dir: dir.to_string(),
+            ..Default::default()
+        };
+    }
+    p.running = true;
+    p.error = None;
+    true
+}
+
+fn fail(err: String) {
+    let mut p = PROGRESS.lock().unwrap();
+    p.running = false;
+    p.error = Some(err);
+    drop(p);
+    save_progress();
+}
+
+/// 导入目录下全部 baseline/update 文件, 不需要网络; 命令行 `rust-eutils import <dir>` 使用
+pub fn import_dir(dir: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !begin(dir) {
+        return Err("import is already running".into());
+    }
+    run(dir)
+}
+
+/// 已经通过 `begin` 标记后执行导入
+fn run(dir: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let files = match list_files(dir) {
+        Ok(files) => files,
+        Err(err) => {
+            fail(err.to_string());
+            return Err(err.into());
+        }
+    };
+
+    for file in files {
+        let name = file
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let skip = {
+            let mut p = PROGRESS.lock().unwrap();
+            if p.done_files.contains(&name) {
+                continue;
+            }
+            if p.current_file.as_ref() != Some(&name) {
+                p.current_file = Some(name.clone());
+                p.current_articles = 0;
+            }
+            p.current_articles
+        };
+
+        log::info!("start import {:?}, skip {} articles", &file, skip);
+        if let Err(err) = import_file(&file, skip) {
+            log::warn!("import {:?} error = {:?}", &file, err);
+            fail(format!("{}: {}", name, err));
+            return Err(err.into());
+        }
+
+        {
+            let mut p = PROGRESS.lock().unwrap();
+            p.done_files.push(name);
+            p.current_file = None;
+            p.current_articles = 0;
+        }
+        save_progress();
+    }
+
+    PROGRESS.lock().unwrap().running = false;
+    save_progress();
+
+    Ok(())
+}
+
+/// 当前的导入进度
+pub fn progress() -> ImportProgress {
+    PROGRESS.lock().unwrap().clone()
+}
+
+#[post("/cache/import?<dir>")]
+pub async fn cache_import(_key: Admin, dir: String) -> ApiResult {
+    if !Path::new(&dir).is_dir() {
+        return Err(AppError::bad_request(format!("{} is not a directory", dir)));
+    }
+    if !begin(&dir) {
+        return Err(AppError::conflict("import is already running"));
+    }
+
+    log::info!("cache import dir = {}", &dir);
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = run(&dir) {
+            log::warn!("cache import error = {:?}", err);
+        }
+    });
+
+    let p = PROGRESS.lock().unwrap().clone();
+    api_ok(&p)
+}
+
+#[get("/cache/import")]
+pub async fn cache_import_status(_key: Auth) -> ApiResult {
+    let p = PROGRESS.lock().unwrap().clone();
+    api_ok(&p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_begin() {
+        assert!(begin("/tmp/import-test"));
+        assert!(!begin("/tmp/import-test"));
+        assert!(!begin("/tmp/other"));
+        PROGRESS.lock().unwrap().running = false;
+        assert!(begin("/tmp/other"));
+        PROGRESS.lock().unwrap().running = false;
+    }
+
+    #[test]
+    fn test_scan() {
+        let xml = r#"<?xml version="1.0" ?>
+<PubmedArticleSet>
+<PubmedArticle>
+    <MedlineCitation Status="MEDLINE" Owner="NLM">
+        <PMID Version="1">1</PMID>
+    </MedlineCitation>
+</PubmedArticle>
+<PubmedArticle>
+    <MedlineCitation Status="MEDLINE" Owner="NLM">
+        <PMID Version="1">2</PMID>
+    </MedlineCitation>
+</PubmedArticle>
+<DeleteCitation>
+    <PMID Version="1">3</PMID>
+    <PMID Version="1">4</PMID>
+</DeleteCitation>
+</PubmedArticleSet>"#;
+
+        let mut articles = Vec::new();
+        let mut deleted = Vec::new();
+        scan(xml.as_bytes(), |item| {
+            match item {
+                Item::Article(a) => articles.push(a),
+                Item::Delete(ids) => deleted.extend(ids),
+            }
+            true
+        })
+        .unwrap();
+
+        assert_eq!(2, articles.len());
+        assert!(articles[1].contains("<PMID Version=\"1\">2</PMID>"));
+        assert_eq!(vec!["3", "4"], deleted);
+    }
+}
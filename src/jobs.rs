@@ -0,0 +1,788 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, Write},
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+use rocket::{form::Form, fs::NamedFile, get, post};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::auth::{ApiKey, Auth, Llm, Ncbi};
+use crate::settings::data_path;
+use crate::{
+    disease::api::UploadCsv,
+    error::{api_ok, ApiResult, AppError},
+    eutils::SearchResult,
+    events::{emit, JobEvent},
+    export::{save_table, xlsx::Meta},
+    model::{GeneDisease, PaperCsvResult, PaperCsvSummary},
+    openai::api::{Upload, UploadQuery},
+};
+
+static JOB_DIR: &str = "jobs";
+
+/// total 任务每次 efetch 的数量
+const FETCH_CHUNK: usize = 20;
+/// total 任务每次 esearch 的数量
+const SEARCH_PAGE: usize = 200;
+
+/// 任务的参数, 上传的文件先复制到 `data/jobs/<id>.input.csv`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobKind {
+    Total {
+        term: String,
+    },
+    Save {
+        term: String,
+        cur_page: Option<usize>,
+        page_size: Option<usize>,
+        file_type: String,
+        columns: Option<String>,
+        profile: Option<String>,
+    },
+    Summary {
+        input: String,
+        question: String,
+        file_type: Option<String>,
+    },
+    SummaryQuery {
+        query: String,
+        question: String,
+        page_size: usize,
+        file_type: Option<String>,
+    },
+    Disease {
+        input: String,
+        file_type: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Done | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// 任务状态, 保存在 `data/jobs/<id>.json`, 每一条部分结果追加到 `data/jobs/<id>.partial.jsonl`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Job {
+    pub id: String,
+    /// 提交任务的 key 的名称, 只有它和管理员可以查看
+    #[serde(default)]
+    pub owner: String,
+    #[serde(flatten)]
+    pub kind: JobKind,
+    pub status: JobStatus,
+    /// 已完成的条目数量
+    pub done: usize,
+    pub total: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub error: Option<String>,
+    /// 完成后的结果, 例如下载 id
+    pub result: Option<serde_json::Value>,
+    /// 完成后生成的文件
+    pub artifact: Option<String>,
+}
+
+static JOBS: Lazy<Mutex<HashMap<String, Job>>> = Lazy::new(|| Mutex::new(load_jobs()));
+
+fn job_path(id: &str, ext: &str) -> String {
+    data_path(&format!("{}/{}.{}", JOB_DIR, id, ext))
+}
+
+fn save(job: &Job) {
+    let _ = fs::create_dir_all(data_path(JOB_DIR));
+    if let Ok(s) = serde_json::to_string_pretty(job) {
+        if let Err(err) = fs::write(job_path(&job.id, "json"), s) {
+            log::warn!("save job {} error = {:?}", &job.id, err);
+        }
+    }
+}
+
+fn load_jobs() -> HashMap<String, Job> {
+    fs::read_dir(data_path(JOB_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+        .filter_map(|p| fs::read_to_string(p).ok())
+        .filter_map(|s| serde_json::from_str::<Job>(&s).ok())
+        .map(|j| (j.id.clone(), j))
+        .collect()
+}
+
+pub fn get_job(id: &str) -> Option<Job> {
+    JOBS.lock().unwrap().get(id).cloned()
+}
+
+fn visible(job: &Job, key: &ApiKey) -> bool {
+    key.admin || job.owner == key.name
+}
+
+/// `key` 可以查看的任务, 其他 key 的任务同不存在
+pub fn get_owned_job(id: &str, key: &ApiKey) -> Option<Job> {
+    get_job(id).filter(|j| visible(j, key))
+}
+
+fn update(id: &str, f: impl FnOnce(&mut Job)) -> Option<Job> {
+    let mut map = JOBS.lock().unwrap();
+    let job = map.get_mut(id)?;
+    f(job);
+    job.updated_at = chrono::Utc::now().timestamp_millis();
+    save(job);
+    Some(job.clone())
+}
+
+fn cancelled(id: &str) -> bool {
+    get_job(id).map(|j| j.status == JobStatus::Cancelled) != Some(false)
+}
+
+/// 追加一条部分结果
+fn push_partial<T: Serialize>(id: &str, item: &T) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(job_path(id, "partial.jsonl"))?;
+    writeln!(file, "{}", serde_json::to_string(item)?)?;
+    update(id, |j| j.done += 1);
+    Ok(())
+}
+
+fn read_partial<T: DeserializeOwned>(id: &str) -> Vec<T> {
+    fs::File::open(job_path(id, "partial.jsonl"))
+        .map(|f| {
+            BufReader::new(f)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|l| serde_json::from_str(&l).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn new_job(kind: JobKind) -> Job {
+    let now = chrono::Utc::now().timestamp_millis();
+    Job {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        owner: String::new(),
+        kind,
+        status: JobStatus::Queued,
+        done: 0,
+        total: 0,
+        created_at: now,
+        updated_at: now,
+        error: None,
+        result: None,
+        artifact: None,
+    }
+}
+
+pub fn submit(mut job: Job, key: &ApiKey) -> Job {
+    job.owner = key.name.clone();
+    save(&job);
+    JOBS.lock().unwrap().insert(job.id.clone(), job.clone());
+
+    let id = job.id.clone();
+    crate::logging::spawn(async move { run(id).await });
+    job
+}
+
+type Outcome = (serde_json::Value, Option<String>);
+
+async fn run(id: String) {
+    let job = match update(&id, |j| j.status = JobStatus::Running) {
+        Some(j) => j,
+        None => return,
+    };
+    log::info!("job {} start, kind = {:?}", &id, &job.kind);
+
+    let res = execute(&id, job.kind).await;
+    let job = update(&id, |j| {
+        // 已经取消的任务保持取消的状态
+        if j.status != JobStatus::Running {
+            return;
+        }
+        match res {
+            Ok((result, artifact)) => {
+                j.status = JobStatus::Done;
+                j.result = Some(result);
+                j.artifact = artifact;
+            }
+            Err(err) => {
+                log::warn!("job {} error = {:?}", &j.id, err);
+                j.status = JobStatus::Failed;
+                j.error = Some(err.to_string());
+            }
+        }
+    });
+    if let Some(job) = job {
+        emit(&id, JobEvent::completed(&job));
+    }
+    log::info!("job {} finished", &id);
+}
+
+async fn execute(id: &str, kind: JobKind) -> Result<Outcome, Box<dyn Error + Send + Sync>> {
+    match kind {
+        JobKind::Total { term } => run_total(id, &term).await,
+        JobKind::Save {
+            term,
+            cur_page,
+            page_size,
+            file_type,
+            columns,
+            profile,
+        } => {
+            let fields =
+                crate::export::columns::request_fields(columns.as_deref(), profile.as_deref())?;
+            update(id, |j| j.total = 1);
+            let r = crate::eutils::esearch3("pubmed", &term, cur_page, page_size).await?;
+            let result = crate::export::save_list(&r, &file_type, &term, fields.as_deref())?;
+            update(id, |j| j.done = 1);
+            let artifact = crate::retention::resolve_token(
+                result["file_type"].as_str().unwrap_or("csv"),
+                result["id"].as_str().unwrap_or(""),
+            );
+            Ok((result, artifact))
+        }
+        JobKind::Summary {
+            input,
+            question,
+            file_type,
+        } => {
+            let mut v: Vec<PaperCsvResult> = Vec::new();
+            crate::utils::read_target_csv(&input, b',', &mut v)?;
+            run_summary(id, v, &question, &question, file_type.as_deref()).await
+        }
+        JobKind::SummaryQuery {
+            query,
+            question,
+            page_size,
+            file_type,
+        } => {
+            let p = crate::openai::summary_page_size(page_size);
+            let v = crate::eutils::esearch3("pubmed", &query, None, Some(p)).await?;
+            for (index, paper) in v.iter().enumerate() {
+                emit(
+                    id,
+                    JobEvent::Fetched {
+                        item: paper.pmid.clone(),
+                        index,
+                        data: serde_json::json!({ "title": paper.title }),
+                    },
+                );
+            }
+            let text = format!("{}\nquestion: {}", query, question);
+            run_summary(id, v, &question, &text, file_type.as_deref()).await
+        }
+        JobKind::Disease { input, file_type } => {
+            run_disease(id, &input, file_type.as_deref()).await
+        }
+    }
+}
+
+fn cancel_error() -> Box<dyn Error + Send + Sync> {
+    "job cancelled".into()
+}
+
+/// 分页读取全部 PMID, `fetch` 的参数为页码, 同 `eutils::fetch_ids` 的 `retstart`
+async fn collect_ids<F, Fut>(
+    mut fetch: F,
+    mut on_count: impl FnMut(usize),
+) -> Result<Vec<String>, Box<dyn Error + Send + Sync>>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: std::future::Future<Output = Result<SearchResult, Box<dyn Error + Send + Sync>>>,
+{
+    let mut ids: Vec<String> = Vec::new();
+    let mut page = 0;
+    loop {
+        let resp = fetch(page).await?;
+        let count = resp.esearchresult.count.parse::<usize>().unwrap_or(0);
+        ids.extend(resp.esearchresult.idlist.iter().cloned());
+        on_count(count);
+
+        if ids.len() >= count || resp.esearchresult.idlist.is_empty() {
+            break;
+        }
+        page += 1;
+    }
+    Ok(ids)
+}
+
+/// 同 `/api/pubmed/total`, 结果写入 `data/jobs/<id>.result.json`
+async fn run_total(id: &str, term: &str) -> Result<Outcome, Box<dyn Error + Send + Sync>> {
+    let ids = collect_ids(
+        |page| crate::eutils::fetch_ids("pubmed", term, page, SEARCH_PAGE),
+        |count| {
+            update(id, |j| j.total = count);
+        },
+    )
+    .await?;
+
+    // 重启后跳过已经下载的
+    let done = read_partial::<PaperCsvResult>(id)
+        .into_iter()
+        .map(|f| f.pmid)
+        .collect::<std::collections::HashSet<String>>();
+    update(id, |j| {
+        j.total = ids.len();
+        j.done = done.len();
+    });
+    let ids = ids
+        .into_iter()
+        .filter(|f| !done.contains(f))
+        .collect::<Vec<String>>();
+
+    let mut index = done.len();
+    for chunk in ids.chunks(FETCH_CHUNK) {
+        if cancelled(id) {
+            return Err(cancel_error());
+        }
+        for paper in crate::eutils::efetch("pubmed", chunk).await? {
+            push_partial(id, &paper)?;
+            emit(
+                id,
+                JobEvent::Fetched {
+                    item: paper.pmid.clone(),
+                    index,
+                    data: serde_json::json!({ "title": paper.title }),
+                },
+            );
+            index += 1;
+        }
+    }
+
+    let papers = read_partial::<PaperCsvResult>(id);
+    let path = job_path(id, "result.json");
+    fs::write(&path, serde_json::to_string(&papers)?)?;
+    Ok((serde_json::json!({ "count": papers.len() }), Some(path)))
+}
+
+async fn run_summary(
+    id: &str,
+    papers: Vec<PaperCsvResult>,
+    question: &str,
+    query: &str,
+    file_type: Option<&str>,
+) -> Result<Outcome, Box<dyn Error + Send + Sync>> {
+    let skip = read_partial::<PaperCsvSummary>(id).len();
+    update(id, |j| {
+        j.total = papers.len();
+        j.done = skip;
+    });
+
+    let total = papers.len();
+    for (index, paper) in papers.into_iter().enumerate().skip(skip) {
+        if cancelled(id) {
+            return Err(cancel_error());
+        }
+        let item = paper.pmid.clone();
+        emit(
+            id,
+            JobEvent::Started {
+                item: item.clone(),
+                index,
+                total,
+            },
+        );
+        // LLM 不可用时不一直重试, 这一篇不带总结, 任务继续
+        match crate::openai::try_summarize(&paper, question, || cancelled(id)).await {
+            Ok(summary) => {
+                let s = paper.to_summary(summary);
+                push_partial(id, &s)?;
+                emit(
+                    id,
+                    JobEvent::Summarized {
+                        item,
+                        index,
+                        summary: s.summary,
+                    },
+                );
+            }
+            Err(_) if cancelled(id) => return Err(cancel_error()),
+            Err(err) => {
+                log::warn!("job {} summary pmid = {} error = {:?}", id, &item, err);
+                push_partial(id, &paper.to_summary(String::new()))?;
+                emit(
+                    id,
+                    JobEvent::Failed {
+                        item,
+                        index,
+                        error: err.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    let rows = read_partial::<PaperCsvSummary>(id);
+    let meta = Meta {
+        query,
+        counts: vec![],
+    };
+    let (file_name, tid, ext) = save_table(&rows, file_type, &meta)?;
+    let token = crate::retention::issue_token(ext, tid);
+    Ok((
+        serde_json::json!({ "id": token, "file_type": ext }),
+        Some(file_name),
+    ))
+}
+
+async fn run_disease(
+    id: &str,
+    input: &str,
+    file_type: Option<&str>,
+) -> Result<Outcome, Box<dyn Error + Send + Sync>> {
+    let mut genes: Vec<GeneDisease> = Vec::new();
+    crate::utils::read_target_csv(input, b',', &mut genes)?;
+
+    let skip = read_partial::<GeneDisease>(id).len();
+    update(id, |j| {
+        j.total = genes.len();
+        j.done = skip;
+    });
+
+    let total = genes.len();
+    for (index, mut g) in genes.into_iter().enumerate().skip(skip) {
+        if cancelled(id) {
+            return Err(cancel_error());
+        }
+        let item = format!("{}|{}", &g.gene, &g.disease);
+        emit(
+            id,
+            JobEvent::Started {
+                item: item.clone(),
+                index,
+                total,
+            },
+        );
+        // 单条失败时保留空的结果继续
+        match crate::disease::query(&mut g).await {
+            Ok(_) => emit(
+                id,
+                JobEvent::Fetched {
+                    item,
+                    index,
+                    data: serde_json::to_value(&g)?,
+                },
+            ),
+            Err(err) => {
+                log::warn!("job {} {} error = {:?}", id, &item, err);
+                emit(
+                    id,
+                    JobEvent::Failed {
+                        item,
+                        index,
+                        error: err.to_string(),
+                    },
+                );
+            }
+        }
+        push_partial(id, &g)?;
+    }
+
+    let rows = read_partial::<GeneDisease>(id);
+    let found = rows.iter().filter(|f| f.n_pubmed_minging > Some(0)).count();
+    let meta = Meta {
+        query: crate::disease::QUERY_TEMPLATE,
+        counts: vec![("genes", rows.len()), ("with_papers", found)],
+    };
+    let (file_name, tid, ext) = save_table(&rows, file_type, &meta)?;
+    let token = crate::retention::issue_token(ext, tid);
+    Ok((
+        serde_json::json!({ "id": token, "file_type": ext }),
+        Some(file_name),
+    ))
+}
+
+/// 重启后继续没有完成的任务
+pub fn start() {
+    let ids = JOBS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|j| !j.status.finished())
+        .map(|j| j.id.clone())
+        .collect::<Vec<String>>();
+
+    for id in ids {
+        log::info!("resume job {}", &id);
+        update(&id, |j| j.status = JobStatus::Queued);
+        tokio::spawn(async move { run(id).await });
+    }
+}
+
+fn not_found(id: &str) -> AppError {
+    AppError::not_found(format!("job not found: {}", id))
+}
+
+#[post("/jobs/total?<term>")]
+pub async fn job_total(key: Ncbi, term: String) -> ApiResult {
+    api_ok(&submit(new_job(JobKind::Total { term }), &key.0))
+}
+
+#[post("/jobs/save?<term>&<cur_page>&<page_size>&<file_type>&<columns>&<profile>")]
+pub async fn job_save(
+    key: Ncbi,
+    term: String,
+    cur_page: Option<usize>,
+    page_size: Option<usize>,
+    file_type: Option<String>,
+    columns: Option<String>,
+    profile: Option<String>,
+) -> ApiResult {
+    let file_type = file_type.unwrap_or_else(|| "csv".to_string());
+    if crate::export::normalize_type(&file_type).is_none() {
+        return Err(AppError::bad_request(format!(
+            "unsupported file_type: {}",
+            file_type
+        )));
+    }
+    crate::export::columns::request_fields(columns.as_deref(), profile.as_deref())
+        .map_err(AppError::bad_request)?;
+
+    let job = new_job(JobKind::Save {
+        term,
+        cur_page,
+        page_size,
+        file_type,
+        columns,
+        profile,
+    });
+    api_ok(&submit(job, &key.0))
+}
+
+#[post("/jobs/summary", data = "<req>")]
+pub async fn job_summary(key: Llm, mut req: Form<Upload<'_>>) -> ApiResult {
+    let mut job = new_job(JobKind::Summary {
+        input: String::new(),
+        question: req.question.to_string(),
+        file_type: req.file_type.map(|f| f.to_string()),
+    });
+
+    let input = job_path(&job.id, "input.csv");
+    fs::create_dir_all(data_path(JOB_DIR))?;
+    req.file.copy_to(&input).await?;
+    if let JobKind::Summary { input: i, .. } = &mut job.kind {
+        *i = input;
+    }
+    api_ok(&submit(job, &key.0))
+}
+
+pub fn summary_query_job(req: &UploadQuery<'_>) -> Job {
+    new_job(JobKind::SummaryQuery {
+        query: req.query.to_string(),
+        question: req.question.to_string(),
+        page_size: req.page_size,
+        file_type: req.file_type.map(|f| f.to_string()),
+    })
+}
+
+#[post("/jobs/summary_with_query", data = "<req>")]
+pub async fn job_summary_with_query(key: Llm, req: Form<UploadQuery<'_>>) -> ApiResult {
+    api_ok(&submit(summary_query_job(&req), &key.0))
+}
+
+/// 上传的 gene-disease 文件复制到任务目录
+pub async fn disease_job(req: &mut UploadCsv<'_>) -> std::io::Result<Job> {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let input = job_path(&id, "input.csv");
+    fs::create_dir_all(data_path(JOB_DIR))?;
+    req.file.copy_to(&input).await?;
+
+    Ok(Job {
+        id,
+        ..new_job(JobKind::Disease {
+            input,
+            file_type: req.file_type.map(|f| f.to_string()),
+        })
+    })
+}
+
+#[post("/jobs/disease", data = "<req>")]
+pub async fn job_disease(key: Ncbi, mut req: Form<UploadCsv<'_>>) -> ApiResult {
+    let job = disease_job(&mut req).await?;
+    api_ok(&submit(job, &key.0))
+}
+
+#[get("/jobs")]
+pub async fn job_list(key: Auth) -> ApiResult {
+    let mut v = JOBS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|j| visible(j, &key.0))
+        .cloned()
+        .collect::<Vec<Job>>();
+    v.sort_by_key(|f| std::cmp::Reverse(f.created_at));
+    api_ok(&v)
+}
+
+#[get("/jobs/<id>")]
+pub async fn job_status(key: Auth, id: String) -> ApiResult {
+    api_ok(&get_owned_job(&id, &key.0).ok_or_else(|| not_found(&id))?)
+}
+
+/// 已经完成的部分结果
+#[get("/jobs/<id>/partial?<offset>&<limit>")]
+pub async fn job_partial(
+    key: Auth,
+    id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> ApiResult {
+    if get_owned_job(&id, &key.0).is_none() {
+        return Err(not_found(&id));
+    }
+    let v = read_partial::<serde_json::Value>(&id)
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(100))
+        .collect::<Vec<serde_json::Value>>();
+    api_ok(&v)
+}
+
+#[post("/jobs/<id>/cancel")]
+pub async fn job_cancel(key: Auth, id: String) -> ApiResult {
+    if get_owned_job(&id, &key.0).is_none() {
+        return Err(not_found(&id));
+    }
+    let job = update(&id, |j| {
+        if !j.status.finished() {
+            j.status = JobStatus::Cancelled;
+        }
+    });
+    api_ok(&job.ok_or_else(|| not_found(&id))?)
+}
+
+#[get("/jobs/<id>/download")]
+pub async fn job_download(key: Auth, id: String) -> Result<NamedFile, AppError> {
+    let job = get_owned_job(&id, &key.0).ok_or_else(|| not_found(&id))?;
+    if job.status != JobStatus::Done {
+        return Err(AppError::conflict(format!(
+            "job {} is {:?}",
+            id, job.status
+        )));
+    }
+    let artifact = job
+        .artifact
+        .ok_or_else(|| AppError::not_found(format!("job {} has no file", id)))?;
+    Ok(NamedFile::open(artifact).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_serde() {
+        let job = new_job(JobKind::Save {
+            term: "covid".to_string(),
+            cur_page: None,
+            page_size: Some(10),
+            file_type: "xlsx".to_string(),
+            columns: None,
+            profile: None,
+        });
+        let s = serde_json::to_string(&job).unwrap();
+        assert!(s.contains(r#""kind":"save""#));
+        assert!(s.contains(r#""status":"queued""#));
+
+        let j: Job = serde_json::from_str(&s).unwrap();
+        assert_eq!(job.id, j.id);
+        assert!(matches!(
+            j.kind,
+            JobKind::Save {
+                page_size: Some(10),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_visible() {
+        let key = |name: &str, admin: bool| {
+            serde_json::from_value::<ApiKey>(serde_json::json!({
+                "key": "k", "name": name, "admin": admin,
+                "ncbi_per_minute": 0, "llm_per_minute": 0,
+                "ncbi_daily": 0, "llm_daily": 0, "created_at": 0,
+            }))
+            .unwrap()
+        };
+        let job = Job {
+            owner: "lab".to_string(),
+            ..new_job(JobKind::Total {
+                term: "covid".to_string(),
+            })
+        };
+        assert!(visible(&job, &key("lab", false)));
+        assert!(!visible(&job, &key("other", false)));
+        assert!(visible(&job, &key("other", true)));
+    }
+
+    fn page(ids: &[String], page: usize, count: usize) -> SearchResult {
+        let start = crate::eutils::page_offset(page, SEARCH_PAGE).min(ids.len());
+        let end = (start + SEARCH_PAGE).min(ids.len());
+        serde_json::from_value(serde_json::json!({ "esearchresult": {
+            "count": count.to_string(),
+            "retmax": SEARCH_PAGE.to_string(),
+            "retstart": start.to_string(),
+            "idlist": &ids[start..end],
+            "querytranslation": "",
+        } }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_collect_ids() {
+        let all = (0..450).map(|f| f.to_string()).collect::<Vec<String>>();
+        let offsets = Mutex::new(Vec::new());
+        let ids = collect_ids(
+            |p| {
+                offsets
+                    .lock()
+                    .unwrap()
+                    .push(crate::eutils::page_offset(p, SEARCH_PAGE));
+                let r = page(&all, p, all.len());
+                async move { Ok(r) }
+            },
+            |_| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(all, ids);
+        assert_eq!(vec![0, 200, 400], *offsets.lock().unwrap());
+
+        // 总数多于实际返回的结果时, 遇到空页结束
+        let ids = collect_ids(
+            |p| {
+                let r = page(&all, p, 1000);
+                async move { Ok(r) }
+            },
+            |_| {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(450, ids.len());
+    }
+}
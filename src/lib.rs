@@ -0,0 +1,66 @@
+//! PubMed E-utilities 客户端, 解析和本地缓存, 以及基于 Rocket 的 http 服务.
+//!
+//! 默认开启全部 feature; 只使用客户端时可以关闭默认 feature:
+//!
+//! ```toml
+//! rust-eutils = { path = "../rust-eutils", default-features = false }
+//! ```
+//!
+//! * `openai`: LLM 总结
+//! * `slurm`: Slurm 作业同步, 依赖 mongo 和 ssh
+//! * `server`: http 服务和命令行, 包含 `openai` 和 `slurm`
+
+pub mod batch;
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub mod cache;
+pub mod config;
+pub mod disease;
+pub mod eutils;
+pub mod export;
+pub mod logging;
+pub mod metrics;
+pub mod model;
+pub mod retention;
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+pub mod search;
+pub mod sets;
+pub mod settings;
+pub mod snapshot;
+pub mod utils;
+
+#[cfg(feature = "openai")]
+pub mod openai;
+#[cfg(feature = "slurm")]
+pub mod slurm;
+
+#[cfg(feature = "server")]
+pub mod alerts;
+#[cfg(feature = "server")]
+pub mod auth;
+#[cfg(feature = "server")]
+pub mod cli;
+#[cfg(feature = "server")]
+pub mod dual;
+#[cfg(feature = "server")]
+pub mod error;
+#[cfg(feature = "server")]
+pub mod events;
+#[cfg(feature = "server")]
+pub mod health;
+#[cfg(feature = "server")]
+pub mod import;
+#[cfg(feature = "server")]
+pub mod jobs;
+#[cfg(feature = "server")]
+pub mod linker;
+#[cfg(feature = "server")]
+pub mod openapi;
+#[cfg(feature = "server")]
+pub mod refresh;
+#[cfg(feature = "server")]
+pub mod response;
+#[cfg(feature = "server")]
+pub mod server;
+
+pub use eutils::EutilsClient;
+pub use model::{PaperCsvResult, PaperCsvSummary};
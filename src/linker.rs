@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+use crate::settings::data_path;
 use crate::utils::{file_exist, read_target_csv};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -26,7 +27,7 @@ pub fn get_target_info(target: &str, left: bool) -> Vec<Core> {
     if target.contains("-") {
         let v: Vec<&str> = target.split("-").collect();
         let t = if left { v[0] } else { v[1] };
-        let csv = format!("data/dual/{}/{}.csv", target, t);
+        let csv = data_path(&format!("dual/{}/{}.csv", target, t));
         if !file_exist(&csv) {
             log::warn!("{} not exist, please check!!", &csv);
         } else {
@@ -44,7 +45,7 @@ pub fn get_target_info(target: &str, left: bool) -> Vec<Core> {
 
 pub fn get_link(target: &str, left: &str, right: &str) -> Vec<Linker> {
     if target.contains("-") {
-        let csv = format!("data/dual/{}/LINK/{}_{}.csv", target, left, right);
+        let csv = data_path(&format!("dual/{}/LINK/{}_{}.csv", target, left, right));
         if !file_exist(&csv) {
             log::warn!("{} not exist, please check!!", &csv);
         } else {
@@ -62,7 +63,7 @@ pub fn get_link(target: &str, left: &str, right: &str) -> Vec<Linker> {
 
 pub fn get_pair(target: &str, link: &str) -> Vec<Core> {
     if target.contains("-") {
-        let csv = format!("data/dual/{}/PAIR/{}", target, link);
+        let csv = data_path(&format!("dual/{}/PAIR/{}", target, link));
         if !file_exist(&csv) {
             log::warn!("{} not exist, please check!!", &csv);
         } else {
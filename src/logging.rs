@@ -0,0 +1,461 @@
+use std::{collections::BTreeMap, future::Future, path::Path, sync::Mutex};
+
+use chrono::{Local, NaiveDate};
+use log::{LevelFilter, Record};
+use log4rs::{
+    append::{
+        console::{ConsoleAppender, Target},
+        rolling_file::{
+            policy::compound::{
+                roll::fixed_window::FixedWindowRoller, trigger::Trigger, CompoundPolicy,
+            },
+            LogFile, RollingFileAppender,
+        },
+    },
+    config::{Appender, Logger, Root},
+    encode::{pattern::PatternEncoder, Encode, Write},
+    Handle,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+
+use crate::settings::Log;
+
+tokio::task_local! {
+    /// 当前请求或后台任务的 id, 日志中自动带上
+    static REQUEST_ID: String;
+}
+
+static STATE: OnceCell<Mutex<State>> = OnceCell::new();
+
+static FILE_NAME: &str = "rust-eutils.log";
+
+static API_KEY_QUERY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)([?&]api_key=)[^&\s#\x1b]*").unwrap());
+
+/// 当前的日志配置, 修改级别时用来重建 log4rs 的配置
+struct State {
+    handle: Handle,
+    target: Target,
+    log: Log,
+}
+
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// 新的 id, `prefix` 用来区分后台任务, 如 `sync-3f2a...`
+pub fn new_id(prefix: &str) -> String {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    if prefix.is_empty() {
+        id[..16].to_string()
+    } else {
+        format!("{}-{}", prefix, &id[..16])
+    }
+}
+
+/// 在 `id` 下执行, 其中的日志都带上这个 id
+pub async fn scope<F: Future>(id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(id, f).await
+}
+
+pub fn sync_scope<R>(id: String, f: impl FnOnce() -> R) -> R {
+    REQUEST_ID.sync_scope(id, f)
+}
+
+/// 带着当前的 id 启动后台任务, 任务的日志可以和提交它的请求关联
+pub fn spawn<F>(f: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match request_id() {
+        Some(id) => tokio::spawn(REQUEST_ID.scope(id, f)),
+        None => tokio::spawn(f),
+    }
+}
+
+/// 隐藏 url 中的 `api_key` 参数, rocket 的请求日志和错误信息中都有完整的 url
+pub fn redact(s: &str) -> std::borrow::Cow<'_, str> {
+    API_KEY_QUERY.replace_all(s, "${1}***")
+}
+
+/// 文本格式为 `{d} - [id] {m}`, json 格式每行一个对象
+#[derive(Debug)]
+struct Encoder {
+    json: bool,
+    pattern: PatternEncoder,
+}
+
+impl Encoder {
+    fn new(format: &str) -> Self {
+        Encoder {
+            json: format == "json",
+            pattern: PatternEncoder::new("{d} - {m}{n}"),
+        }
+    }
+}
+
+impl Encode for Encoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        let id = request_id();
+        let msg = record.args().to_string();
+        let msg = redact(&msg);
+        if self.json {
+            let v = serde_json::json!({
+                "time": Local::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "request_id": id,
+                "msg": msg,
+            });
+            writeln!(w, "{}", v)?;
+            return Ok(());
+        }
+
+        let text = match id {
+            Some(id) => format!("[{}] {}", id, msg),
+            None => msg.into_owned(),
+        };
+        self.pattern.encode(
+            w,
+            &Record::builder()
+                .args(format_args!("{}", text))
+                .level(record.level())
+                .target(record.target())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        )
+    }
+}
+
+/// 跨天或者超过 `max_size` 时轮转
+#[derive(Debug)]
+struct DailySizeTrigger {
+    max_size: u64,
+    day: Mutex<NaiveDate>,
+}
+
+impl DailySizeTrigger {
+    fn new(path: &Path, max_size: u64) -> Self {
+        // 已有的文件按修改时间算, 重启后也能按天轮转
+        let day = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map(|t| chrono::DateTime::<Local>::from(t).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        DailySizeTrigger {
+            max_size,
+            day: Mutex::new(day),
+        }
+    }
+
+    fn should_roll(&self, len: u64, today: NaiveDate) -> bool {
+        let mut day = self.day.lock().unwrap();
+        if len > 0 && (len > self.max_size || *day != today) {
+            *day = today;
+            return true;
+        }
+        false
+    }
+}
+
+impl Trigger for DailySizeTrigger {
+    fn trigger(&self, file: &LogFile) -> anyhow::Result<bool> {
+        Ok(self.should_roll(file.len_estimate(), Local::now().date_naive()))
+    }
+}
+
+fn build_config(target: Target, log: &Log) -> anyhow::Result<log4rs::Config> {
+    let stdout = ConsoleAppender::builder()
+        .target(target)
+        .encoder(Box::new(Encoder::new(&log.format)))
+        .build();
+
+    let path = Path::new(&log.dir).join(FILE_NAME);
+    let pattern = Path::new(&log.dir).join("rust-eutils.{}.log.gz");
+    let roller = FixedWindowRoller::builder().build(&pattern.to_string_lossy(), log.max_files)?;
+    let trigger = DailySizeTrigger::new(&path, log.max_size_mb * 1024 * 1024);
+    let file = RollingFileAppender::builder()
+        .encoder(Box::new(Encoder::new(&log.format)))
+        .build(
+            path,
+            Box::new(CompoundPolicy::new(Box::new(trigger), Box::new(roller))),
+        )?;
+
+    let mut builder = log4rs::Config::builder()
+        .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .appender(Appender::builder().build("file", Box::new(file)));
+    for (module, level) in &log.modules {
+        let level = level.parse::<LevelFilter>()?;
+        builder = builder.logger(Logger::builder().build(module, level));
+    }
+    let root = log.level.parse::<LevelFilter>()?;
+    Ok(builder.build(
+        Root::builder()
+            .appender("stdout")
+            .appender("file")
+            .build(root),
+    )?)
+}
+
+/// 用默认配置初始化, 读取配置文件之前的日志也能输出; 重复调用时忽略
+pub fn init(target: Target) {
+    if STATE.get().is_some() {
+        return;
+    }
+    let log = Log::default();
+    let handle = log4rs::init_config(build_config(target, &log).unwrap()).unwrap();
+    let _ = STATE.set(Mutex::new(State {
+        handle,
+        target,
+        log,
+    }));
+}
+
+/// 按配置文件中的 `[log]` 重新配置
+pub fn apply(log: &Log) -> anyhow::Result<()> {
+    let Some(state) = STATE.get() else {
+        return Ok(());
+    };
+    let mut state = state.lock().unwrap();
+    state.handle.set_config(build_config(state.target, log)?);
+    state.log = log.clone();
+    Ok(())
+}
+
+/// 当前的默认级别和按模块的级别
+pub fn levels() -> (String, BTreeMap<String, String>) {
+    match STATE.get() {
+        Some(state) => {
+            let state = state.lock().unwrap();
+            let modules = state.log.modules.clone().into_iter().collect();
+            (state.log.level.clone(), modules)
+        }
+        None => (Log::default().level, BTreeMap::new()),
+    }
+}
+
+/// 运行时修改级别, 不写回配置文件, 重启后恢复;
+/// `module` 为 `root` 时修改默认级别, `level` 为空时删除模块的级别
+pub fn set_level(module: &str, level: &str) -> Result<(), String> {
+    if !level.is_empty() && level.parse::<LevelFilter>().is_err() {
+        return Err(format!("invalid level: {}", level));
+    }
+    if module.is_empty() {
+        return Err("module is empty".to_string());
+    }
+    if module == "root" && level.is_empty() {
+        return Err("root level is required".to_string());
+    }
+
+    let mut log = match STATE.get() {
+        Some(state) => state.lock().unwrap().log.clone(),
+        None => return Err("log is not initialized".to_string()),
+    };
+    if module == "root" {
+        log.level = level.to_string();
+    } else if level.is_empty() {
+        log.modules.remove(module);
+    } else {
+        log.modules.insert(module.to_string(), level.to_string());
+    }
+    apply(&log).map_err(|err| err.to_string())?;
+    log::info!("set log level {} = {:?}", module, level);
+    Ok(())
+}
+
+/// http 接口和请求 id 的 fairing
+#[cfg(feature = "server")]
+pub mod api {
+    use std::time::Instant;
+
+    use rocket::{
+        fairing::{Fairing, Info, Kind},
+        get,
+        http::Header,
+        put,
+        route::{Handler, Outcome},
+        serde::json::Json,
+        Data, Request, Response, Route,
+    };
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::auth::Admin;
+    use crate::error::{api_ok, ApiResult, AppError};
+
+    static HEADER: &str = "X-Request-Id";
+
+    /// 请求的 id 和开始时间, 保存在 `local_cache`
+    struct RequestId(String, Instant);
+
+    /// 客户端传了合法的 `X-Request-Id` 时沿用, 否则新建
+    fn assign<'r>(req: &'r Request<'_>) -> &'r RequestId {
+        req.local_cache(|| {
+            let id = req
+                .headers()
+                .get_one(HEADER)
+                .filter(|f| valid_id(f))
+                .map(|f| f.to_string())
+                .unwrap_or_else(|| new_id(""));
+            RequestId(id, Instant::now())
+        })
+    }
+
+    fn valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// 给每个请求分配 id, 响应头返回 `X-Request-Id`, 结束时记录访问日志
+    pub struct Tracing;
+
+    #[rocket::async_trait]
+    impl Fairing for Tracing {
+        fn info(&self) -> Info {
+            Info {
+                name: "Request id",
+                kind: Kind::Request | Kind::Response,
+            }
+        }
+
+        async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+            assign(req);
+        }
+
+        async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+            let RequestId(id, start) = assign(req);
+            res.set_header(Header::new(HEADER, id.clone()));
+            sync_scope(id.clone(), || {
+                log::info!(
+                    "{} {} => {} {}ms",
+                    req.method(),
+                    req.uri().path(),
+                    res.status().code,
+                    start.elapsed().as_millis()
+                )
+            });
+        }
+    }
+
+    /// 在请求 id 下执行路由, eutils, openai, ssh 等调用的日志都带上 id
+    #[derive(Clone)]
+    struct Traced(Box<dyn Handler>);
+
+    #[rocket::async_trait]
+    impl Handler for Traced {
+        async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+            let id = assign(req).0.clone();
+            scope(id, self.0.handle(req, data)).await
+        }
+    }
+
+    pub fn traced(routes: Vec<Route>) -> Vec<Route> {
+        routes
+            .into_iter()
+            .map(|mut r| {
+                r.handler = Box::new(Traced(r.handler.clone()));
+                r
+            })
+            .collect()
+    }
+
+    #[derive(Debug, Serialize, Deserialize, JsonSchema)]
+    pub struct SetLevel {
+        /// 模块名, 如 `rust_eutils::eutils`, `root` 为默认级别
+        pub module: String,
+        /// off, error, warn, info, debug, trace, 为空时删除模块的级别
+        #[serde(default)]
+        pub level: String,
+    }
+
+    /// 当前的日志级别
+    #[get("/admin/log")]
+    pub async fn log_levels(_admin: Admin) -> ApiResult {
+        let (root, modules) = levels();
+        api_ok(&serde_json::json!({ "root": root, "modules": modules }))
+    }
+
+    /// 修改模块的日志级别, 重启后恢复为配置文件中的级别
+    #[put("/admin/log", format = "json", data = "<req>")]
+    pub async fn set_log_level(_admin: Admin, req: Json<SetLevel>) -> ApiResult {
+        set_level(req.module.trim(), &req.level.trim().to_lowercase())
+            .map_err(AppError::bad_request)?;
+        let (root, modules) = levels();
+        api_ok(&serde_json::json!({ "root": root, "modules": modules }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            "GET /api/pubmed/1?api_key=***&x=1",
+            redact("GET /api/pubmed/1?api_key=abc-123&x=1")
+        );
+        assert_eq!(
+            "GET /api/pubmed/1?x=1&API_KEY=*** text/html",
+            redact("GET /api/pubmed/1?x=1&API_KEY=abc text/html")
+        );
+        assert_eq!(
+            "\x1b[34m/a?api_key=***\x1b[0m",
+            redact("\x1b[34m/a?api_key=abc\x1b[0m")
+        );
+        assert_eq!("GET /api/pubmed/1", redact("GET /api/pubmed/1"));
+    }
+
+    #[test]
+    fn test_trigger() {
+        let day = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        let t = DailySizeTrigger {
+            max_size: 100,
+            day: Mutex::new(day),
+        };
+        assert!(!t.should_roll(50, day));
+        assert!(t.should_roll(101, day));
+        // 跨天, 空文件不轮转
+        let next = day.succ_opt().unwrap();
+        assert!(!t.should_roll(0, next));
+        assert!(t.should_roll(1, next));
+        assert!(!t.should_roll(1, next));
+    }
+
+    #[test]
+    fn test_encoder() {
+        let record = Record::builder()
+            .args(format_args!("hello"))
+            .level(log::Level::Warn)
+            .target("rust_eutils::eutils")
+            .build();
+
+        let mut buf = log4rs::encode::writer::simple::SimpleWriter(Vec::new());
+        sync_scope("abc".to_string(), || {
+            Encoder::new("json").encode(&mut buf, &record).unwrap()
+        });
+        let v: serde_json::Value = serde_json::from_slice(&buf.0).unwrap();
+        assert_eq!("abc", v["request_id"]);
+        assert_eq!("WARN", v["level"]);
+        assert_eq!("hello", v["msg"]);
+
+        let mut buf = log4rs::encode::writer::simple::SimpleWriter(Vec::new());
+        sync_scope("abc".to_string(), || {
+            Encoder::new("text").encode(&mut buf, &record).unwrap()
+        });
+        assert!(String::from_utf8(buf.0)
+            .unwrap()
+            .ends_with(" - [abc] hello\n"));
+
+        let mut buf = log4rs::encode::writer::simple::SimpleWriter(Vec::new());
+        Encoder::new("text").encode(&mut buf, &record).unwrap();
+        assert!(String::from_utf8(buf.0).unwrap().ends_with(" - hello\n"));
+    }
+}
@@ -0,0 +1,215 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, TextEncoder,
+};
+
+/// 外部请求的耗时分布, 秒
+static LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+pub static EUTILS_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "eutils_requests_total",
+        "E-utilities requests by type and HTTP status",
+        &["type", "status"]
+    )
+    .unwrap()
+});
+
+pub static EUTILS_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "eutils_request_duration_seconds",
+        "E-utilities request latency",
+        &["type"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static LIMITER_WAIT: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "eutils_limiter_wait_seconds",
+        "Time spent waiting for an E-utilities request slot",
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static CACHE_HITS: Lazy<IntCounter> =
+    Lazy::new(|| register_int_counter!("paper_cache_hits_total", "Paper cache hits").unwrap());
+
+pub static CACHE_MISSES: Lazy<IntCounter> =
+    Lazy::new(|| register_int_counter!("paper_cache_misses_total", "Paper cache misses").unwrap());
+
+pub static LLM_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!("llm_requests_total", "LLM calls by result", &["status"]).unwrap()
+});
+
+pub static LLM_LATENCY: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "llm_request_duration_seconds",
+        "LLM call latency",
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static LLM_TOKENS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "llm_tokens_total",
+        "LLM token usage, kind is prompt or completion",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static SLURM_SYNC_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "slurm_sync_duration_seconds",
+        "Duration of one Slurm sync cycle per cloud",
+        &["cloud"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static SLURM_JOBS_FOUND: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "slurm_sync_jobs_found_total",
+        "Jobs read from sacct per cloud",
+        &["cloud"]
+    )
+    .unwrap()
+});
+
+pub static SLURM_NOTIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "slurm_notifications_sent_total",
+        "DingTalk notifications sent per cloud",
+        &["cloud"]
+    )
+    .unwrap()
+});
+
+pub static SLURM_SSH_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "slurm_ssh_failures_total",
+        "SSH login failures per cloud",
+        &["cloud"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latency by route",
+        &["method", "route", "status"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+/// 指标在第一次使用时注册, 这里提前注册, 没有标签的指标在没有数据时也会输出
+fn init() {
+    Lazy::force(&EUTILS_REQUESTS);
+    Lazy::force(&EUTILS_LATENCY);
+    Lazy::force(&LIMITER_WAIT);
+    Lazy::force(&CACHE_HITS);
+    Lazy::force(&CACHE_MISSES);
+    Lazy::force(&LLM_REQUESTS);
+    Lazy::force(&LLM_LATENCY);
+    Lazy::force(&LLM_TOKENS);
+    Lazy::force(&SLURM_SYNC_DURATION);
+    Lazy::force(&SLURM_JOBS_FOUND);
+    Lazy::force(&SLURM_NOTIFICATIONS);
+    Lazy::force(&SLURM_SSH_FAILURES);
+    Lazy::force(&HTTP_LATENCY);
+}
+
+pub fn render() -> String {
+    init();
+    let mut buf = Vec::new();
+    if let Err(err) = TextEncoder::new().encode(&prometheus::gather(), &mut buf) {
+        log::warn!("encode metrics error = {:?}", err);
+    }
+    String::from_utf8(buf).unwrap_or_default()
+}
+
+/// http 服务的耗时统计和抓取接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{
+        fairing::{Fairing, Info, Kind},
+        get,
+        http::ContentType,
+        Data, Request, Response,
+    };
+
+    use std::time::Instant;
+
+    use super::*;
+
+    /// 请求开始的时间, 保存在 `local_cache`
+    struct Start(Option<Instant>);
+
+    /// 记录每个路由的耗时
+    pub struct Metrics;
+
+    #[rocket::async_trait]
+    impl Fairing for Metrics {
+        fn info(&self) -> Info {
+            Info {
+                name: "Prometheus metrics",
+                kind: Kind::Request | Kind::Response,
+            }
+        }
+
+        async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+            req.local_cache(|| Start(Some(Instant::now())));
+        }
+
+        async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+            if let Some(start) = req.local_cache(|| Start(None)).0 {
+                let route = req
+                    .route()
+                    .map(|r| r.uri.to_string())
+                    .unwrap_or_else(|| "unmatched".to_string());
+                HTTP_LATENCY
+                    .with_label_values(&[
+                        req.method().as_str(),
+                        &route,
+                        &res.status().code.to_string(),
+                    ])
+                    .observe(start.elapsed().as_secs_f64());
+            }
+        }
+    }
+
+    /// Prometheus 抓取的接口
+    #[get("/metrics")]
+    pub async fn metrics() -> (ContentType, String) {
+        (
+            ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+            render(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        EUTILS_REQUESTS.with_label_values(&["esearch", "200"]).inc();
+        LLM_TOKENS.with_label_values(&["prompt"]).inc_by(12);
+
+        let s = render();
+        assert!(s.contains(r#"eutils_requests_total{status="200",type="esearch"}"#));
+        assert!(s.contains(r#"llm_tokens_total{kind="prompt"}"#));
+        // 没有标签的指标即使没有数据也会输出
+        assert!(s.contains("# TYPE paper_cache_hits_total counter"));
+        assert!(s.contains("# TYPE eutils_limiter_wait_seconds histogram"));
+    }
+}
@@ -1,10 +1,11 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::{fs::File, io};
 
-use crate::utils::{file_exist, get_download_path, get_pmid_path_by_id};
+use crate::utils::get_download_path;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct GeneDisease {
     pub disease: String,
     pub gene: String,
@@ -35,10 +36,46 @@ pub struct MedlineCitation {
     pub status: String,
     #[serde(rename = "Owner")]
     pub owner: String,
+    pub date_revised: Option<DateRevised>,
+    pub mesh_heading_list: Option<MeshHeadingList>,
     // #[serde(rename = "IndexingMethod")]
     // pub indexing_method: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DateRevised {
+    pub year: String,
+    pub month: String,
+    pub day: String,
+}
+
+impl DateRevised {
+    pub fn to_date_str(&self) -> String {
+        format!("{}/{}/{}", self.year, self.month, self.day)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MeshHeadingList {
+    #[serde(rename = "MeshHeading")]
+    pub mesh_headings: Vec<MeshHeading>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MeshHeading {
+    pub descriptor_name: DescriptorName,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DescriptorName {
+    #[serde(rename = "$value")]
+    pub value: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Article {
@@ -51,6 +88,14 @@ pub struct Article {
     pub pub_model: String,
     #[serde(rename = "ArticleDate")]
     pub article_date: Option<ArticleDate>,
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Pagination {
+    #[serde(rename = "MedlinePgn")]
+    pub medline_pgn: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,6 +112,8 @@ pub struct Journal {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct JournalIssue {
+    pub volume: Option<String>,
+    pub issue: Option<String>,
     pub pub_date: PubDate,
     #[serde(rename = "CitedMedium")]
     pub cited_medium: Option<String>,
@@ -102,13 +149,16 @@ pub struct AuthorList {
     pub authors: Vec<Author>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct Author {
     // #[serde(rename = "ValidYN")]
     // pub valid_yn: String,
     pub last_name: Option<String>,
     pub fore_name: Option<String>,
+    pub initials: Option<String>,
+    /// 团体作者
+    pub collective_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -155,7 +205,7 @@ pub struct ArticleId {
     pub value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(rename_all = "PascalCase")]
 pub struct PaperCsvResult {
     #[serde(rename = "PMID")]
@@ -179,7 +229,7 @@ pub struct PaperCsvResult {
     pub epub_month: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(rename_all = "PascalCase")]
 pub struct PaperCsvSummary {
     #[serde(rename = "PMID")]
@@ -229,20 +279,8 @@ impl PaperCsvResult {
     fn get_csv_header() -> String {
         format!("PMID,Title,PubDateYear,PubDateMonth,JournalTitle,JournalAbbr,Abstract,AuthorFirst,AuthorLast,PublicationType,DOI,ISSN,EpubYear,EpubMonth")
     }
-    // 保存到 CSV 文件
-    pub fn save_csv(&self) -> io::Result<()> {
-        if self.pmid.is_empty() {
-            return Ok(());
-        }
-
-        let id = self.pmid.parse::<usize>().unwrap();
-
-        let file_name = get_pmid_path_by_id(id);
-
-        if file_exist(&file_name) {
-            return Ok(());
-        }
-
+    /// 写入单篇文献的 csv, 覆盖已有的文件
+    pub fn write_csv(&self, file_name: &str) -> io::Result<()> {
         let path = std::path::Path::new(&file_name);
         let prefix = path.parent().unwrap();
         std::fs::create_dir_all(prefix)?;
@@ -258,22 +296,27 @@ impl PaperCsvResult {
         Ok(())
     }
 
-    pub fn save_list_csv(list: &Vec<PaperCsvResult>) -> io::Result<serde_json::Value> {
+    pub fn save_list_csv(list: &[PaperCsvResult]) -> io::Result<serde_json::Value> {
         let (file_name, now) = get_download_path("csv")?;
+        Self::write_list_csv(list, &file_name)?;
 
-        // 创建文件并写入标题行
+        Ok(serde_json::json!({ "id": crate::retention::issue_token("csv", now) }))
+    }
+
+    /// 列表写入 csv 文件, 包含标题行
+    pub fn write_list_csv(list: &[PaperCsvResult], file_name: &str) -> io::Result<()> {
         let mut file: File = File::create(file_name)?;
+        write!(file, "{}", Self::list_csv(list))
+    }
 
+    /// 多篇文献的 csv 内容, 包含标题行
+    pub fn list_csv(list: &[PaperCsvResult]) -> String {
         let header = Self::get_csv_header();
         let content = list
             .iter()
             .map(|f| f.get_row_str())
             .collect::<Vec<String>>();
-        let c = format!("{}\n{}", header.as_str(), content.join("\n").as_str());
-
-        writeln!(file, "{}", c)?;
-
-        Ok(serde_json::json!({ "id": now }))
+        format!("{}\n{}\n", header.as_str(), content.join("\n").as_str())
     }
 
     pub fn to_summary(&self, summary: String) -> PaperCsvSummary {
@@ -0,0 +1,815 @@
+use once_cell::sync::Lazy;
+use rocket::{get, response::content};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::auth::{ApiKey, IssueKey};
+use crate::{
+    alerts::{Alert, SearchRequest},
+    batch::{api::FetchRequest, Lookup},
+    cache::PrefetchRequest,
+    eutils::SearchResult,
+    events::JobEvent,
+    export::{columns::Profile, csl::CslItem},
+    jobs::Job,
+    logging::api::SetLevel,
+    model::{GeneDisease, PaperCsvResult, PaperCsvSummary},
+    openai::ChatRequest,
+    sets::{SetRequest, SetResult},
+    snapshot::{api::SnapshotRequest, Diff, Snapshot},
+};
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+/// 请求体
+enum Body {
+    None,
+    Json(SchemaFn),
+    /// multipart 表单, (字段, 类型, 是否必填), 类型为 `file` 时是上传的文件
+    Form(&'static [(&'static str, &'static str, bool)]),
+}
+
+/// 成功时的返回
+enum Resp {
+    /// `{"ok": ...}`
+    Ok(SchemaFn),
+    /// 下载文件
+    File(&'static [&'static str]),
+    /// 按 `Accept` 返回 `{"ok": ...}` 或其他格式的文件
+    Negotiated(SchemaFn, &'static [&'static str]),
+    /// `text/event-stream`, 事件的格式为 `JobEvent`
+    Events,
+    /// 不包装的 json
+    Raw,
+    Html,
+}
+
+/// 一个接口, 路径参数写成 `{term}`, `params` 中的其他参数为 query 参数, `public` 为不需要 API key
+struct Op {
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    params: &'static [(&'static str, &'static str, bool)],
+    body: Body,
+    resp: Resp,
+    public: bool,
+}
+
+const fn op(
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+) -> Op {
+    Op {
+        method,
+        path,
+        tag,
+        summary,
+        params: &[],
+        body: Body::None,
+        resp: Resp::Raw,
+        public: false,
+    }
+}
+
+impl Op {
+    fn params(self, params: &'static [(&'static str, &'static str, bool)]) -> Self {
+        Op { params, ..self }
+    }
+
+    fn body(self, body: Body) -> Self {
+        Op { body, ..self }
+    }
+
+    fn ok(self, f: SchemaFn) -> Self {
+        Op {
+            resp: Resp::Ok(f),
+            ..self
+        }
+    }
+
+    fn file(self, types: &'static [&'static str]) -> Self {
+        Op {
+            resp: Resp::File(types),
+            ..self
+        }
+    }
+
+    fn negotiated(self, f: SchemaFn, types: &'static [&'static str]) -> Self {
+        Op {
+            resp: Resp::Negotiated(f, types),
+            ..self
+        }
+    }
+
+    fn events(self) -> Self {
+        Op {
+            resp: Resp::Events,
+            ..self
+        }
+    }
+
+    fn public(self) -> Self {
+        Op {
+            public: true,
+            ..self
+        }
+    }
+
+    fn html(self) -> Self {
+        Op {
+            resp: Resp::Html,
+            ..self
+        }
+    }
+}
+
+static TABLE: &[&str] = &[
+    "text/csv",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+];
+
+static SUMMARY_FORM: &[(&str, &str, bool)] = &[
+    ("question", "string", true),
+    ("file", "file", true),
+    ("file_type", "string", false),
+];
+
+static SUMMARY_QUERY_FORM: &[(&str, &str, bool)] = &[
+    ("question", "string", true),
+    ("query", "string", true),
+    ("page_size", "integer", true),
+    ("file_type", "string", false),
+];
+
+static DISEASE_FORM: &[(&str, &str, bool)] =
+    &[("file", "file", true), ("file_type", "string", false)];
+
+static SAVE_PARAMS: &[(&str, &str, bool)] = &[
+    ("term", "string", true),
+    ("cur_page", "integer", false),
+    ("page_size", "integer", false),
+    ("file_type", "string", false),
+    ("columns", "string", false),
+    ("profile", "string", false),
+];
+
+/// 全部接口, 新增路由时需要加到这里, 否则 `test_all_routes_documented` 会失败
+fn operations() -> Vec<Op> {
+    vec![
+        // pubmed
+        op("get", "/api/pubmed/{term}", "pubmed", "分页检索 PubMed")
+            .params(&[
+                ("term", "string", true),
+                ("cur_page", "integer", false),
+                ("page_size", "integer", false),
+            ])
+            .ok(schema::<Vec<PaperCsvResult>>),
+        op(
+            "get",
+            "/api/pubmed/save/{term}",
+            "pubmed",
+            "检索并保存为 csv/ris/bib/xml/xlsx, 返回下载 id",
+        )
+        .params(SAVE_PARAMS)
+        .ok(schema::<Value>),
+        op("get", "/api/pubmed/total/{term}", "pubmed", "检索全部结果")
+            .params(&[("term", "string", true)])
+            .ok(schema::<Vec<PaperCsvResult>>),
+        op(
+            "post",
+            "/api/pubmed/fetch",
+            "pubmed",
+            "按 PMID 或 DOI 批量获取论文, 最多 5000 个, 按 Accept 返回 json, csv 或 ris; csv 和 ris 没有找到的 id 数在响应头 X-Not-Found-Count, 前 100 个在 X-Not-Found",
+        )
+        .body(Body::Json(schema::<FetchRequest>))
+        .negotiated(
+            schema::<Lookup>,
+            &["text/csv", "application/x-research-info-systems"],
+        ),
+        op(
+            "post",
+            "/api/pubmed/sets",
+            "pubmed",
+            "多个检索式或快照按 PMID 求并集, 交集或差集, 返回每个 PMID 的来源",
+        )
+        .body(Body::Json(schema::<SetRequest>))
+        .ok(schema::<SetResult>),
+        op(
+            "get",
+            "/api/pubmed/pmid/{pmid}",
+            "pubmed",
+            "按 pmid 获取论文",
+        )
+        .params(&[("pmid", "integer", true)])
+        .ok(schema::<PaperCsvResult>),
+        op(
+            "get",
+            "/api/pubmed/cite",
+            "pubmed",
+            "生成引用, style 为 vancouver/apa/nature/csl",
+        )
+        .params(&[("pmids", "string", true), ("style", "string", false)])
+        .ok(schema::<Vec<CslItem>>),
+        op("get", "/api/local/search", "pubmed", "本地缓存的全文检索")
+            .params(&[
+                ("q", "string", true),
+                ("cur_page", "integer", false),
+                ("page_size", "integer", false),
+                ("year", "string", false),
+                ("journal", "string", false),
+            ])
+            .ok(schema::<Value>),
+        op("get", "/pubmed/{pmid}", "pubmed", "下载论文的 xml")
+            .params(&[("pmid", "integer", true)])
+            .file(&["application/xml"]),
+        op(
+            "get",
+            "/download/{file_type}/{id}",
+            "pubmed",
+            "按下载 id 下载文件",
+        )
+        .params(&[("file_type", "string", true), ("id", "string", true)])
+        .file(&["application/octet-stream"])
+        .public(),
+        // 导出配置
+        op(
+            "get",
+            "/api/export/profiles",
+            "export",
+            "可用的字段和已保存的导出配置",
+        )
+        .ok(schema::<Value>),
+        op("post", "/api/export/profiles", "export", "保存导出配置")
+            .body(Body::Json(schema::<Profile>))
+            .ok(schema::<Profile>),
+        op(
+            "delete",
+            "/api/export/profiles/{name}",
+            "export",
+            "删除导出配置",
+        )
+        .params(&[("name", "string", true)])
+        .ok(schema::<Value>),
+        // openai
+        op("post", "/api/openai/chat", "openai", "对话")
+            .body(Body::Json(schema::<ChatRequest>))
+            .ok(schema::<String>),
+        op(
+            "post",
+            "/api/openai/summary",
+            "openai",
+            "上传 pmid 文件, 对每篇摘要回答 question",
+        )
+        .body(Body::Form(SUMMARY_FORM))
+        .file(TABLE),
+        op(
+            "post",
+            "/api/openai/summary_with_query",
+            "openai",
+            "检索后对每篇摘要回答 question, 返回下载 id",
+        )
+        .body(Body::Form(SUMMARY_QUERY_FORM))
+        .ok(schema::<Value>),
+        op(
+            "post",
+            "/api/openai/summary_with_query/events",
+            "openai",
+            "提交 summary_with_query 任务并返回进度事件",
+        )
+        .body(Body::Form(SUMMARY_QUERY_FORM))
+        .events(),
+        // gene-disease
+        op(
+            "post",
+            "/api/query/disease",
+            "disease",
+            "上传 gene,disease 文件, 统计相关论文",
+        )
+        .body(Body::Form(DISEASE_FORM))
+        .file(TABLE),
+        op(
+            "post",
+            "/api/query/disease/events",
+            "disease",
+            "提交 gene-disease 任务并返回进度事件",
+        )
+        .body(Body::Form(DISEASE_FORM))
+        .events(),
+        // 后台任务
+        op("post", "/api/jobs/total", "jobs", "后台检索全部结果")
+            .params(&[("term", "string", true)])
+            .ok(schema::<Job>),
+        op("post", "/api/jobs/save", "jobs", "后台检索并保存")
+            .params(SAVE_PARAMS)
+            .ok(schema::<Job>),
+        op(
+            "post",
+            "/api/jobs/summary",
+            "jobs",
+            "后台执行 openai/summary",
+        )
+        .body(Body::Form(SUMMARY_FORM))
+        .ok(schema::<Job>),
+        op(
+            "post",
+            "/api/jobs/summary_with_query",
+            "jobs",
+            "后台执行 openai/summary_with_query",
+        )
+        .body(Body::Form(SUMMARY_QUERY_FORM))
+        .ok(schema::<Job>),
+        op(
+            "post",
+            "/api/jobs/disease",
+            "jobs",
+            "后台执行 query/disease",
+        )
+        .body(Body::Form(DISEASE_FORM))
+        .ok(schema::<Job>),
+        op("get", "/api/jobs", "jobs", "全部任务").ok(schema::<Vec<Job>>),
+        op("get", "/api/jobs/{id}", "jobs", "任务状态")
+            .params(&[("id", "string", true)])
+            .ok(schema::<Job>),
+        op(
+            "get",
+            "/api/jobs/{id}/partial",
+            "jobs",
+            "已经完成的部分结果",
+        )
+        .params(&[
+            ("id", "string", true),
+            ("offset", "integer", false),
+            ("limit", "integer", false),
+        ])
+        .ok(schema::<Vec<Value>>),
+        op("post", "/api/jobs/{id}/cancel", "jobs", "取消任务")
+            .params(&[("id", "string", true)])
+            .ok(schema::<Job>),
+        op(
+            "get",
+            "/api/jobs/{id}/download",
+            "jobs",
+            "下载任务生成的文件",
+        )
+        .params(&[("id", "string", true)])
+        .file(&["application/octet-stream"]),
+        op("get", "/api/jobs/{id}/events", "jobs", "任务进度事件")
+            .params(&[("id", "string", true)])
+            .events(),
+        // 缓存
+        op("get", "/api/cache/stats", "cache", "缓存统计").ok(schema::<Value>),
+        op("post", "/api/cache/purge", "cache", "删除缓存")
+            .params(&[
+                ("from", "integer", false),
+                ("to", "integer", false),
+                ("older_than_days", "integer", false),
+            ])
+            .ok(schema::<Value>),
+        op("post", "/api/cache/prefetch", "cache", "预先下载 pmid")
+            .body(Body::Json(schema::<PrefetchRequest>))
+            .ok(schema::<Value>),
+        op(
+            "post",
+            "/api/cache/scan",
+            "cache",
+            "检查缓存文件, fix 为 true 时重新下载损坏的文件",
+        )
+        .params(&[("fix", "boolean", false)])
+        .ok(schema::<Value>),
+        op("get", "/api/cache/scan", "cache", "上次检查的结果").ok(schema::<Value>),
+        op("get", "/api/cache/refresh", "cache", "定时刷新的状态").ok(schema::<Value>),
+        op(
+            "post",
+            "/api/cache/import",
+            "cache",
+            "导入 PubMed baseline 目录",
+        )
+        .params(&[("dir", "string", true)])
+        .ok(schema::<Value>),
+        op("get", "/api/cache/import", "cache", "导入的进度").ok(schema::<Value>),
+        // dual
+        op("get", "/api/dual/list", "dual", "全部靶点组合").ok(schema::<Vec<String>>),
+        op(
+            "get",
+            "/api/dual/{target}/{left_or_right}",
+            "dual",
+            "靶点左右两边的片段, 0 为左边",
+        )
+        .params(&[
+            ("target", "string", true),
+            ("left_or_right", "integer", true),
+        ])
+        .ok(schema::<Vec<Value>>),
+        op("get", "/api/dual/pair", "dual", "linker 对应的片段")
+            .params(&[("target", "string", true), ("link", "string", true)])
+            .ok(schema::<Vec<Value>>),
+        op(
+            "get",
+            "/api/dual/{target}/{left}/{right}",
+            "dual",
+            "左右片段生成的化合物",
+        )
+        .params(&[
+            ("target", "string", true),
+            ("left", "string", true),
+            ("right", "string", true),
+        ])
+        .ok(schema::<Vec<Value>>),
+        op("get", "/dual/{file}", "dual", "dual 的静态文件")
+            .params(&[("file", "string", true)])
+            .file(&["application/octet-stream"])
+            .public(),
+        op("get", "/smiles/{smiles}", "dual", "SMILES 的结构图")
+            .params(&[("smiles", "string", true)])
+            .file(&["image/svg+xml"])
+            .public(),
+        // 快照
+        op(
+            "post",
+            "/api/snapshots",
+            "snapshots",
+            "检索并保存快照, 包括全部 PMID 和缓存的记录版本",
+        )
+        .body(Body::Json(schema::<SnapshotRequest>))
+        .ok(schema::<Value>),
+        op("get", "/api/snapshots", "snapshots", "保存的快照, 管理员返回全部")
+            .ok(schema::<Vec<Value>>),
+        op("get", "/api/snapshots/{id}", "snapshots", "一个快照")
+            .params(&[("id", "string", true)])
+            .ok(schema::<Snapshot>),
+        op(
+            "get",
+            "/api/snapshots/{id}/export",
+            "snapshots",
+            "导出快照, 返回下载 id, json 为快照本身",
+        )
+        .params(&[("id", "string", true), ("file_type", "string", false)])
+        .ok(schema::<Value>),
+        op(
+            "get",
+            "/api/snapshots/{id}/diff",
+            "snapshots",
+            "和另一个快照或重新检索的结果比较",
+        )
+        .params(&[("id", "string", true), ("against", "string", false)])
+        .ok(schema::<Diff>),
+        // 保存的检索
+        op(
+            "post",
+            "/api/searches",
+            "searches",
+            "保存检索, 按 schedule 检查新文献并发送钉钉提醒",
+        )
+        .body(Body::Json(schema::<SearchRequest>))
+        .ok(schema::<Value>),
+        op("get", "/api/searches", "searches", "保存的检索, 管理员返回全部").ok(schema::<Vec<Value>>),
+        op("get", "/api/searches/{id}", "searches", "一个保存的检索")
+            .params(&[("id", "string", true)])
+            .ok(schema::<Value>),
+        op(
+            "put",
+            "/api/searches/{id}",
+            "searches",
+            "修改保存的检索, 修改检索式时重新记录当前的结果",
+        )
+        .params(&[("id", "string", true)])
+        .body(Body::Json(schema::<SearchRequest>))
+        .ok(schema::<Value>),
+        op(
+            "delete",
+            "/api/searches/{id}",
+            "searches",
+            "删除保存的检索和提醒历史",
+        )
+        .params(&[("id", "string", true)])
+        .ok(schema::<Value>),
+        op(
+            "post",
+            "/api/searches/{id}/run",
+            "searches",
+            "立即检查新文献, 没有新文献时返回 null",
+        )
+        .params(&[("id", "string", true)])
+        .ok(schema::<Option<Alert>>),
+        op("get", "/api/searches/{id}/alerts", "searches", "提醒历史")
+            .params(&[("id", "string", true), ("limit", "integer", false)])
+            .ok(schema::<Vec<Alert>>),
+        // API key
+        op(
+            "post",
+            "/api/admin/keys",
+            "keys",
+            "新建 API key, 需要管理员 key",
+        )
+        .body(Body::Json(schema::<IssueKey>))
+        .ok(schema::<ApiKey>),
+        op(
+            "get",
+            "/api/admin/keys",
+            "keys",
+            "全部 API key 和当天的用量",
+        )
+        .ok(schema::<Vec<Value>>),
+        op("delete", "/api/admin/keys/{key}", "keys", "吊销 API key")
+            .params(&[("key", "string", true)])
+            .ok(schema::<Value>),
+        op(
+            "get",
+            "/api/keys/usage",
+            "keys",
+            "当前 key 的限制和当天的用量",
+        )
+        .ok(schema::<Value>),
+        op(
+            "get",
+            "/api/admin/log",
+            "keys",
+            "当前的日志级别, 需要管理员 key",
+        )
+        .ok(schema::<Value>),
+        op(
+            "put",
+            "/api/admin/log",
+            "keys",
+            "修改模块的日志级别, 需要管理员 key, 重启后恢复",
+        )
+        .body(Body::Json(schema::<SetLevel>))
+        .ok(schema::<Value>),
+        op("get", "/health", "docs", "存活检查").public(),
+        op(
+            "get",
+            "/ready",
+            "docs",
+            "就绪检查, 包括 mongo, NCBI, LLM, SSH 和 data 目录",
+        )
+        .public(),
+        op("get", "/metrics", "docs", "Prometheus 指标")
+            .file(&["text/plain"])
+            .public(),
+        // 文档
+        op("get", "/api/openapi.json", "docs", "OpenAPI 文档").public(),
+        op("get", "/api/docs", "docs", "交互式接口文档")
+            .html()
+            .public(),
+    ]
+}
+
+fn param_schema(ty: &str) -> Value {
+    match ty {
+        "file" => json!({ "type": "string", "format": "binary" }),
+        _ => json!({ "type": ty }),
+    }
+}
+
+fn operation(o: &Op, gen: &mut SchemaGenerator) -> Value {
+    let params = o
+        .params
+        .iter()
+        .map(|(name, ty, required)| {
+            let path = o.path.contains(&format!("{{{}}}", name));
+            json!({
+                "name": name,
+                "in": if path { "path" } else { "query" },
+                "required": path || *required,
+                "schema": param_schema(ty),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let mut v = json!({
+        "tags": [o.tag],
+        "summary": o.summary,
+        "parameters": params,
+        "responses": {
+            "default": {
+                "description": "错误",
+                "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+            },
+        },
+    });
+
+    if o.public {
+        v["security"] = json!([]);
+    }
+
+    match &o.body {
+        Body::None => {}
+        Body::Json(f) => {
+            v["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": f(gen) } },
+            });
+        }
+        Body::Form(fields) => {
+            let props = fields
+                .iter()
+                .map(|(name, ty, _)| (name.to_string(), param_schema(ty)))
+                .collect::<Map<String, Value>>();
+            let required = fields
+                .iter()
+                .filter(|f| f.2)
+                .map(|f| f.0)
+                .collect::<Vec<&str>>();
+            v["requestBody"] = json!({
+                "required": true,
+                "content": { "multipart/form-data": { "schema": {
+                    "type": "object",
+                    "properties": props,
+                    "required": required,
+                } } },
+            });
+        }
+    }
+
+    let ok = |f: &SchemaFn, gen: &mut SchemaGenerator| {
+        json!({ "schema": {
+            "type": "object",
+            "properties": { "ok": f(gen) },
+            "required": ["ok"],
+        } })
+    };
+    let files = |types: &[&str]| {
+        types
+            .iter()
+            .map(|t| {
+                (
+                    t.to_string(),
+                    json!({ "schema": { "type": "string", "format": "binary" } }),
+                )
+            })
+            .collect::<Map<String, Value>>()
+    };
+    let content = match &o.resp {
+        Resp::Ok(f) => json!({ "application/json": ok(f, gen) }),
+        Resp::File(types) => files(types).into(),
+        Resp::Negotiated(f, types) => {
+            let mut m = files(types);
+            m.insert("application/json".to_string(), ok(f, gen));
+            m.into()
+        }
+        Resp::Events => json!({ "text/event-stream": { "schema": schema::<JobEvent>(gen) } }),
+        Resp::Raw => json!({ "application/json": { "schema": { "type": "object" } } }),
+        Resp::Html => json!({ "text/html": { "schema": { "type": "string" } } }),
+    };
+    v["responses"]["200"] = json!({ "description": "成功", "content": content });
+    v
+}
+
+pub fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    // 没有出现在接口中, 但客户端会用到的类型
+    let _ = schema::<PaperCsvSummary>(&mut gen);
+    let _ = schema::<GeneDisease>(&mut gen);
+    let _ = schema::<SearchResult>(&mut gen);
+
+    let mut paths = Map::new();
+    for o in operations() {
+        let v = operation(&o, &mut gen);
+        let item = paths.entry(o.path.to_string()).or_insert_with(|| json!({}));
+        item[o.method] = v;
+    }
+
+    let mut schemas = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(k, v)| (k, serde_json::to_value(v).unwrap_or_default()))
+        .collect::<Map<String, Value>>();
+    schemas.insert(
+        "Error".to_string(),
+        json!({
+            "type": "object",
+            "properties": { "error": {
+                "type": "object",
+                "properties": {
+                    "msg": { "type": "string" },
+                    "code": {
+                        "type": "string",
+                        "enum": ["invalid_argument", "unauthenticated", "permission_denied", "not_found",
+                            "conflict", "rate_limited", "quota_exceeded", "upstream_error",
+                            "upstream_unavailable", "internal"],
+                    },
+                },
+            } },
+        }),
+    );
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "rust-eutils",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "`eutils http api` 的二次封装",
+        },
+        "paths": paths,
+        "security": [{ "ApiKeyHeader": [] }, { "ApiKeyQuery": [] }],
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "ApiKeyHeader": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+                "ApiKeyQuery": { "type": "apiKey", "in": "query", "name": "api_key" },
+            },
+        },
+    })
+}
+
+static SPEC: Lazy<String> = Lazy::new(|| serde_json::to_string_pretty(&spec()).unwrap_or_default());
+
+#[get("/openapi.json")]
+pub async fn openapi_json() -> content::RawJson<&'static str> {
+    content::RawJson(SPEC.as_str())
+}
+
+static DOCS: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8" />
+  <title>rust-eutils API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Swagger UI, 静态资源从 unpkg 加载
+#[get("/docs")]
+pub async fn docs() -> content::RawHtml<&'static str> {
+    content::RawHtml(DOCS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// rocket 的路径 `/pubmed/<term>` 转换成 `/pubmed/{term}`
+    fn openapi_path(path: &str) -> String {
+        path.split('/')
+            .map(
+                |f| match f.strip_prefix('<').and_then(|f| f.strip_suffix('>')) {
+                    Some(name) => format!("{{{}}}", name.trim_end_matches("..")),
+                    None => f.to_string(),
+                },
+            )
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+
+    #[test]
+    fn test_all_routes_documented() {
+        let spec = spec();
+        let mut rocket = rocket::build();
+        for (base, routes) in crate::server::mounts() {
+            rocket = rocket.mount(base, routes);
+        }
+
+        let mut missing = Vec::new();
+        for r in rocket.routes() {
+            let path = openapi_path(r.uri.path());
+            let method = r.method.as_str().to_lowercase();
+            if spec["paths"][&path][&method].is_null() {
+                missing.push(format!("{} {}", method, path));
+            }
+        }
+        assert!(
+            missing.is_empty(),
+            "routes missing from openapi: {:?}",
+            missing
+        );
+    }
+
+    #[test]
+    fn test_spec() {
+        let spec = spec();
+        assert_eq!("3.0.3", spec["openapi"]);
+
+        let chat = &spec["paths"]["/api/openai/chat"];
+        assert!(chat["get"].is_null());
+        assert_eq!(
+            "#/components/schemas/ChatRequest",
+            chat["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"]
+        );
+        assert!(spec["components"]["schemas"]["Job"].is_object());
+        assert!(chat["post"]["security"].is_null());
+        assert_eq!(json!([]), spec["paths"]["/api/docs"]["get"]["security"]);
+
+        let p = &spec["paths"]["/api/pubmed/{term}"]["get"]["parameters"];
+        assert_eq!("path", p[0]["in"]);
+        assert_eq!("query", p[1]["in"]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use rocket::get;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::RwLock, time::sleep};
+
+use crate::auth::Auth;
+use crate::settings::data_path;
+use crate::{
+    cache::{cached_pmids, PaperMeta},
+    error::{api_ok, ApiResult},
+    eutils::{fetch_ids, refetch},
+};
+
+static STATE_FILE: &str = "refresh.json";
+
+/// 每次 esearch 检查的 pmid 数量
+const CHUNK_SIZE: usize = 200;
+/// 第一次运行时往前检查的天数
+const FIRST_WINDOW_DAYS: i64 = 30;
+/// 两次刷新之间的间隔, 6 小时
+const REFRESH_INTERVAL: u64 = 6 * 3600;
+
+/// 刷新进度, 完成后写入 `data/refresh.json`, 下一次从 `last_run` 开始检查
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RefreshProgress {
+    pub running: bool,
+    /// 上一次成功完成时检查到的日期, 如 2023/02/10
+    pub last_run: Option<String>,
+    pub window_from: String,
+    pub window_to: String,
+    /// 本地缓存的文献数量
+    pub total: usize,
+    /// 已检查的数量
+    pub checked: usize,
+    /// PubMed 上有修改的数量
+    pub revised: usize,
+    /// 重新下载成功的数量
+    pub refetched: usize,
+    pub failed: usize,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+static PROGRESS: Lazy<RwLock<RefreshProgress>> = Lazy::new(|| {
+    let p = std::fs::read_to_string(data_path(STATE_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str::<RefreshProgress>(&s).ok())
+        .unwrap_or_default();
+    RwLock::new(RefreshProgress {
+        running: false,
+        ..p
+    })
+});
+
+fn date_str(d: NaiveDate) -> String {
+    d.format("%Y/%m/%d").to_string()
+}
+
+fn mdat_query(ids: &[usize], from: &str, to: &str) -> String {
+    let uids = ids
+        .iter()
+        .map(|f| format!("{}[uid]", f))
+        .collect::<Vec<String>>()
+        .join(" OR ");
+    format!("({}) AND (\"{}\"[mdat] : \"{}\"[mdat])", uids, from, to)
+}
+
+/// 时间窗口内修改过, 且在本次刷新开始后没有重新下载过的才需要更新;
+/// mdat 只精确到天, 窗口内下载过的也可能在下载后又被修改, 所以按窗口结束的时间比较
+fn need_refetch(id: usize, to_millis: i64) -> bool {
+    match PaperMeta::load(id) {
+        Some(meta) => meta.fetched_at < to_millis,
+        None => true,
+    }
+}
+
+async fn save_progress() {
+    let p = PROGRESS.read().await.clone();
+    if let Ok(s) = serde_json::to_string_pretty(&p) {
+        let _ = std::fs::write(data_path(STATE_FILE), s);
+    }
+}
+
+/// 有错误或者下载失败的文献时不更新 `last_run`, 下一次重新检查这个窗口
+fn finish(p: &mut RefreshProgress, to: &str, error: Option<String>) {
+    p.running = false;
+    p.finished_at = Some(Utc::now().timestamp_millis());
+    let error = error.or_else(|| {
+        (p.failed > 0).then(|| format!("{} revised papers failed to refetch", p.failed))
+    });
+    if error.is_none() {
+        p.last_run = Some(to.to_string());
+    }
+    p.error = error;
+}
+
+pub async fn refresh_once() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let today = Utc::now().date_naive();
+    let ids = cached_pmids();
+    let from_date = {
+        let mut p = PROGRESS.write().await;
+        if p.running {
+            return Err("refresh is already running".into());
+        }
+
+        let from_date = p
+            .last_run
+            .as_ref()
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y/%m/%d").ok())
+            .unwrap_or(today - chrono::Duration::days(FIRST_WINDOW_DAYS));
+
+        *p = RefreshProgress {
+            running: true,
+            last_run: p.last_run.clone(),
+            window_from: date_str(from_date),
+            window_to: date_str(today),
+            total: ids.len(),
+            started_at: Some(Utc::now().timestamp_millis()),
+            ..Default::default()
+        };
+        from_date
+    };
+
+    let from = date_str(from_date);
+    let to = date_str(today);
+    let to_millis = Utc::now().timestamp_millis();
+
+    log::info!("start refresh cache, mdat {} : {}", &from, &to);
+
+    let mut error = None;
+
+    for chunk in ids.chunks(CHUNK_SIZE) {
+        let result = fetch_ids("pubmed", &mdat_query(chunk, &from, &to), 0, chunk.len()).await;
+        let revised = match result {
+            Ok(r) => r
+                .esearchresult
+                .idlist
+                .into_iter()
+                .filter(|f| {
+                    f.parse::<usize>()
+                        .map(|id| need_refetch(id, to_millis))
+                        .unwrap_or(false)
+                })
+                .collect::<Vec<String>>(),
+            Err(err) => {
+                log::warn!("refresh esearch error = {:?}", err);
+                error = Some(err.to_string());
+                break;
+            }
+        };
+
+        let refetched = match refetch("pubmed", &revised).await {
+            Ok(n) => n,
+            Err(err) => {
+                log::warn!("refresh efetch error = {:?}", err);
+                error.get_or_insert(err.to_string());
+                0
+            }
+        };
+
+        let mut p = PROGRESS.write().await;
+        p.checked += chunk.len();
+        p.revised += revised.len();
+        p.refetched += refetched;
+        p.failed += revised.len().saturating_sub(refetched);
+    }
+
+    {
+        let mut p = PROGRESS.write().await;
+        finish(&mut p, &to, error);
+        log::info!(
+            "refresh cache done, checked = {}, revised = {}, refetched = {}",
+            p.checked,
+            p.revised,
+            p.refetched
+        );
+    }
+    save_progress().await;
+
+    Ok(())
+}
+
+pub fn start_timetask() {
+    tokio::spawn(async {
+        sleep(Duration::from_secs(60)).await;
+        loop {
+            if let Err(err) = refresh_once().await {
+                log::warn!("refresh cache error = {:?}", err);
+            }
+
+            sleep(Duration::from_secs(REFRESH_INTERVAL)).await;
+        }
+    });
+}
+
+#[get("/cache/refresh")]
+pub async fn refresh_status(_key: Auth) -> ApiResult {
+    let p = PROGRESS.read().await.clone();
+    api_ok(&p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdat_query() {
+        let q = mdat_query(&[1, 22], "2023/01/01", "2023/02/01");
+        assert_eq!(
+            "(1[uid] OR 22[uid]) AND (\"2023/01/01\"[mdat] : \"2023/02/01\"[mdat])",
+            q
+        );
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut p = RefreshProgress {
+            running: true,
+            last_run: Some("2023/01/01".to_string()),
+            revised: 3,
+            refetched: 1,
+            failed: 2,
+            ..Default::default()
+        };
+        finish(&mut p, "2023/02/01", None);
+        assert!(!p.running);
+        assert_eq!(Some("2023/01/01".to_string()), p.last_run);
+        assert!(p.error.is_some());
+
+        let mut p = RefreshProgress {
+            last_run: Some("2023/01/01".to_string()),
+            ..Default::default()
+        };
+        finish(&mut p, "2023/02/01", Some("efetch error".to_string()));
+        assert_eq!(Some("2023/01/01".to_string()), p.last_run);
+
+        let mut p = RefreshProgress::default();
+        finish(&mut p, "2023/02/01", None);
+        assert_eq!(Some("2023/02/01".to_string()), p.last_run);
+        assert_eq!(None, p.error);
+    }
+}
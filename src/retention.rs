@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::settings::{data_path, Retention};
+use crate::utils::get_download_path_by_time;
+
+static TOKEN_FILE: &str = "download_tokens.json";
+
+/// 下载链接的有效期, 24 小时
+const TOKEN_TTL: i64 = 24 * 3600 * 1000;
+/// 清理任务的间隔, 1 小时
+const CLEANUP_INTERVAL: u64 = 3600;
+
+/// 文件类型的保留策略, 见配置 `data.retention`
+pub fn policy(file_type: &str) -> Retention {
+    let map = &crate::settings::settings().data.retention;
+    map.get(file_type)
+        .or_else(|| map.get("default"))
+        .copied()
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DownloadToken {
+    pub file_type: String,
+    /// `get_download_path` 返回的时间戳
+    pub id: i64,
+    pub expires_at: i64,
+}
+
+static TOKENS: Lazy<Mutex<HashMap<String, DownloadToken>>> = Lazy::new(|| {
+    let map = fs::read_to_string(data_path(TOKEN_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    Mutex::new(map)
+});
+
+fn save_tokens(map: &HashMap<String, DownloadToken>) {
+    if let Ok(s) = serde_json::to_string(map) {
+        let _ = fs::write(data_path(TOKEN_FILE), s);
+    }
+}
+
+/// 为生成的下载文件签发一个随机的下载 id, 代替原来的时间戳
+pub fn issue_token(file_type: &str, id: i64) -> String {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let mut map = TOKENS.lock().unwrap();
+    map.insert(
+        token.clone(),
+        DownloadToken {
+            file_type: file_type.to_string(),
+            id,
+            expires_at: chrono::Utc::now().timestamp_millis() + TOKEN_TTL,
+        },
+    );
+    save_tokens(&map);
+
+    token
+}
+
+/// 下载 id 对应的文件路径, 过期或类型不符时返回 None
+pub fn resolve_token(file_type: &str, token: &str) -> Option<String> {
+    let map = TOKENS.lock().unwrap();
+    let t = map.get(token)?;
+    if t.file_type != file_type || t.expires_at < chrono::Utc::now().timestamp_millis() {
+        return None;
+    }
+
+    Some(get_download_path_by_time(file_type, t.id))
+}
+
+fn download_dirs() -> Vec<(String, PathBuf)> {
+    fs::read_dir(&crate::settings::settings().data.dir)
+        .map(|dir| {
+            dir.filter_map(|f| f.ok())
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    name.strip_prefix("download_")
+                        .map(|t| (t.to_string(), e.path()))
+                })
+                .filter(|(_, p)| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// (路径, 大小, 修改时间 毫秒)
+fn list_files(dir: &Path) -> Vec<(PathBuf, u64, i64)> {
+    let mut v = Vec::new();
+    for day in fs::read_dir(dir).into_iter().flatten().flatten() {
+        for file in fs::read_dir(day.path()).into_iter().flatten().flatten() {
+            if let Ok(meta) = file.metadata() {
+                if meta.is_file() {
+                    let modified = meta
+                        .modified()
+                        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).timestamp_millis())
+                        .unwrap_or(0);
+                    v.push((file.path(), meta.len(), modified));
+                }
+            }
+        }
+    }
+    v
+}
+
+/// 按保留策略要删除的文件: 先删过期的, 再从最旧的开始删直到总大小和文件数不超过配额
+fn select_expired(
+    mut files: Vec<(PathBuf, u64, i64)>,
+    policy: Retention,
+    now: i64,
+) -> Vec<PathBuf> {
+    let before = now - policy.max_age_days * 24 * 3600 * 1000;
+    let max_bytes = policy.max_total_mb * 1024 * 1024;
+    files.sort_by_key(|f| f.2);
+
+    let mut total: u64 = files.iter().map(|f| f.1).sum();
+    let mut count = files.len();
+    let mut v = Vec::new();
+    for (path, size, modified) in files {
+        let too_many = policy.max_files > 0 && count > policy.max_files;
+        if modified < before || total > max_bytes || too_many {
+            total -= size;
+            count -= 1;
+            v.push(path);
+        }
+    }
+    v
+}
+
+pub fn cleanup() {
+    let now = chrono::Utc::now().timestamp_millis();
+
+    for (file_type, dir) in download_dirs() {
+        let expired = select_expired(list_files(&dir), policy(&file_type), now);
+        for path in &expired {
+            let _ = fs::remove_file(path);
+        }
+        // 删除空的日期目录
+        for day in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let _ = fs::remove_dir(day.path());
+        }
+        if !expired.is_empty() {
+            log::info!("retention {}: removed {} files", &file_type, expired.len());
+        }
+    }
+
+    let mut map = TOKENS.lock().unwrap();
+    let len = map.len();
+    map.retain(|_, t| {
+        t.expires_at >= now && Path::new(&get_download_path_by_time(&t.file_type, t.id)).exists()
+    });
+    if map.len() != len {
+        save_tokens(&map);
+    }
+}
+
+pub fn start_timetask() {
+    tokio::spawn(async {
+        loop {
+            let _ = tokio::task::spawn_blocking(cleanup).await;
+            sleep(Duration::from_secs(CLEANUP_INTERVAL)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_expired() {
+        let day = 24 * 3600 * 1000;
+        let now = 100 * day;
+        let mb = 1024 * 1024;
+        let policy = Retention {
+            max_age_days: 10,
+            max_total_mb: 25,
+            max_files: 0,
+        };
+        let files = vec![
+            (PathBuf::from("a"), 10 * mb, now - 20 * day),
+            (PathBuf::from("b"), 10 * mb, now - 3 * day),
+            (PathBuf::from("c"), 10 * mb, now - 2 * day),
+            (PathBuf::from("d"), 10 * mb, now - day),
+        ];
+
+        // a 过期, 剩下 30 超过配额, 再删最旧的 b
+        assert_eq!(
+            vec![PathBuf::from("a"), PathBuf::from("b")],
+            select_expired(files.clone(), policy, now)
+        );
+
+        // 只保留最新的 1 个
+        let policy = Retention {
+            max_age_days: 30,
+            max_total_mb: 1024,
+            max_files: 1,
+        };
+        assert_eq!(
+            vec![PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c")],
+            select_expired(files, policy, now)
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        assert_eq!(30, policy("csv").max_age_days);
+        for t in ["ris", "bib", "xml", "xlsx", "json", "unknown"] {
+            assert_eq!(7, policy(t).max_age_days);
+        }
+    }
+}
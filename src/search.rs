@@ -0,0 +1,492 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::{
+    cache::{cached_pmids, load_paper, PaperMeta},
+    export::endnote::escape,
+    model::PaperCsvResult,
+};
+
+const FIELDS: usize = 4;
+const FIELD_NAMES: [&str; FIELDS] = ["title", "abstract", "mesh", "journal"];
+/// 各字段在 BM25 打分中的权重
+const FIELD_WEIGHTS: [f64; FIELDS] = [2.0, 1.0, 1.5, 0.5];
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// 摘要片段的词数
+const SNIPPET_WORDS: usize = 40;
+/// 每个分面返回的数量
+const FACET_SIZE: usize = 20;
+
+static STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "that", "the", "to", "was", "were", "with",
+];
+
+#[derive(Debug, Default)]
+struct DocEntry {
+    len: [u32; FIELDS],
+    terms: Vec<String>,
+    year: String,
+    journal: String,
+    mesh: Vec<String>,
+}
+
+/// 倒排索引, term -> (pmid -> 各字段的词频)
+#[derive(Debug, Default)]
+struct Index {
+    docs: HashMap<u32, DocEntry>,
+    postings: HashMap<String, HashMap<u32, [u32; FIELDS]>>,
+    total_len: [u64; FIELDS],
+}
+
+#[derive(Debug, Default)]
+struct Query {
+    /// (限定的字段, 词)
+    terms: Vec<(Option<usize>, String)>,
+    year: Option<String>,
+    journal: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Hit {
+    pmid: String,
+    score: f64,
+    title: String,
+    snippet: String,
+    journal: String,
+    year: String,
+    mesh: Vec<String>,
+}
+
+/// 本地检索的倒排索引, 由 `cache::PaperCache` 在写入和删除缓存时更新
+#[derive(Debug, Default)]
+pub struct SearchIndex(RwLock<Index>);
+
+impl SearchIndex {
+    pub fn index_paper(&self, paper: &PaperCsvResult, meta: &PaperMeta) {
+        self.0.write().unwrap().insert(paper, Some(meta));
+    }
+
+    pub fn remove(&self, id: usize) {
+        self.0.write().unwrap().remove(id as u32);
+    }
+}
+
+/// 服务使用的索引, `/api/local/search` 从这里检索
+static INDEX: Lazy<Arc<SearchIndex>> = Lazy::new(Default::default);
+static BUILDING: AtomicBool = AtomicBool::new(false);
+
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut v = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            if start.is_none() {
+                start = Some(i);
+            }
+        } else if let Some(s) = start.take() {
+            v.push((s, i));
+        }
+    }
+    if let Some(s) = start {
+        v.push((s, text.len()));
+    }
+    v
+}
+
+fn normalize(word: &str) -> Option<String> {
+    let w = word.to_lowercase();
+    if w.chars().count() < 2 || STOP_WORDS.contains(&w.as_str()) {
+        None
+    } else {
+        Some(w)
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    word_spans(text)
+        .into_iter()
+        .filter_map(|(s, e)| normalize(&text[s..e]))
+        .collect()
+}
+
+impl Index {
+    fn insert(&mut self, paper: &PaperCsvResult, meta: Option<&PaperMeta>) {
+        let id = match paper.pmid.parse::<u32>() {
+            Ok(id) => id,
+            Err(_) => return,
+        };
+        self.remove(id);
+
+        let mesh = meta.map(|m| m.mesh.clone()).unwrap_or_default();
+        let texts = [
+            paper.title.clone(),
+            paper.r#abstract.clone(),
+            mesh.join(" "),
+            format!("{} {}", paper.journal_title, paper.journal_abbr),
+        ];
+
+        let mut doc = DocEntry {
+            year: paper.pubdate_year.clone(),
+            journal: paper.journal_abbr.clone(),
+            mesh,
+            ..Default::default()
+        };
+        let mut terms = HashSet::new();
+
+        for (f, text) in texts.iter().enumerate() {
+            let tokens = tokenize(text);
+            doc.len[f] = tokens.len() as u32;
+            self.total_len[f] += tokens.len() as u64;
+            for t in tokens {
+                self.postings
+                    .entry(t.clone())
+                    .or_default()
+                    .entry(id)
+                    .or_insert([0; FIELDS])[f] += 1;
+                terms.insert(t);
+            }
+        }
+
+        doc.terms = terms.into_iter().collect();
+        self.docs.insert(id, doc);
+    }
+
+    fn remove(&mut self, id: u32) {
+        if let Some(doc) = self.docs.remove(&id) {
+            for f in 0..FIELDS {
+                self.total_len[f] -= doc.len[f] as u64;
+            }
+            for t in doc.terms {
+                if let Some(p) = self.postings.get_mut(&t) {
+                    p.remove(&id);
+                    if p.is_empty() {
+                        self.postings.remove(&t);
+                    }
+                }
+            }
+        }
+    }
+
+    fn idf(&self, term: &str) -> f64 {
+        let n = self.docs.len() as f64;
+        let df = self.postings.get(term).map(|p| p.len()).unwrap_or(0) as f64;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    /// 所有词都要命中 (AND), 按 BM25 分数倒序
+    fn search(&self, query: &Query) -> Vec<(u32, f64)> {
+        if query.terms.is_empty() || self.docs.is_empty() {
+            return vec![];
+        }
+
+        let n = self.docs.len() as f64;
+        let avg = self.total_len.map(|l| (l as f64 / n).max(1.0));
+
+        let mut scores: HashMap<u32, (usize, f64)> = HashMap::new();
+        for (field, term) in &query.terms {
+            let postings = match self.postings.get(term) {
+                Some(p) => p,
+                None => return vec![],
+            };
+            let idf = self.idf(term);
+
+            for (id, tf) in postings {
+                let doc = &self.docs[id];
+                let mut score = 0.0;
+                for f in 0..FIELDS {
+                    if tf[f] == 0 || field.is_some_and(|x| x != f) {
+                        continue;
+                    }
+                    let tf = tf[f] as f64;
+                    let norm = 1.0 - B + B * doc.len[f] as f64 / avg[f];
+                    score += FIELD_WEIGHTS[f] * tf * (K1 + 1.0) / (tf + K1 * norm);
+                }
+                if score > 0.0 {
+                    let e = scores.entry(*id).or_insert((0, 0.0));
+                    e.0 += 1;
+                    e.1 += idf * score;
+                }
+            }
+        }
+
+        let mut v = scores
+            .into_iter()
+            .filter(|(id, (matched, _))| {
+                let doc = &self.docs[id];
+                *matched == query.terms.len()
+                    && query.year.as_ref().is_none_or(|y| &doc.year == y)
+                    && query.journal.as_ref().is_none_or(|j| &doc.journal == j)
+            })
+            .map(|(id, (_, score))| (id, score))
+            .collect::<Vec<(u32, f64)>>();
+
+        v.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+        v
+    }
+
+    fn facets(&self, ids: &[(u32, f64)]) -> serde_json::Value {
+        let mut year: HashMap<&str, usize> = HashMap::new();
+        let mut journal: HashMap<&str, usize> = HashMap::new();
+        let mut mesh: HashMap<&str, usize> = HashMap::new();
+
+        for (id, _) in ids {
+            let doc = &self.docs[id];
+            *year.entry(&doc.year).or_default() += 1;
+            *journal.entry(&doc.journal).or_default() += 1;
+            for m in &doc.mesh {
+                *mesh.entry(m).or_default() += 1;
+            }
+        }
+
+        let top = |m: HashMap<&str, usize>| {
+            let mut v = m
+                .into_iter()
+                .filter(|(k, _)| !k.is_empty())
+                .collect::<Vec<_>>();
+            v.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            v.truncate(FACET_SIZE);
+            v.into_iter()
+                .map(|(k, c)| serde_json::json!({ "value": k, "count": c }))
+                .collect::<Vec<_>>()
+        };
+
+        serde_json::json!({
+            "year": top(year),
+            "journal": top(journal),
+            "mesh": top(mesh),
+        })
+    }
+}
+
+fn parse_query(q: &str) -> Query {
+    let mut query = Query::default();
+    for part in q.split_whitespace() {
+        let (field, word) = match part.split_once(':') {
+            Some((f, w)) => match FIELD_NAMES.iter().position(|n| *n == f.to_lowercase()) {
+                Some(i) => (Some(i), w),
+                None => (None, part),
+            },
+            None => (None, part),
+        };
+        for t in tokenize(word) {
+            query.terms.push((field, t));
+        }
+    }
+    query
+}
+
+/// 用 `<em>` 标出命中的词
+fn highlight(text: &str, terms: &HashSet<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (s, e) in word_spans(text) {
+        if normalize(&text[s..e]).is_some_and(|w| terms.contains(&w)) {
+            out.push_str(&escape(&text[last..s]));
+            out.push_str("<em>");
+            out.push_str(&escape(&text[s..e]));
+            out.push_str("</em>");
+            last = e;
+        }
+    }
+    out.push_str(&escape(&text[last..]));
+    out
+}
+
+/// 第一个命中词附近的摘要片段
+fn snippet(text: &str, terms: &HashSet<String>) -> String {
+    let spans = word_spans(text);
+    if spans.is_empty() {
+        return String::new();
+    }
+
+    let first = spans
+        .iter()
+        .position(|(s, e)| normalize(&text[*s..*e]).is_some_and(|w| terms.contains(&w)))
+        .unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_WORDS / 4);
+    let end = (start + SNIPPET_WORDS).min(spans.len());
+
+    let mut out = highlight(&text[spans[start].0..spans[end - 1].1], terms);
+    if start > 0 {
+        out = format!("...{}", out);
+    }
+    if end < spans.len() {
+        out.push_str("...");
+    }
+    out
+}
+
+pub fn shared() -> Arc<SearchIndex> {
+    INDEX.clone()
+}
+
+/// 启动时从本地缓存重建索引
+pub fn start_build() {
+    tokio::task::spawn_blocking(|| {
+        BUILDING.store(true, Ordering::SeqCst);
+        let ids = cached_pmids();
+        log::info!("start build local index, papers = {}", ids.len());
+        for id in ids {
+            if let Some(paper) = load_paper(id) {
+                let meta = PaperMeta::load(id);
+                INDEX.0.write().unwrap().insert(&paper, meta.as_ref());
+            }
+        }
+        BUILDING.store(false, Ordering::SeqCst);
+        log::info!(
+            "build local index done, docs = {}",
+            INDEX.0.read().unwrap().docs.len()
+        );
+    });
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::get;
+
+    use super::*;
+    use crate::auth::Auth;
+    use crate::error::{ApiResult, AppError};
+    use crate::response::response_ok;
+
+    #[get("/local/search?<q>&<cur_page>&<page_size>&<year>&<journal>")]
+    pub async fn local_search(
+        _key: Auth,
+        q: String,
+        cur_page: Option<usize>,
+        page_size: Option<usize>,
+        year: Option<String>,
+        journal: Option<String>,
+    ) -> ApiResult {
+        let start = std::time::Instant::now();
+        let cur_page = cur_page.unwrap_or(0);
+        let page_size = page_size.unwrap_or(10);
+
+        let mut query = parse_query(&q);
+        if query.terms.is_empty() {
+            return Err(AppError::bad_request("empty query"));
+        }
+        query.year = year;
+        query.journal = journal;
+
+        let (total, facets, page) = {
+            let index = INDEX.0.read().unwrap();
+            let result = index.search(&query);
+            let facets = index.facets(&result);
+            let page = result
+                .iter()
+                .skip(cur_page * page_size)
+                .take(page_size)
+                .map(|(id, score)| (*id, *score, index.docs[id].mesh.clone()))
+                .collect::<Vec<_>>();
+            (result.len(), facets, page)
+        };
+
+        let terms = query
+            .terms
+            .iter()
+            .map(|(_, t)| t.clone())
+            .collect::<HashSet<String>>();
+        let data = page
+            .into_iter()
+            .filter_map(|(id, score, mesh)| {
+                load_paper(id as usize).map(|p| Hit {
+                    pmid: p.pmid,
+                    score,
+                    title: highlight(&p.title, &terms),
+                    snippet: snippet(&p.r#abstract, &terms),
+                    journal: p.journal_abbr,
+                    year: p.pubdate_year,
+                    mesh,
+                })
+            })
+            .collect::<Vec<Hit>>();
+
+        Ok(response_ok(serde_json::json!({
+            "count": total,
+            "cur_page": cur_page,
+            "page_size": page_size,
+            "data": data,
+            "facets": facets,
+            "building": BUILDING.load(Ordering::SeqCst),
+            "took_ms": start.elapsed().as_millis() as u64,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paper(pmid: &str, title: &str, abs: &str, year: &str) -> PaperCsvResult {
+        PaperCsvResult {
+            pmid: pmid.to_string(),
+            title: title.to_string(),
+            r#abstract: abs.to_string(),
+            pubdate_year: year.to_string(),
+            journal_abbr: "BMC Med".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = Index::default();
+        let mut meta = PaperMeta::new("1", "2023/02/10");
+        meta.mesh = vec!["Spondylitis, Ankylosing".to_string()];
+        index.insert(
+            &paper("1", "DUSP22 in ankylosing spondylitis", "T cells", "2023"),
+            Some(&meta),
+        );
+        index.insert(
+            &paper("2", "T cells", "spondylitis and T cells", "2022"),
+            None,
+        );
+
+        let r = index.search(&parse_query("spondylitis"));
+        assert_eq!(vec![1, 2], r.iter().map(|f| f.0).collect::<Vec<u32>>());
+
+        let r = index.search(&parse_query("title:cells"));
+        assert_eq!(vec![2], r.iter().map(|f| f.0).collect::<Vec<u32>>());
+
+        let r = index.search(&parse_query("spondylitis dusp22"));
+        assert_eq!(vec![1], r.iter().map(|f| f.0).collect::<Vec<u32>>());
+
+        let mut q = parse_query("spondylitis");
+        q.year = Some("2022".to_string());
+        assert_eq!(1, index.search(&q).len());
+
+        index.remove(1);
+        assert_eq!(1, index.search(&parse_query("spondylitis")).len());
+        assert!(index.search(&parse_query("dusp22")).is_empty());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let terms = ["cells".to_string()]
+            .into_iter()
+            .collect::<HashSet<String>>();
+        assert_eq!(
+            "peripheral T <em>cells</em>.",
+            highlight("peripheral T cells.", &terms)
+        );
+        // 命中词以外的内容也转义, 只有 <em> 是标签
+        assert_eq!(
+            "p&lt;0.05 in T <em>cells</em> &amp; CD3&lt;sup&gt;+&lt;/sup&gt;",
+            highlight("p<0.05 in T cells & CD3<sup>+</sup>", &terms)
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::PathBuf;
+
+use rocket::{
+    catchers,
+    fairing::{Fairing, Info, Kind},
+    fs::{FileServer, NamedFile},
+    get,
+    http::Header,
+    info,
+    log::LogLevel,
+    routes, Build, Request, Response, Rocket, Route,
+};
+use urlencoding::encode;
+
+use crate::auth::{Ncbi, Public};
+use crate::error::{api_ok, ApiResult, AppError};
+use crate::response::response_ok;
+use crate::settings::data_path;
+use crate::utils::file_exist;
+
+#[get("/pubmed/<term>?<cur_page>&<page_size>")]
+async fn query_pubmed(
+    _key: Ncbi,
+    term: String,
+    cur_page: Option<usize>,
+    page_size: Option<usize>,
+) -> ApiResult {
+    info!("pubmed query = {:?}", term.as_str());
+
+    let r = crate::eutils::esearch2("pubmed", &term, cur_page, page_size).await?;
+    api_ok(&r)
+}
+
+/// 下载, `columns` 如 `PMID,Title:标题,Authors`, 或者使用保存的 `profile`
+#[get("/pubmed/save/<term>?<cur_page>&<page_size>&<file_type>&<columns>&<profile>")]
+async fn query_pubmed_and_save(
+    _key: Ncbi,
+    term: String,
+    cur_page: Option<usize>,
+    page_size: Option<usize>,
+    file_type: Option<String>,
+    columns: Option<String>,
+    profile: Option<String>,
+) -> ApiResult {
+    info!(
+        "pubmed query = {:?}, file_type = {:?}, columns = {:?}, profile = {:?}",
+        term.as_str(),
+        &file_type,
+        &columns,
+        &profile
+    );
+
+    let fields = crate::export::columns::request_fields(columns.as_deref(), profile.as_deref())
+        .map_err(AppError::bad_request)?;
+
+    let r = crate::eutils::esearch3("pubmed", &term, cur_page, page_size).await?;
+    let file_type = file_type.as_deref().unwrap_or("csv");
+    Ok(response_ok(crate::export::save_list(
+        &r,
+        file_type,
+        &term,
+        fields.as_deref(),
+    )?))
+}
+
+#[get("/pubmed/total/<term>")]
+async fn query_pubmed_total(_key: Ncbi, term: String) -> ApiResult {
+    info!("pubmed query = {:?}", term.as_str());
+
+    let r = crate::eutils::esearch("pubmed", &term).await?;
+    api_ok(&r)
+}
+
+#[get("/pubmed/pmid/<pmid>")]
+async fn get_pubmed_by_id(_key: Ncbi, pmid: String) -> ApiResult {
+    pmid.parse::<usize>()
+        .map_err(|_| AppError::bad_request(format!("invalid pmid: {}", pmid)))?;
+
+    let r = crate::eutils::efetch("pubmed", std::slice::from_ref(&pmid)).await?;
+    match r.first() {
+        Some(p) => api_ok(p),
+        None => Err(AppError::not_found(format!("pmid not found: {}", pmid))),
+    }
+}
+
+#[get("/pubmed/<pmid>")]
+async fn get_pubmed(_key: Ncbi, pmid: String) -> Result<NamedFile, AppError> {
+    let id = pmid
+        .parse::<usize>()
+        .map_err(|_| AppError::bad_request(format!("invalid pmid: {}", pmid)))?;
+
+    let path = crate::eutils::client().cache().csv_path(id);
+    if !file_exist(&path) {
+        let result = crate::eutils::efetch("pubmed", std::slice::from_ref(&pmid)).await;
+        if let Err(err) = result {
+            log::info!("downloaded error... {:?}", err);
+            return Err(err.into());
+        }
+    }
+
+    NamedFile::open(&path)
+        .await
+        .map_err(|_| AppError::not_found(format!("pmid not found: {}", pmid)))
+}
+
+#[get("/<file_type>/<id>")]
+async fn download(file_type: String, id: String) -> Result<NamedFile, AppError> {
+    let not_found = || AppError::not_found(format!("download not found or expired: {}", id));
+    let path = crate::retention::resolve_token(&file_type, &id).ok_or_else(not_found)?;
+    if !file_exist(&path) {
+        return Err(not_found());
+    }
+    NamedFile::open(&path).await.map_err(|_| not_found())
+}
+
+#[get("/<file..>", rank = 998)]
+async fn static_files(_key: Public, file: PathBuf) -> Option<NamedFile> {
+    let mut path = PathBuf::new();
+    path.push(data_path("dual"));
+    path.push(file);
+    // log::info!("path = {:?}", &path);
+
+    let r = NamedFile::open(path).await;
+    if r.is_err() {
+        NamedFile::open("./data/logo.png").await.ok()
+    } else {
+        r.ok()
+    }
+}
+
+#[get("/<smiles>", rank = 999)]
+async fn smiles_files(_key: Public, smiles: String) -> Option<NamedFile> {
+    let mut path = PathBuf::new();
+    path.push(data_path("SMILES"));
+    path.push(format!("{}.svg", encode(&smiles)));
+    log::info!("path = {:?}", &path);
+
+    let r = NamedFile::open(path).await;
+    if r.is_err() {
+        NamedFile::open(data_path("*.svg")).await.ok()
+    } else {
+        r.ok()
+    }
+}
+
+/// 全部路由, `openapi` 的测试用来检查每个路由都有文档
+pub fn mounts() -> Vec<(&'static str, Vec<Route>)> {
+    vec![
+        (
+            "/api",
+            routes![
+                query_pubmed,
+                get_pubmed_by_id,
+                query_pubmed_total,
+                query_pubmed_and_save,
+                crate::batch::api::fetch,
+                crate::sets::api::combine_sets,
+                crate::export::cite::api::cite,
+                crate::export::columns::api::list_profiles,
+                crate::export::columns::api::save_profile,
+                crate::export::columns::api::delete_profile,
+                crate::jobs::job_total,
+                crate::jobs::job_save,
+                crate::jobs::job_summary,
+                crate::jobs::job_summary_with_query,
+                crate::jobs::job_disease,
+                crate::jobs::job_list,
+                crate::jobs::job_status,
+                crate::jobs::job_partial,
+                crate::jobs::job_cancel,
+                crate::jobs::job_download,
+                crate::events::job_events,
+                crate::events::summary_with_query_events,
+                crate::events::disease_events,
+                // openai_chat_form,
+                crate::openai::api::openai_chat,
+                crate::openai::api::openai_chat_summary_file,
+                crate::openai::api::openai_chat_summary_file2,
+                crate::disease::api::query_disease_gene_,
+                crate::dual::dual_list,
+                crate::dual::dual_target_info,
+                crate::dual::dual_gen_cpds,
+                crate::dual::dual_pair,
+                crate::refresh::refresh_status,
+                crate::import::cache_import,
+                crate::import::cache_import_status,
+                crate::search::api::local_search,
+                crate::cache::api::cache_stats,
+                crate::cache::api::cache_purge,
+                crate::cache::api::cache_prefetch,
+                crate::cache::api::cache_scan,
+                crate::cache::api::cache_scan_status,
+                crate::snapshot::api::create_snapshot,
+                crate::snapshot::api::list_snapshots,
+                crate::snapshot::api::get_snapshot,
+                crate::snapshot::api::export_snapshot,
+                crate::snapshot::api::diff_snapshot,
+                crate::alerts::create_search,
+                crate::alerts::list_searches,
+                crate::alerts::get_search,
+                crate::alerts::update_search,
+                crate::alerts::delete_search,
+                crate::alerts::run_search_now,
+                crate::alerts::search_alerts,
+                crate::auth::issue_key,
+                crate::auth::list_keys,
+                crate::auth::revoke_key,
+                crate::auth::key_usage,
+                crate::logging::api::log_levels,
+                crate::logging::api::set_log_level,
+                crate::openapi::openapi_json,
+                crate::openapi::docs,
+            ],
+        ),
+        ("/dual", routes![static_files]),
+        ("/smiles", routes![smiles_files]),
+        ("/", routes![get_pubmed]),
+        ("/download", routes![download]),
+        (
+            "/",
+            routes![
+                crate::metrics::api::metrics,
+                crate::health::health,
+                crate::health::ready
+            ],
+        ),
+    ]
+}
+
+/// 创建 http 服务, 同时启动后台的定时任务
+pub async fn rocket() -> Rocket<Build> {
+    crate::health::init();
+    crate::slurm::init().await;
+
+    crate::slurm::start_timetask();
+    crate::refresh::start_timetask();
+    crate::alerts::start_timetask();
+    crate::search::start_build();
+    crate::retention::start_timetask();
+    crate::jobs::start();
+
+    let mut cfg = rocket::config::Config::default();
+    let server = &crate::settings::settings().server;
+    cfg.address = server.address.parse().expect("validated server.address");
+    cfg.log_level = LogLevel::Normal;
+    cfg.port = server.port;
+
+    // let options = Options::Index | Options::DotFiles;
+
+    let mut r = rocket::custom(cfg)
+        .attach(Cors) // cors
+        .attach(crate::logging::api::Tracing)
+        .attach(crate::metrics::api::Metrics)
+        .mount("/", FileServer::from("./web/dist"));
+    // .mount(
+    //     "/dual",
+    //     FileServer::new("./data/dual", Options::default()).rank(999),
+    // )
+    for (base, routes) in mounts() {
+        r = r.mount(base, crate::logging::api::traced(routes));
+    }
+    r.register(
+        "/api",
+        catchers![
+            crate::error::bad_request,
+            crate::error::not_found,
+            crate::error::unprocessable,
+            crate::error::internal,
+            crate::error::default,
+        ],
+    )
+    .register("/", catchers![crate::error::default])
+}
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "Cross-Origin-Resource-Sharing Fairing",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Methods",
+            "POST, PATCH, PUT, DELETE, HEAD, OPTIONS, GET",
+        ));
+        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
+        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            "X-Request-Id, X-Not-Found",
+        ));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::model::PaperCsvResult;
+
+/// 一次最多组合的集合数
+pub const MAX_SETS: usize = 10;
+/// 每页最多返回的文献数
+pub const MAX_PAGE_SIZE: usize = 1000;
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SetOp {
+    Union,
+    Intersection,
+    /// 第一个集合中有, 其他集合中都没有
+    Difference,
+}
+
+/// 一个输入集合, `query` 和 `snapshot` 二选一
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SetInput {
+    /// 结果中标记来源的名称, 默认为检索式或快照 id
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 检索式, 使用全部结果, 最多 10000 个
+    #[serde(default)]
+    pub query: Option<String>,
+    /// 保存的快照 id
+    #[serde(default)]
+    pub snapshot: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SetRequest {
+    pub op: SetOp,
+    pub sets: Vec<SetInput>,
+    /// 从 0 开始
+    #[serde(default)]
+    pub page: usize,
+    /// 默认 100, 最多 1000
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    /// 为 false 时只返回 PMID 和来源, 不读取文献
+    #[serde(default = "hydrate")]
+    pub hydrate: bool,
+}
+
+fn hydrate() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SetSource {
+    pub name: String,
+    pub count: usize,
+    /// 检索结果超过 10000 个, 只使用了前 10000 个
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SetItem {
+    pub pmid: String,
+    /// 包含这个 PMID 的输入集合的名称, 按输入的顺序
+    pub sets: Vec<String>,
+    /// 缓存和 PubMed 上都没有时为空
+    pub paper: Option<PaperCsvResult>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SetResult {
+    pub op: SetOp,
+    pub sources: Vec<SetSource>,
+    /// 运算后的 PMID 总数
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub data: Vec<SetItem>,
+}
+
+impl SetInput {
+    fn name(&self) -> String {
+        self.name
+            .clone()
+            .filter(|f| !f.trim().is_empty())
+            .or_else(|| self.query.clone())
+            .or_else(|| self.snapshot.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// 检查参数, 返回错误信息
+pub fn validate(req: &SetRequest) -> Result<(), String> {
+    if req.sets.is_empty() || req.sets.len() > MAX_SETS {
+        return Err(format!("sets must have 1 to {} items", MAX_SETS));
+    }
+    if req.page_size.is_some_and(|f| f == 0 || f > MAX_PAGE_SIZE) {
+        return Err(format!("page_size must be 1 to {}", MAX_PAGE_SIZE));
+    }
+    let mut names = HashSet::new();
+    for (i, s) in req.sets.iter().enumerate() {
+        let query = s.query.as_deref().is_some_and(|f| !f.trim().is_empty());
+        if query == s.snapshot.is_some() {
+            return Err(format!("sets[{}] must have either query or snapshot", i));
+        }
+        if !names.insert(s.name()) {
+            return Err(format!("duplicate set name: {}", s.name()));
+        }
+    }
+    Ok(())
+}
+
+/// 按 PMID 运算, 去重并记录来源; 按在输入中第一次出现的顺序
+fn combine(op: SetOp, inputs: &[(String, Vec<String>)]) -> Vec<(String, Vec<String>)> {
+    let mut order = Vec::new();
+    let mut sources: HashMap<&String, Vec<String>> = HashMap::new();
+    for (name, pmids) in inputs {
+        for pmid in pmids {
+            let v = sources.entry(pmid).or_insert_with(|| {
+                order.push(pmid);
+                Vec::new()
+            });
+            if v.last() != Some(name) {
+                v.push(name.clone());
+            }
+        }
+    }
+
+    let first = inputs.first().map(|(name, _)| name);
+    order
+        .into_iter()
+        .filter_map(|pmid| {
+            let v = sources.remove(pmid)?;
+            let keep = match op {
+                SetOp::Union => true,
+                SetOp::Intersection => v.len() == inputs.len(),
+                SetOp::Difference => v.len() == 1 && v.first() == first,
+            };
+            keep.then(|| (pmid.clone(), v))
+        })
+        .collect()
+}
+
+async fn resolve(input: &SetInput) -> Result<(Vec<String>, bool), Box<dyn Error + Send + Sync>> {
+    if let Some(id) = &input.snapshot {
+        let s = crate::snapshot::load(id).ok_or_else(|| format!("snapshot not found: {}", id))?;
+        return Ok((s.pmids, s.truncated));
+    }
+    let query = input.query.as_deref().unwrap_or_default().trim();
+    let r = crate::snapshot::search_all(query).await?;
+    let truncated = r.pmids.len() < r.count;
+    Ok((r.pmids, truncated))
+}
+
+/// 读取全部输入集合后运算, 只读取当前页的文献
+pub async fn evaluate(req: &SetRequest) -> Result<SetResult, Box<dyn Error + Send + Sync>> {
+    let resolved = futures::future::try_join_all(req.sets.iter().map(resolve)).await?;
+    let mut sources = Vec::new();
+    let mut inputs = Vec::new();
+    for (s, (pmids, truncated)) in req.sets.iter().zip(resolved) {
+        sources.push(SetSource {
+            name: s.name(),
+            count: pmids.len(),
+            truncated,
+        });
+        inputs.push((s.name(), pmids));
+    }
+
+    let combined = combine(req.op, &inputs);
+    let page_size = req.page_size.unwrap_or(PAGE_SIZE);
+    let page = combined
+        .iter()
+        .skip(req.page * page_size)
+        .take(page_size)
+        .collect::<Vec<_>>();
+    log::info!(
+        "set {:?} inputs = {}, total = {}, page = {}",
+        req.op,
+        inputs.len(),
+        combined.len(),
+        page.len()
+    );
+
+    let mut papers = if req.hydrate && !page.is_empty() {
+        let ids = page
+            .iter()
+            .map(|(pmid, _)| pmid.clone())
+            .collect::<Vec<String>>();
+        crate::eutils::efetch_batch("pubmed", &ids).await?
+    } else {
+        HashMap::new()
+    };
+    let data = page
+        .into_iter()
+        .map(|(pmid, sets)| SetItem {
+            pmid: pmid.clone(),
+            sets: sets.clone(),
+            paper: papers.remove(pmid),
+        })
+        .collect();
+
+    Ok(SetResult {
+        op: req.op,
+        sources,
+        total: combined.len(),
+        page: req.page,
+        page_size,
+        data,
+    })
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{post, serde::json::Json};
+
+    use super::*;
+    use crate::auth::Ncbi;
+    use crate::error::{api_ok, ApiResult, AppError};
+
+    /// 多个检索式或快照按 PMID 求并集, 交集或差集
+    #[post("/pubmed/sets", format = "json", data = "<req>")]
+    pub async fn combine_sets(_key: Ncbi, req: Json<SetRequest>) -> ApiResult {
+        validate(&req).map_err(AppError::bad_request)?;
+        for id in req.sets.iter().filter_map(|f| f.snapshot.as_ref()) {
+            if crate::snapshot::load(id).is_none() {
+                return Err(AppError::not_found(format!("snapshot not found: {}", id)));
+            }
+        }
+        api_ok(&evaluate(&req).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str, pmids: &[&str]) -> (String, Vec<String>) {
+        (
+            name.to_string(),
+            pmids.iter().map(|f| f.to_string()).collect(),
+        )
+    }
+
+    fn pmids(v: &[(String, Vec<String>)]) -> Vec<&str> {
+        v.iter().map(|(pmid, _)| pmid.as_str()).collect()
+    }
+
+    #[test]
+    fn test_combine() {
+        let inputs = [
+            input("A", &["1", "2", "3", "2"]),
+            input("B", &["3", "4", "1"]),
+            input("C", &["1", "5"]),
+        ];
+
+        let union = combine(SetOp::Union, &inputs);
+        assert_eq!(vec!["1", "2", "3", "4", "5"], pmids(&union));
+        assert_eq!(vec!["A", "B", "C"], union[0].1);
+        assert_eq!(vec!["A"], union[1].1);
+
+        assert_eq!(vec!["1"], pmids(&combine(SetOp::Intersection, &inputs)));
+        assert_eq!(vec!["2"], pmids(&combine(SetOp::Difference, &inputs)));
+        assert_eq!(
+            vec!["2", "3"],
+            pmids(&combine(
+                SetOp::Difference,
+                &inputs[..1]
+                    .iter()
+                    .chain(&inputs[2..])
+                    .cloned()
+                    .collect::<Vec<_>>()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let req = |sets: serde_json::Value| {
+            serde_json::from_value::<SetRequest>(serde_json::json!({ "op": "union", "sets": sets }))
+                .unwrap()
+        };
+        assert!(validate(&req(
+            serde_json::json!([{ "query": "A" }, { "snapshot": "s1" }])
+        ))
+        .is_ok());
+        assert!(validate(&req(serde_json::json!([]))).is_err());
+        assert!(validate(&req(
+            serde_json::json!([{ "query": "A", "snapshot": "s1" }])
+        ))
+        .is_err());
+        assert!(validate(&req(serde_json::json!([{ "query": " " }]))).is_err());
+        assert!(validate(&req(
+            serde_json::json!([{ "query": "A" }, { "query": "A" }])
+        ))
+        .is_err());
+        assert!(validate(&req(
+            serde_json::json!([{ "name": "x", "query": "A" }, { "query": "A" }])
+        ))
+        .is_ok());
+    }
+}
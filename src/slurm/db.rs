@@ -2,6 +2,7 @@ use std::{sync::Arc, time::Duration};
 
 use log::info;
 
+use futures::StreamExt;
 use mongodb::{
     bson::{self, doc, Bson, Document},
     error::Error,
@@ -9,7 +10,6 @@ use mongodb::{
     Client, Cursor,
 };
 use once_cell::sync::OnceCell;
-use rocket::futures::StreamExt;
 use serde::de::DeserializeOwned;
 
 static INSTANCE: OnceCell<Arc<Client>> = OnceCell::new();
@@ -35,6 +35,24 @@ impl Db {
         INSTANCE.get().expect("db need init first")
     }
 
+    /// mongo 初始化失败时为 `None`
+    pub fn instance() -> Option<&'static Arc<Client>> {
+        INSTANCE.get()
+    }
+
+    pub async fn ping() -> Result<(), Error> {
+        let client = Db::instance().ok_or_else(|| {
+            Error::from(mongodb::error::ErrorKind::Custom(Arc::new(
+                "db not initialized",
+            )))
+        })?;
+        client
+            .database("admin")
+            .run_command(doc! { "ping": 1 }, None)
+            .await?;
+        Ok(())
+    }
+
     pub async fn find<T>(
         table: &str,
         filter: impl Into<Option<Document>>,
@@ -243,18 +261,18 @@ impl Db {
     }
 }
 
-pub async fn init_db(url: &str) {
+/// 失败时只返回错误, 不影响不依赖 mongo 的接口
+pub async fn init_db(url: &str) -> Result<(), Error> {
     if INSTANCE.get().is_some() {
-        return;
+        return Ok(());
     }
-    let mut client_options = ClientOptions::parse(url).await.unwrap();
+    let mut client_options = ClientOptions::parse(url).await?;
     client_options.connect_timeout = Some(Duration::new(4, 0));
     // 选择超时
     client_options.server_selection_timeout = Some(Duration::new(8, 0));
 
-    INSTANCE
-        .set(Arc::new(Client::with_options(client_options).unwrap()))
-        .expect("db init error");
+    let _ = INSTANCE.set(Arc::new(Client::with_options(client_options)?));
+    Ok(())
 }
 
 #[cfg(test)]
@@ -2,7 +2,7 @@ use std::process::Command;
 
 use bson::{doc, Bson};
 use chrono::Duration;
-use rocket::futures::StreamExt;
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -55,7 +55,6 @@ pub struct JobInDb {
     pub cloud: String,
     pub is_send: Option<u32>,
 }
-pub const ROOT_PATH: &'static str = "/mnt/share/cloud_sbatch";
 
 fn get_info_path(path: &str) -> (&str, &str) {
     let path_obj = Path::new(path);
@@ -111,10 +110,11 @@ impl JobInDb {
     pub fn local_dir(&self) -> String {
         let vv = self.work_dir.split(&self.user).collect::<Vec<&str>>();
         let local_dir = if vv.len() > 1 { vv[1] } else { "/tgz" };
-        let dir = format!("{}{}", ROOT_PATH, local_dir);
+        let root = &crate::settings::settings().data.slurm_root;
+        let dir = format!("{}{}", root, local_dir);
         let (d, _) = get_info_path(&dir);
         if d.is_empty() {
-            format!("{}{}", ROOT_PATH, local_dir)
+            format!("{}{}", root, local_dir)
         } else {
             d.to_string()
         }
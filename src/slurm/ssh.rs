@@ -2,8 +2,8 @@ use std::path::Path;
 
 use bson::{doc, Bson};
 use chrono::Duration;
+use futures::StreamExt;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use rocket::futures::StreamExt;
 use ssh_rs::ssh;
 
 use crate::{
@@ -92,7 +92,7 @@ pub async fn job_restart(job: &JobInDb, cloud: &Cloud) {
     log::info!("start ssh: {}", &cloud.username);
     let result = ssh::create_session()
         .username(&cloud.username)
-        .password(&cloud.password)
+        .password(cloud.password.expose())
         .timeout(300 * 1000)
         .private_key_path(&cloud.ssh_pri_key)
         .connect(&cloud.ssh_url);
@@ -125,6 +125,9 @@ pub async fn job_restart(job: &JobInDb, cloud: &Cloud) {
         }
         Err(err) => {
             log::error!("ssh登录失败: {:?}", &err);
+            crate::metrics::SLURM_SSH_FAILURES
+                .with_label_values(&[&cloud.info])
+                .inc();
         }
     }
 }
@@ -133,7 +136,7 @@ pub fn sync_tgz(cloud: &Cloud, job: &JobInDb) {
     log::info!("start ssh: {}", &cloud.username);
     let result = ssh::create_session()
         .username(&cloud.username)
-        .password(&cloud.password)
+        .password(cloud.password.expose())
         .timeout(3000 * 1000)
         .private_key_path(&cloud.ssh_pri_key)
         .connect(&cloud.ssh_url);
@@ -167,6 +170,9 @@ pub fn sync_tgz(cloud: &Cloud, job: &JobInDb) {
         }
         Err(err) => {
             log::error!("ssh登录失败: {:?}", &err);
+            crate::metrics::SLURM_SSH_FAILURES
+                .with_label_values(&[&cloud.info])
+                .inc();
         }
     }
 }
@@ -175,7 +181,7 @@ pub fn get_jobs_from_cloud(cloud: &Cloud, start_time: &str) -> Vec<JobInDb> {
     log::info!("start ssh: {}", &cloud.username);
     let result = ssh::create_session()
         .username(&cloud.username)
-        .password(&cloud.password)
+        .password(cloud.password.expose())
         .timeout(300 * 1000)
         .private_key_path(&cloud.ssh_pri_key)
         .connect(&cloud.ssh_url);
@@ -233,6 +239,9 @@ pub fn get_jobs_from_cloud(cloud: &Cloud, start_time: &str) -> Vec<JobInDb> {
         }
         Err(err) => {
             log::error!("ssh登录失败: {:?}", &err);
+            crate::metrics::SLURM_SSH_FAILURES
+                .with_label_values(&[&cloud.info])
+                .inc();
             return vec![];
         }
     }
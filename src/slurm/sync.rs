@@ -1,11 +1,11 @@
 use bson::{doc, Bson};
 use chrono::Duration;
 
+use futures::StreamExt;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Client,
 };
-use rocket::{form::validate::Contains, futures::StreamExt};
 use serde_json::json;
 
 use crate::utils::file_exist;
@@ -66,13 +66,50 @@ async fn find_jobs_in_day(user: &str) -> Vec<JobInDb> {
     v
 }
 
-async fn send_notification(c: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let url = "https://oapi.dingtalk.com/robot/send?access_token=1abbebf38e77d1228b63b43333fd2cb60dda34ca48c3e5c73aaa4d709f4ebdb9";
+async fn send_notification(c: &str) -> Result<Delivery, Box<dyn std::error::Error + Send + Sync>> {
+    send_markdown("任务信息", c).await
+}
+
+/// 钉钉消息的发送结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delivery {
+    Sent,
+    /// 没有配置 `dingtalk.token`, 没有发送
+    Skipped,
+}
+
+/// 钉钉返回 `errcode` 不为 0 时发送失败, 如 token 错误或者触发了限流
+fn check_response(body: &str) -> Result<(), String> {
+    let v: serde_json::Value =
+        serde_json::from_str(body).map_err(|_| format!("dingtalk response: {}", body))?;
+    match v["errcode"].as_i64() {
+        Some(0) => Ok(()),
+        _ => Err(format!(
+            "dingtalk errcode = {}, errmsg = {}",
+            v["errcode"], v["errmsg"]
+        )),
+    }
+}
+
+/// 通过钉钉机器人发送 markdown 消息, 没有配置 `dingtalk.token` 时跳过
+pub async fn send_markdown(
+    title: &str,
+    c: &str,
+) -> Result<Delivery, Box<dyn std::error::Error + Send + Sync>> {
+    let token = &crate::settings::settings().dingtalk.token;
+    if token.is_empty() {
+        log::warn!("dingtalk.token is not set, skip notification");
+        return Ok(Delivery::Skipped);
+    }
+    let url = format!(
+        "https://oapi.dingtalk.com/robot/send?access_token={}",
+        token.expose()
+    );
     let content = json!(
         {
             "msgtype": "markdown",
             "markdown": {
-                "title":"任务信息",
+                "title": title,
                 "text": c
             },
              "at": {
@@ -99,13 +136,15 @@ async fn send_notification(c: &str) -> Result<(), Box<dyn std::error::Error + Se
         .headers(headers)
         .json(&content)
         .send()
-        .await?;
+        .await
+        .map_err(|e| e.without_url())?;
 
     // log::info!("Response status: {}", response.status());
-    let body = response.text().await?;
+    let body = response.text().await.map_err(|e| e.without_url())?;
     log::info!("Response body: {}", body);
+    check_response(&body)?;
 
-    Ok(())
+    Ok(Delivery::Sent)
 }
 
 fn succ_notification(job: &JobInDb, local: &str) -> String {
@@ -161,6 +200,15 @@ async fn save_failed_send(job: &JobInDb) {
     let _ = Db::save_with_table(TABLE_NAME, COLLECTION_JOB, filter, doc! {"is_send": 1 }).await;
 }
 
+/// 只统计实际发出的通知, 没有配置钉钉时跳过
+fn sent(cloud: &Cloud, d: Delivery) {
+    if d == Delivery::Sent {
+        crate::metrics::SLURM_NOTIFICATIONS
+            .with_label_values(&[&cloud.info])
+            .inc();
+    }
+}
+
 async fn sync_cloud(cloud: &Cloud) {
     let vv = find_jobs_in_day(&cloud.user()).await;
     for job in &vv {
@@ -178,7 +226,8 @@ async fn sync_cloud(cloud: &Cloud) {
                             // 推送成功
                             log::info!("start send succ notidication");
                             let result = send_notification(&succ_notification(&job, &p)).await;
-                            if result.is_ok() {
+                            if let Ok(d) = result {
+                                sent(cloud, d);
                                 // 发送状态保存
                                 let filter = doc! {
                                     "job_id": job.job_id,
@@ -208,7 +257,8 @@ async fn sync_cloud(cloud: &Cloud) {
                         super::ssh::job_restart(job, cloud).await;
                     } else {
                         let result = send_notification(&failed_notification(job)).await;
-                        if result.is_ok() {
+                        if let Ok(d) = result {
+                            sent(cloud, d);
                             save_failed_send(job).await;
                         }
                     }
@@ -218,12 +268,49 @@ async fn sync_cloud(cloud: &Cloud) {
     }
 }
 
+/// 最近结束的作业, 按结束时间倒序, `cloud` 为集群名或者集群的描述
+pub async fn recent_jobs(
+    cloud: Option<&str>,
+    limit: i64,
+) -> Result<Vec<JobInDb>, mongodb::error::Error> {
+    let filter = match cloud {
+        Some(c) => {
+            let info = config::Config::clouds_map()
+                .get(c)
+                .map(|f| f.info.clone())
+                .unwrap_or_else(|| c.to_string());
+            doc! { "cloud": info }
+        }
+        None => doc! {},
+    };
+    let option = mongodb::options::FindOptions::builder()
+        .sort(doc! { "end": -1 })
+        .limit(limit)
+        .build();
+
+    let mut cursor = Db::find_with_table(TABLE_NAME, COLLECTION_JOB, filter, option).await?;
+    let mut v: Vec<JobInDb> = Vec::new();
+    while let Some(document) = cursor.next().await {
+        match bson::from_bson::<JobInDb>(Bson::Document(document?)) {
+            Ok(job) => v.push(job),
+            Err(err) => log::error!("parse doc error : {:?}", err),
+        }
+    }
+    Ok(v)
+}
+
 pub async fn start_sync() {
     let v = config::Config::clouds();
 
     for f in v {
+        let timer = crate::metrics::SLURM_SYNC_DURATION
+            .with_label_values(&[&f.info])
+            .start_timer();
         let start = super::ssh::find_start_time(&f.user()).await;
         let v = get_jobs_from_cloud(&f, &start);
+        crate::metrics::SLURM_JOBS_FOUND
+            .with_label_values(&[&f.info])
+            .inc_by(v.len() as u64);
         for f in v {
             let filter = doc! {
                 "job_id": f.job_id,
@@ -241,6 +328,7 @@ pub async fn start_sync() {
 
         sync_cloud(&f).await;
         super::dg::do_dg(&f).await;
+        timer.observe_duration();
     }
 }
 
@@ -258,6 +346,14 @@ mod tests {
         start_sync().await;
     }
 
+    #[test]
+    fn test_check_response() {
+        assert!(check_response(r#"{"errcode":0,"errmsg":"ok"}"#).is_ok());
+        let err = check_response(r#"{"errcode":300001,"errmsg":"token is not exist"}"#);
+        assert!(err.unwrap_err().contains("300001"));
+        assert!(check_response("bad gateway").is_err());
+    }
+
     #[tokio::test]
     async fn test_send_notification() {
         crate::config::init_config();
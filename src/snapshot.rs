@@ -0,0 +1,546 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    io::Write,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::PaperMeta, settings::data_path};
+
+static SNAPSHOT_DIR: &str = "snapshots";
+
+/// esearch 最多返回前 10000 条, 超过时只保存前 10000 条并标记 `truncated`
+pub const MAX_PMIDS: usize = 10000;
+const SEARCH_PAGE: usize = 5000;
+
+/// 快照时本地缓存的记录版本
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RecordVersion {
+    pub pmid: String,
+    /// PubMed 上的最后修改日期 (DateRevised)
+    pub modified_at: String,
+    /// 下载到本地的时间, 毫秒
+    pub fetched_at: i64,
+}
+
+/// 检索结果的快照, 保存在 `data/snapshots/<id>.json`, 保存后不再修改
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Snapshot {
+    pub id: String,
+    /// 创建者的 API key 名称, 命令行创建时为空
+    #[serde(default)]
+    pub owner: String,
+    pub query: String,
+    /// NCBI 实际执行的检索式
+    pub querytranslation: String,
+    /// 检索的时间, 毫秒
+    pub created_at: i64,
+    /// NCBI 返回的总数
+    pub count: usize,
+    /// 按日期排序的全部 PMID
+    pub pmids: Vec<String>,
+    pub truncated: bool,
+    /// 只包含快照时已经缓存的文献
+    pub versions: Vec<RecordVersion>,
+}
+
+impl Snapshot {
+    /// 列表中返回的内容, 不包含 PMID 和记录版本
+    pub fn view(&self) -> serde_json::Value {
+        let mut v = serde_json::to_value(self).unwrap_or_default();
+        if let Some(m) = v.as_object_mut() {
+            m.remove("pmids");
+            m.remove("versions");
+            m.insert("pmid_count".to_string(), self.pmids.len().into());
+            m.insert("cached_count".to_string(), self.versions.len().into());
+        }
+        v
+    }
+}
+
+/// 两次结果的比较, `to` 为空时是重新检索的结果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Diff {
+    pub from: String,
+    pub to: Option<String>,
+    pub from_at: i64,
+    pub to_at: i64,
+    pub from_count: usize,
+    pub to_count: usize,
+    pub querytranslation_changed: bool,
+    /// 新出现的 PMID, 按新结果的顺序
+    pub added: Vec<String>,
+    /// 不再出现的 PMID, 按旧结果的顺序
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    /// 两次都有缓存, 但 PubMed 修改日期不同的 PMID
+    pub revised: Vec<String>,
+}
+
+fn snapshot_path(id: &str) -> String {
+    data_path(&format!("{}/{}.json", SNAPSHOT_DIR, id))
+}
+
+/// id 只能是字母和数字, 避免读取其他目录
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// 去掉分页期间因为新文献导致的重复, 保持顺序
+fn push_unique(ids: &mut Vec<String>, seen: &mut HashSet<String>, page: &[String]) {
+    for id in page {
+        if seen.insert(id.clone()) {
+            ids.push(id.clone());
+        }
+    }
+}
+
+fn versions(pmids: &[String]) -> Vec<RecordVersion> {
+    pmids
+        .iter()
+        .filter_map(|f| f.parse::<usize>().ok())
+        .filter_map(PaperMeta::load)
+        .map(|m| RecordVersion {
+            pmid: m.pmid,
+            modified_at: m.modified_at,
+            fetched_at: m.fetched_at,
+        })
+        .collect()
+}
+
+/// 导出时一条文献的版本
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ExportVersion {
+    pub pmid: String,
+    /// 导出时缓存的修改日期
+    pub modified_at: String,
+    /// 快照时的修改日期, 快照时没有缓存的为空
+    pub snapshot_modified_at: Option<String>,
+    /// 修改日期和快照时不同, 导出的内容不是快照时的版本
+    pub revised: bool,
+}
+
+/// 按 `current` 的顺序和快照时的版本比较
+pub fn export_versions(s: &Snapshot, current: &[RecordVersion]) -> Vec<ExportVersion> {
+    let frozen = s
+        .versions
+        .iter()
+        .map(|f| (&f.pmid, &f.modified_at))
+        .collect::<HashMap<&String, &String>>();
+
+    current
+        .iter()
+        .map(|f| {
+            let old = frozen.get(&f.pmid).map(|m| m.to_string());
+            ExportVersion {
+                pmid: f.pmid.clone(),
+                modified_at: f.modified_at.clone(),
+                revised: old.as_ref().is_some_and(|m| *m != f.modified_at),
+                snapshot_modified_at: old,
+            }
+        })
+        .collect()
+}
+
+/// 一个检索式的全部 PMID
+#[derive(Debug, Clone)]
+pub struct AllIds {
+    pub pmids: Vec<String>,
+    /// NCBI 返回的总数, 超过 `MAX_PMIDS` 时大于 `pmids` 的数量
+    pub count: usize,
+    pub querytranslation: String,
+}
+
+/// 分页检索全部 PMID, 最多 `MAX_PMIDS` 个
+pub async fn search_all(query: &str) -> Result<AllIds, Box<dyn Error + Send + Sync>> {
+    let mut pmids = Vec::new();
+    let mut seen = HashSet::new();
+    let mut count = 0;
+    let mut querytranslation;
+    let mut page = 0;
+    loop {
+        let r = crate::eutils::fetch_ids("pubmed", query, page, SEARCH_PAGE).await?;
+        count = r.esearchresult.count.parse::<usize>().unwrap_or(count);
+        querytranslation = r.esearchresult.querytranslation;
+        push_unique(&mut pmids, &mut seen, &r.esearchresult.idlist);
+
+        page += 1;
+        if r.esearchresult.idlist.is_empty()
+            || pmids.len() >= count
+            || page * SEARCH_PAGE >= MAX_PMIDS
+        {
+            break;
+        }
+    }
+    pmids.truncate(MAX_PMIDS);
+    log::info!(
+        "search all query = {}, count = {}, pmids = {}",
+        query,
+        count,
+        pmids.len()
+    );
+
+    Ok(AllIds {
+        pmids,
+        count,
+        querytranslation,
+    })
+}
+
+/// 检索全部 PMID 并记录缓存的版本, 不保存
+pub async fn take(query: &str) -> Result<Snapshot, Box<dyn Error + Send + Sync>> {
+    let r = search_all(query).await?;
+    Ok(Snapshot {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        owner: String::new(),
+        query: query.to_string(),
+        querytranslation: r.querytranslation,
+        created_at: chrono::Utc::now().timestamp_millis(),
+        count: r.count,
+        truncated: r.pmids.len() < r.count,
+        versions: versions(&r.pmids),
+        pmids: r.pmids,
+    })
+}
+
+/// 保存快照, 已经存在时返回错误
+pub fn save(s: &Snapshot) -> Result<(), Box<dyn Error + Send + Sync>> {
+    fs::create_dir_all(data_path(SNAPSHOT_DIR))?;
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(snapshot_path(&s.id))?;
+    f.write_all(serde_json::to_string_pretty(s)?.as_bytes())?;
+    Ok(())
+}
+
+pub fn load(id: &str) -> Option<Snapshot> {
+    if !valid_id(id) {
+        return None;
+    }
+    let text = fs::read_to_string(snapshot_path(id)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// 全部快照, 新的在前
+pub fn list() -> Vec<Snapshot> {
+    let mut v = fs::read_dir(data_path(SNAPSHOT_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+        .filter_map(|p| fs::read_to_string(p).ok())
+        .filter_map(|s| serde_json::from_str::<Snapshot>(&s).ok())
+        .collect::<Vec<Snapshot>>();
+    v.sort_by_key(|f| std::cmp::Reverse(f.created_at));
+    v
+}
+
+pub fn diff(from: &Snapshot, to: &Snapshot, saved: bool) -> Diff {
+    let old = from.pmids.iter().collect::<HashSet<&String>>();
+    let new = to.pmids.iter().collect::<HashSet<&String>>();
+    let old_versions = from
+        .versions
+        .iter()
+        .map(|f| (&f.pmid, &f.modified_at))
+        .collect::<HashMap<&String, &String>>();
+
+    Diff {
+        from: from.id.clone(),
+        to: Some(to.id.clone()).filter(|_| saved),
+        from_at: from.created_at,
+        to_at: to.created_at,
+        from_count: from.count,
+        to_count: to.count,
+        querytranslation_changed: from.querytranslation != to.querytranslation,
+        added: to
+            .pmids
+            .iter()
+            .filter(|f| !old.contains(f))
+            .cloned()
+            .collect(),
+        removed: from
+            .pmids
+            .iter()
+            .filter(|f| !new.contains(f))
+            .cloned()
+            .collect(),
+        unchanged: to.pmids.iter().filter(|f| old.contains(f)).count(),
+        revised: to
+            .versions
+            .iter()
+            .filter(|f| {
+                old_versions
+                    .get(&f.pmid)
+                    .is_some_and(|m| **m != f.modified_at)
+            })
+            .map(|f| f.pmid.clone())
+            .collect(),
+    }
+}
+
+/// http 接口
+#[cfg(feature = "server")]
+pub mod api {
+    use rocket::{get, post, serde::json::Json};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::auth::{Auth, Ncbi};
+    use crate::error::{api_ok, ApiResult, AppError};
+    use crate::export::{
+        columns::{csv_fields, Table},
+        xlsx::{col, save_cells, Kind, Meta},
+        Record,
+    };
+    use crate::model::PaperCsvResult;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    pub struct SnapshotRequest {
+        pub query: String,
+    }
+
+    /// 快照按 id 共享, 任何 key 都可以读取
+    fn find(id: &str) -> Result<Snapshot, AppError> {
+        load(id).ok_or_else(|| AppError::not_found(format!("snapshot not found: {}", id)))
+    }
+
+    /// 检索并保存快照
+    #[post("/snapshots", format = "json", data = "<req>")]
+    pub async fn create_snapshot(key: Ncbi, req: Json<SnapshotRequest>) -> ApiResult {
+        let query = req.query.trim();
+        if query.is_empty() {
+            return Err(AppError::bad_request("query is required"));
+        }
+        let mut s = take(query).await?;
+        s.owner = key.0.name;
+        save(&s)?;
+        log::info!("save snapshot id = {}, owner = {}", &s.id, &s.owner);
+        api_ok(&s.view())
+    }
+
+    /// 自己的快照, 管理员返回全部
+    #[get("/snapshots")]
+    pub async fn list_snapshots(auth: Auth) -> ApiResult {
+        let v = list()
+            .iter()
+            .filter(|f| auth.0.admin || f.owner == auth.0.name)
+            .map(Snapshot::view)
+            .collect::<Vec<serde_json::Value>>();
+        api_ok(&v)
+    }
+
+    #[get("/snapshots/<id>")]
+    pub async fn get_snapshot(_key: Auth, id: String) -> ApiResult {
+        api_ok(&find(&id)?)
+    }
+
+    /// csv 和 xlsx 在默认的列后面加上修改日期和快照时的修改日期
+    fn save_versioned(
+        list: &[PaperCsvResult],
+        versions: &[ExportVersion],
+        ext: &str,
+        query: &str,
+    ) -> Result<serde_json::Value, Box<dyn Error + Send + Sync>> {
+        let by_pmid = versions
+            .iter()
+            .map(|f| (f.pmid.as_str(), f))
+            .collect::<HashMap<&str, &ExportVersion>>();
+        let records = list.iter().map(Record::new).collect::<Vec<Record>>();
+        let mut table = Table::new(&records, &csv_fields());
+        table.columns.extend([
+            col("ModifiedAt", 14.0, Kind::Text),
+            col("SnapshotModifiedAt", 18.0, Kind::Text),
+            col("Revised", 10.0, Kind::Text),
+        ]);
+        for (row, p) in table.rows.iter_mut().zip(list) {
+            let v = by_pmid.get(p.pmid.as_str());
+            row.push(v.map(|f| f.modified_at.clone()).unwrap_or_default());
+            row.push(
+                v.and_then(|f| f.snapshot_modified_at.clone())
+                    .unwrap_or_default(),
+            );
+            row.push(v.is_some_and(|f| f.revised).to_string());
+        }
+
+        let (file_name, t) = crate::utils::get_download_path(ext)?;
+        if ext == "xlsx" {
+            let meta = Meta {
+                query,
+                counts: vec![("revised", versions.iter().filter(|f| f.revised).count())],
+            };
+            save_cells(&file_name, &table.columns, &table.rows, &meta)?;
+        } else {
+            table.save_csv(&file_name)?;
+        }
+        Ok(serde_json::json!({
+            "id": crate::retention::issue_token(ext, t),
+            "file_type": ext,
+        }))
+    }
+
+    /// `json` 导出快照本身和当前缓存的版本, 其他格式同 `/api/pubmed/save`, 按快照的顺序导出当前缓存的文献;
+    /// 返回每条文献的修改日期, `revised` 为和快照时版本不同的 PMID
+    #[get("/snapshots/<id>/export?<file_type>")]
+    pub async fn export_snapshot(_key: Ncbi, id: String, file_type: Option<String>) -> ApiResult {
+        let s = find(&id)?;
+        let file_type = file_type.as_deref().unwrap_or("csv");
+        let (mut v, versions) = if file_type.eq_ignore_ascii_case("json") {
+            let versions = export_versions(&s, &super::versions(&s.pmids));
+            let mut content = serde_json::to_value(&s)?;
+            content["records"] = serde_json::to_value(&versions)?;
+            let (file_name, t) = crate::utils::get_download_path("json")?;
+            fs::write(&file_name, serde_json::to_string_pretty(&content)?)?;
+            let v = serde_json::json!({
+                "id": crate::retention::issue_token("json", t),
+                "file_type": "json",
+            });
+            (v, versions)
+        } else {
+            let ext = crate::export::normalize_type(file_type).ok_or_else(|| {
+                AppError::bad_request(format!("unsupported file_type: {}", file_type))
+            })?;
+
+            let mut found = crate::eutils::efetch_batch("pubmed", &s.pmids).await?;
+            let list = s
+                .pmids
+                .iter()
+                .filter_map(|f| found.remove(f))
+                .collect::<Vec<PaperCsvResult>>();
+            let pmids = list.iter().map(|f| f.pmid.clone()).collect::<Vec<String>>();
+            let versions = export_versions(&s, &super::versions(&pmids));
+
+            let created = chrono::NaiveDateTime::from_timestamp_millis(s.created_at)
+                .map(|f| f.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_default();
+            let query = format!("{} (snapshot {}, {})", &s.query, &s.id, created);
+            let v = if ext == "csv" || ext == "xlsx" {
+                save_versioned(&list, &versions, ext, &query)?
+            } else {
+                crate::export::save_list(&list, ext, &query, None)?
+            };
+            (v, versions)
+        };
+
+        v["revised"] = versions
+            .iter()
+            .filter(|f| f.revised)
+            .map(|f| f.pmid.clone())
+            .collect::<Vec<String>>()
+            .into();
+        v["versions"] = serde_json::to_value(&versions)?;
+        api_ok(&v)
+    }
+
+    /// 和另一个快照比较, 没有 `against` 时重新检索后比较, 重新检索的结果不保存
+    #[get("/snapshots/<id>/diff?<against>")]
+    pub async fn diff_snapshot(_key: Ncbi, id: String, against: Option<String>) -> ApiResult {
+        let s = find(&id)?;
+        match against {
+            Some(other) => api_ok(&diff(&s, &find(&other)?, true)),
+            None => api_ok(&diff(&s, &take(&s.query).await?, false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(v: &[&str]) -> Vec<String> {
+        v.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn snapshot(id: &str, pmids: &[&str], versions: &[(&str, &str)]) -> Snapshot {
+        Snapshot {
+            id: id.to_string(),
+            owner: String::new(),
+            query: "DUSP22".to_string(),
+            querytranslation: "DUSP22[All Fields]".to_string(),
+            created_at: 0,
+            count: pmids.len(),
+            pmids: ids(pmids),
+            truncated: false,
+            versions: versions
+                .iter()
+                .map(|(pmid, m)| RecordVersion {
+                    pmid: pmid.to_string(),
+                    modified_at: m.to_string(),
+                    fetched_at: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = snapshot(
+            "a",
+            &["3", "2", "1"],
+            &[("2", "2023/01/01"), ("1", "2022/05/01")],
+        );
+        let b = snapshot("b", &["5", "4", "3", "1"], &[("1", "2023/03/02")]);
+
+        let d = diff(&a, &b, true);
+        assert_eq!(ids(&["5", "4"]), d.added);
+        assert_eq!(ids(&["2"]), d.removed);
+        assert_eq!(2, d.unchanged);
+        assert_eq!(ids(&["1"]), d.revised);
+        assert_eq!(Some("b".to_string()), d.to);
+        assert!(!d.querytranslation_changed);
+        assert_eq!(None, diff(&a, &b, false).to);
+    }
+
+    #[test]
+    fn test_export_versions() {
+        let s = snapshot(
+            "a",
+            &["3", "2", "1"],
+            &[("2", "2023/01/01"), ("1", "2022/05/01")],
+        );
+        let current = snapshot(
+            "b",
+            &[],
+            &[
+                ("3", "2023/04/01"),
+                ("2", "2023/01/01"),
+                ("1", "2023/03/02"),
+            ],
+        )
+        .versions;
+
+        let v = export_versions(&s, &current);
+        assert_eq!(3, v.len());
+        assert_eq!(None, v[0].snapshot_modified_at);
+        assert!(!v[0].revised);
+        assert_eq!(Some("2023/01/01".to_string()), v[1].snapshot_modified_at);
+        assert!(!v[1].revised);
+        assert_eq!("2023/03/02", v[2].modified_at);
+        assert!(v[2].revised);
+    }
+
+    #[test]
+    fn test_push_unique() {
+        let mut v = Vec::new();
+        let mut seen = HashSet::new();
+        push_unique(&mut v, &mut seen, &ids(&["3", "2"]));
+        push_unique(&mut v, &mut seen, &ids(&["2", "1"]));
+        assert_eq!(ids(&["3", "2", "1"]), v);
+    }
+
+    #[test]
+    fn test_view() {
+        let v = snapshot("a", &["3", "2", "1"], &[("2", "2023/01/01")]).view();
+        assert!(v["pmids"].is_null());
+        assert_eq!(3, v["pmid_count"]);
+        assert_eq!(1, v["cached_count"]);
+
+        assert!(valid_id("0f2c9e"));
+        assert!(!valid_id("../config"));
+        assert!(load("..").is_none());
+    }
+}
@@ -4,7 +4,10 @@ use chrono::Datelike;
 use csv::{QuoteStyle, WriterBuilder};
 use serde::{de::DeserializeOwned, Serialize};
 
-pub fn get_pmid_path_by_id(id: usize) -> String {
+use crate::settings::data_path;
+
+/// 数据目录 `dir` 下文献缓存的路径, 如 `data/pmid/37000000/766000/36765305.csv`
+pub fn pmid_path(dir: &str, id: usize) -> String {
     let million: usize = 1000000;
     let thousand: usize = 1000;
 
@@ -12,12 +15,17 @@ pub fn get_pmid_path_by_id(id: usize) -> String {
 
     let second = (id - first * million) / thousand;
 
-    return format!(
-        "data/pmid/{}/{}/{}.csv",
+    format!(
+        "{}/pmid/{}/{}/{}.csv",
+        dir.trim_end_matches('/'),
         (first + 1) * million,
         (second + 1) * thousand,
         id
-    );
+    )
+}
+
+pub fn get_pmid_path_by_id(id: usize) -> String {
+    pmid_path(&crate::settings::settings().data.dir, id)
 }
 
 pub fn get_download_path_by_time(file_type: &str, id: i64) -> String {
@@ -27,12 +35,12 @@ pub fn get_download_path_by_time(file_type: &str, id: i64) -> String {
         let month = d.month();
         let day = d.day();
 
-        return format!(
-            "data/download_{}/{}-{}-{}/{}.{}",
+        data_path(&format!(
+            "download_{}/{}-{}-{}/{}.{}",
             file_type, year, month, day, id, file_type
-        );
+        ))
     } else {
-        return format!("not_found");
+        "not_found".to_string()
     }
 }
 
@@ -101,7 +109,7 @@ pub fn read_csv_from_str<T: DeserializeOwned>(
 
 pub fn save_to_file<T: Serialize>(
     name: &str,
-    v: &Vec<T>,
+    v: &[T],
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut writer = WriterBuilder::new()
         .quote_style(QuoteStyle::Necessary)